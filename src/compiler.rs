@@ -1,28 +1,2758 @@
-use std::{fmt::Display, path::Path};
+use std::{collections::{BTreeMap, BTreeSet, HashMap, HashSet}, fmt::Display, path::PathBuf};
 
-use crate::parser::FireLocation;
+use crate::{parser::FireLocation, parser_pass_two::{walk_expression, walk_statements, BinaryOperator, Expression, ExpressionKind, FireType, MatchArm, ParsedFireProject, ParsedFunction, Statement, UnaryOperator}};
 
-pub enum CompileException<'a> {
-    NoFileAccess(&'a Path),
-    FileException(&'a Path),
-    DuplicateResource(&'a FireLocation),
+pub enum CompileException {
+    NoFileAccess(PathBuf),
+    FileException(PathBuf, Box<CompileException>),
+    InResource(FireLocation, Box<CompileException>),
+    DuplicateResource(FireLocation),
+    UnknownResource(String, usize, usize),
+    UnknownName(String, usize, usize),
+    WrongResourceType(FireLocation, &'static str),
+    NotVisible(FireLocation, &'static str, FireLocation, usize, usize), // A resource, whether it's private or internal, and where it can be used
     UnrecognizedToken(String, usize, usize),
+    UnexpectedToken(String, usize, usize),
+    UnexpectedEnd(FireLocation),
+    CyclicDependency(Vec<FireLocation>),
+    TypeError(String, usize, usize),
+    Unsupported(String, usize, usize),
+    NotConstant(String, usize, usize), // Something that only works on values known while compiling, used on one that isn't
     UnfinishedString(usize, usize),
-    UnrecognizedEscape(usize, usize)
+    UnrecognizedEscape(usize, usize),
+    OutOfRange(String, &'static str, usize, usize), // A number literal that's too big for its type
+    InvalidRename(String) // Why a rename can't be done
 }
-impl Display for CompileException<'_> {
+impl Display for CompileException {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             CompileException::NoFileAccess(p) => f.write_str(format!("Cannot read file {:?}.", p).as_str())?,
-            CompileException::FileException(p) => f.write_str(format!("An error occured inside of {:?}.", p).as_str())?,
-            CompileException::DuplicateResource(p) => f.write_str(format!("Duplicate resource {:?}.", p).as_str())?,
+            CompileException::FileException(p, e) => f.write_str(format!("An error occured inside of {:?}. {}", p, e).as_str())?,
+            CompileException::InResource(l, e) => f.write_str(format!("Inside of {}: {}", l, e).as_str())?,
+            CompileException::DuplicateResource(p) => f.write_str(format!("Duplicate resource {}.", p).as_str())?,
+            CompileException::UnknownResource(p, l, c) => f.write_str(format!("Cannot find resource {}. At {}:{}", p, l, c).as_str())?,
+            CompileException::UnknownName(a, b, c) => f.write_str(format!("Cannot find {}. At {}:{}", a, b, c).as_str())?,
+            CompileException::WrongResourceType(p, t) => f.write_str(format!("{} is not a {}.", p, t).as_str())?,
+            CompileException::NotVisible(a, b, c, d, e) => f.write_str(format!("{} is {} to {}, so it cannot be used here. At {}:{}", a, b, c, d, e).as_str())?,
             CompileException::UnrecognizedToken(a, b, c) => f.write_str(format!("Unrecognized token {:?}. At {}:{}", a, b, c).as_str())?,
+            CompileException::UnexpectedToken(a, b, c) => f.write_str(format!("Unexpected token {}. At {}:{}", a, b, c).as_str())?,
+            CompileException::UnexpectedEnd(p) => f.write_str(format!("Unexpected end of file while reading {}.", p).as_str())?,
+            CompileException::TypeError(a, b, c) => f.write_str(format!("{}. At {}:{}", a, b, c).as_str())?,
+            CompileException::Unsupported(a, b, c) => f.write_str(format!("{} is not supported. At {}:{}", a, b, c).as_str())?,
+            CompileException::NotConstant(a, b, c) => f.write_str(format!("{} only works on values known while compiling, but this is only known once the datapack runs. At {}:{}", a, b, c).as_str())?,
+            CompileException::CyclicDependency(l) => f.write_str(format!("{} depends on itself: {}.", l[0], l.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(" -> ")).as_str())?,
             CompileException::UnfinishedString(a, b) => f.write_str(format!("Unfinished string. Started at {}:{}", a, b).as_str())?,
             CompileException::UnrecognizedEscape(a, b) => f.write_str(format!("Unrecognized escape code. At {}:{}", a, b).as_str())?,
+            CompileException::OutOfRange(a, b, c, d) => f.write_str(format!("{} does not fit in a{} {}. At {}:{}", a, if b.starts_with('I') { "n" } else { "" }, b, c, d).as_str())?,
+            CompileException::InvalidRename(a) => f.write_str(format!("Cannot rename: {}.", a).as_str())?,
         };
         Ok(())
     }
 }
+impl CompileException {
+    // Where in its file the error happened, if it's known
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match self {
+            CompileException::FileException(_, e) | CompileException::InResource(_, e) => e.position(),
+            CompileException::UnknownName(_, l, c) | CompileException::UnknownResource(_, l, c) | CompileException::NotVisible(_, _, _, l, c) | CompileException::UnrecognizedToken(_, l, c) |
+                CompileException::UnexpectedToken(_, l, c) | CompileException::TypeError(_, l, c) | CompileException::Unsupported(_, l, c) |
+                CompileException::NotConstant(_, l, c) | CompileException::UnfinishedString(l, c) | CompileException::UnrecognizedEscape(l, c) |
+                CompileException::OutOfRange(_, _, l, c) => Some((*l, *c)),
+            _ => None
+        }
+    }
+}
+impl From<CompileException> for Box<dyn Display> {
+    fn from(e: CompileException) -> Self {
+        Box::new(e)
+    }
+}
 
 // Represents the minecraft commands that we output
-pub type CompiledCommands = Vec<String>;
\ No newline at end of file
+pub type CompiledCommands = Vec<String>;
+
+// The scoreboard objective that every score the compiler makes lives on
+pub const OBJECTIVE: &str = "fire";
+// Where a raise is kept while it unwinds: a score that is 1 while raising, and the message and location in storage
+pub const RAISED: &str = "_raised";
+pub const FAILED: &str = "_failed"; // When testing, the first raise that nothing caught is kept here for the test runner
+
+// Everything that gets written into the target folder. Functions are keyed by their path inside of the namespace.
+pub struct CompiledDatapack {
+    pub namespace: String,
+    pub functions: BTreeMap<String, CompiledCommands>,
+    pub load: Vec<String>, // Functions that #minecraft:load runs
+    pub tick: Vec<String> // Functions that #minecraft:tick runs
+}
+
+// A .name that was found to be a field or method of a struct or enum, where member is the type's location with the name on the end
+pub struct MemberUse {
+    pub within: FireLocation, // The function or global it was written in
+    pub member: FireLocation,
+    pub line: usize,
+    pub column: usize
+}
+
+// The mcfunction path of a resource, such as main/main/max
+pub fn function_path(loc: &FireLocation) -> String {
+    loc.segments().iter().map(|s| s.to_lowercase()).collect::<Vec<_>>().join("/")
+}
+
+// The name that a resource's values are stored under, such as main.main.max
+pub fn holder_name(loc: &FireLocation) -> String {
+    loc.segments().join(".")
+}
+
+// The name an event's functions and subscriptions are kept under, such as player_join
+fn event_id(event: &str) -> String {
+    let mut id = String::new();
+    for c in event.trim_end_matches("Event").chars() {
+        if c.is_uppercase() && !id.is_empty() {
+            id.push('_');
+        }
+        id.push(c.to_ascii_lowercase());
+    }
+    id
+}
+
+// Quotes a string for use in SNBT or JSON
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Formats an inclusive range for execute if score ... matches
+fn range(min: i64, max: i64) -> String {
+    if min == max { min.to_string() } else { format!("{}..{}", min, max) }
+}
+
+// Minecraft divides and takes remainders by flooring, so constant folding has to do the same
+fn floor_div(a: i64, b: i64) -> i64 {
+    let q = a / b;
+    if a % b != 0 && ((a < 0) != (b < 0)) { q - 1 } else { q }
+}
+
+// A folded Int has to fit in a score just like a literal does
+fn fold(value: Option<i32>, at: &Expression, written: impl Fn() -> String) -> Result<Value, CompileException> {
+    match value {
+        Some(v) => Ok(Value::Constant(v as i64)),
+        None => Err(CompileException::OutOfRange(written(), "Int", at.line, at.column))
+    }
+}
+
+// Where a value lives while the datapack runs
+#[derive(Clone, Debug)]
+enum Value {
+    Constant(i64), // Known while compiling, used for Int, Boolean and enum values
+    ConstString(String),
+    Score(String), // A score holder and its objective
+    Storage(String), // A path in the namespace's storage
+    Struct(String), // The name every field is stored under
+    Entity(String), // A selector for a single player
+    Void
+}
+
+// The value that a variable of some type stored under a name has
+fn place(ty: &FireType, base: &str) -> Value {
+    match ty {
+        FireType::String | FireType::List(_) | FireType::Map(..) | FireType::Function(..) => Value::Storage(base.to_string()),
+        FireType::Struct(_) => Value::Struct(base.to_string()),
+        FireType::Player => Value::Entity(format!("@e[tag={},limit=1]", base)), // A stored player is whoever has the tag
+        FireType::Event(_) => Value::Entity("@s".to_string()), // Handlers run as the player the event happened to
+        FireType::Void => Value::Void,
+        _ => Value::Score(score(base))
+    }
+}
+
+// The score that a variable stored under a name is kept in
+fn score(base: &str) -> String {
+    format!("${} {}", base, OBJECTIVE)
+}
+
+// Whether a value of one type can be used where another is expected. [] and [:] fit any List or Map.
+fn fits(expected: &FireType, found: &FireType) -> bool {
+    match (expected, found) {
+        (FireType::List(_), FireType::List(e)) | (FireType::Map(_, _), FireType::Map(e, _)) if **e == FireType::Void => true,
+        _ => expected == found
+    }
+}
+
+// An element of a List or Map. A key only known at runtime is put in _args, and a macro writes it into the path.
+enum Element {
+    Path(String),
+    Macro(&'static str) // Whether the macro is for a list or a map
+}
+
+// A range being looped over. It goes from first, adding step each time, for as long as it hasn't passed limit.
+struct RangeLoop {
+    first: Value,
+    step: i64,
+    limit: Value,
+    inclusive: bool
+}
+
+// Ranges with constant bounds and at most this many values call the body once for each of them instead of looping
+const UNROLL_LIMIT: usize = 16;
+
+// Everything known while compiling the body of one function
+struct FunctionContext {
+    path: String,
+    holder: String,
+    return_type: FireType,
+    scopes: Vec<HashMap<String, (FireType, String)>>, // Local name -> type and the name it's stored under
+    counter: usize,
+    depth: usize, // How many generated functions deep we are. Returning from inside of one needs a flag to stop the callers too.
+    nested_return: bool,
+    resumes: Vec<(i64, String)>, // For a pc, every place it waits and the function that carries on from there
+    function: Option<FireLocation>,
+    temps: Vec<(FireType, String)> // Temps made by the statements being compiled, which may still be needed after a call
+}
+impl FunctionContext {
+    fn new(path: String, holder: String, return_type: FireType) -> FunctionContext {
+        FunctionContext { path, holder, return_type, scopes: vec![HashMap::new()], counter: 0, depth: 0, nested_return: false, resumes: Vec::new(), function: None, temps: Vec::new() }
+    }
+    fn next_id(&mut self) -> usize {
+        self.counter += 1;
+        self.counter - 1
+    }
+    fn temp(&mut self, ty: &FireType) -> String {
+        let id = self.next_id();
+        let base = format!("{}._t{}", self.holder, id);
+        self.temps.push((ty.clone(), base.clone()));
+        base
+    }
+    // A new path for a function generated from part of this one
+    fn sub_path(&mut self, kind: &str) -> String {
+        let id = self.next_id();
+        format!("{}/_{}{}", self.path, kind, id)
+    }
+    fn lookup(&self, name: &str) -> Option<&(FireType, String)> {
+        self.scopes.iter().rev().find_map(|s| s.get(name))
+    }
+    // Adds a local, giving it a new name if another local already used this one
+    fn declare(&mut self, name: &str, ty: FireType) -> String {
+        let mut base = format!("{}.{}", self.holder, name);
+        if self.scopes.iter().any(|s| s.values().any(|(_, b)| *b == base)) {
+            let id = self.next_id();
+            base = format!("{}.{}_{}", self.holder, name, id);
+        }
+        self.scopes.last_mut().unwrap().insert(name.to_string(), (ty, base.clone()));
+        base
+    }
+    fn returned_flag(&self) -> String {
+        score(&format!("{}._returned", self.holder))
+    }
+}
+
+pub fn compile_project(project: &ParsedFireProject, namespace: &str) -> Result<CompiledDatapack, CompileException> {
+    compile(project, namespace, false).map(|(datapack, _)| datapack)
+}
+
+// Compiles a project along with its @Test functions, giving the id of the function each test is compiled into
+pub fn compile_tests(project: &ParsedFireProject, namespace: &str) -> Result<(CompiledDatapack, Vec<(FireLocation, String)>), CompileException> {
+    let (datapack, _) = compile(project, namespace, true)?;
+    let tests = project.tests.iter().map(|t| (t.clone(), format!("{}:{}", namespace, function_path(t)))).collect();
+    Ok((datapack, tests))
+}
+
+// Every field and method used with a ., which takes knowing the types of values and so compiling the project
+pub fn member_uses(project: &ParsedFireProject) -> Result<Vec<MemberUse>, CompileException> {
+    compile(project, "fire", true).map(|(_, members)| members)
+}
+
+fn compile(project: &ParsedFireProject, namespace: &str, tests: bool) -> Result<(CompiledDatapack, Vec<MemberUse>), CompileException> {
+    // @Test functions are left out unless testing
+    let included = |f: &&ParsedFunction| tests || !project.tests.contains(&f.loc);
+    let raises = project.functions.values().filter(included).any(|f| can_raise(&f.body));
+    let mut compiler = Compiler {
+        project, namespace: namespace.to_string(), functions: BTreeMap::new(), constants: BTreeSet::new(), enum_helpers: HashSet::new(), globals: HashMap::new(),
+        tick: Vec::new(), raises, testing: tests, reaches: call_graph(project), events: BTreeSet::new(), compiling: FireLocation::new(), members: Vec::new()
+    };
+    // Globals are set up first, so functions know their types
+    let mut ctx = FunctionContext::new("_load".to_string(), "_load".to_string(), FireType::Void);
+    let mut init = Vec::new();
+    for loc in global_order(project)? {
+        compiler.compiling = loc.clone();
+        compiler.compile_global(&mut ctx, &loc, &mut init).map_err(|e| CompileException::InResource(loc.clone(), Box::new(e)))?;
+    }
+    for f in project.functions.values().filter(included) {
+        // Functions over traits are compiled for each type they're used with
+        if f.params.iter().any(|(_, t)| matches!(t, FireType::Trait(_))) {
+            compiler.trait_members(f);
+            continue;
+        }
+        compiler.compile_function(&f.loc).map_err(|e| CompileException::InResource(f.loc.clone(), Box::new(e)))?;
+    }
+    for (event, _) in &project.event_handlers {
+        compiler.events.insert(event.clone());
+    }
+    let mut load = vec![format!("scoreboard objectives add {} dummy", OBJECTIVE)];
+    if compiler.functions.contains_key("_player_id") {
+        load.push("scoreboard objectives add fire.id dummy".to_string());
+    }
+    for event in compiler.events.clone() {
+        compiler.compile_event(&event, &mut load)?;
+    }
+    for c in &compiler.constants {
+        load.push(format!("scoreboard players set #{} {} {}", c, OBJECTIVE, c));
+    }
+    if raises && !init.is_empty() {
+        // Setting up the globals is the start of a chain, so a raise while doing it is reported rather than stopping the load
+        compiler.functions.insert("_load/_globals".to_string(), init);
+        load.push(format!("scoreboard players set {} 0", score(RAISED)));
+        load.push(format!("function {}", compiler.function_id("_load/_globals")));
+        load.push(compiler.report_uncaught()?);
+    } else {
+        load.append(&mut init);
+    }
+    compiler.functions.insert("_load".to_string(), load);
+    let mut tick = Vec::new();
+    if !compiler.tick.is_empty() {
+        compiler.functions.insert("_tick".to_string(), compiler.tick);
+        tick.push(format!("{}:_tick", namespace));
+    }
+    let datapack = CompiledDatapack { namespace: namespace.to_string(), functions: compiler.functions, load: vec![format!("{}:_load", namespace)], tick };
+    Ok((datapack, compiler.members))
+}
+
+// Orders the globals so that each one is set up after every global its value uses, either directly or through the functions it calls
+fn global_order(project: &ParsedFireProject) -> Result<Vec<FireLocation>, CompileException> {
+    fn visit(project: &ParsedFireProject, loc: &FireLocation, visiting: &mut Vec<FireLocation>, order: &mut Vec<FireLocation>) -> Result<(), CompileException> {
+        if order.contains(loc) {
+            return Ok(());
+        }
+        if let Some(i) = visiting.iter().position(|l| l == loc) {
+            let mut cycle = visiting[i..].to_vec();
+            cycle.push(loc.clone());
+            return Err(CompileException::CyclicDependency(cycle));
+        }
+        visiting.push(loc.clone());
+        for dependency in global_dependencies(project, &project.globals[loc].value) {
+            visit(project, &dependency, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(loc.clone());
+        Ok(())
+    }
+    let mut globals: Vec<&FireLocation> = project.globals.keys().collect();
+    globals.sort_by_key(|l| l.to_string());
+    let mut order = Vec::new();
+    for loc in globals {
+        visit(project, loc, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+// Where the first wait inside of some statements is, if there is one
+fn first_wait(statements: &[Statement]) -> Option<(usize, usize)> {
+    statements.iter().find_map(|s| match s {
+        Statement::Wait(_, line, column) => Some((*line, *column)),
+        Statement::If(_, a, b) | Statement::Try(a, _, b) => first_wait(a).or_else(|| first_wait(b)),
+        Statement::While(_, body) | Statement::For(_, _, body) | Statement::Block(body) => first_wait(body),
+        Statement::Match(_, arms) => arms.iter().find_map(|a| first_wait(&a.body)),
+        _ => None
+    })
+}
+
+// Whether any of the statements, or the statements nested inside of them, matches
+fn contains(statements: &[Statement], f: &dyn Fn(&Statement) -> bool) -> bool {
+    statements.iter().any(|s| f(s) || match s {
+        Statement::If(_, a, b) | Statement::Try(a, _, b) => contains(a, f) || contains(b, f),
+        Statement::While(_, body) | Statement::For(_, _, body) | Statement::Block(body) => contains(body, f),
+        Statement::Match(_, arms) => arms.iter().any(|a| contains(&a.body, f)),
+        _ => false
+    })
+}
+
+// Whether an expression is a range, possibly with .step(n) or .reverse() on it
+fn is_range(expr: &Expression) -> bool {
+    match &expr.kind {
+        ExpressionKind::Range(..) => true,
+        ExpressionKind::Call(callee, _) => matches!(&callee.kind, ExpressionKind::Field(inner, name) if (name == "step" || name == "reverse") && is_range(inner)),
+        _ => false
+    }
+}
+
+// Whether any of the statements can raise, including inside of the lambdas they make. A failed assertion raises too.
+fn can_raise(statements: &[Statement]) -> bool {
+    let mut found = contains(statements, &|s| matches!(s, Statement::Raise(..)));
+    walk_statements(statements, &mut |e| match &e.kind {
+        ExpressionKind::Lambda(_, body) => found |= contains(body, &|s| matches!(s, Statement::Raise(..))),
+        ExpressionKind::Call(callee, _) => found |= matches!(&callee.kind, ExpressionKind::Builtin(name) if name == "assert" || name == "assertEq"),
+        _ => {}
+    });
+    found
+}
+
+// Every global an expression reads, including ones read by the functions it calls
+fn global_dependencies(project: &ParsedFireProject, value: &Expression) -> Vec<FireLocation> {
+    let mut found = Vec::new();
+    let mut functions: Vec<FireLocation> = Vec::new();
+    let mut check = |e: &Expression, found: &mut Vec<FireLocation>, pending: &mut Vec<FireLocation>| {
+        if let ExpressionKind::Resource(loc, _) = &e.kind {
+            if project.globals.contains_key(loc) && !found.contains(loc) {
+                found.push(loc.clone());
+            } else if project.functions.contains_key(loc) && !functions.contains(loc) {
+                functions.push(loc.clone());
+                pending.push(loc.clone());
+            }
+        }
+    };
+    let mut pending = Vec::new();
+    walk_expression(value, &mut |e| check(e, &mut found, &mut pending));
+    while let Some(f) = pending.pop() {
+        let mut next = Vec::new();
+        walk_statements(&project.functions[&f].body, &mut |e| check(e, &mut found, &mut next));
+        pending.append(&mut next);
+    }
+    found
+}
+
+// Every function that each function can end up calling, either directly or through others. A method call could be to any method with that name.
+// A lambda's body runs as part of the function that made it, so calling a lambda could be calling any function that makes one.
+fn call_graph(project: &ParsedFireProject) -> HashMap<FireLocation, HashSet<FireLocation>> {
+    let makes_lambdas: Vec<FireLocation> = project.functions.values()
+        .filter(|f| {
+            let mut found = false;
+            walk_statements(&f.body, &mut |e| found |= matches!(e.kind, ExpressionKind::Lambda(..)));
+            found
+        })
+        .map(|f| f.loc.clone())
+        .collect();
+    let calls: HashMap<FireLocation, Vec<FireLocation>> = project.functions.values().map(|f| {
+        let mut called = Vec::new();
+        walk_statements(&f.body, &mut |e| match &e.kind {
+            ExpressionKind::Resource(loc, rest) if rest.is_empty() && project.functions.contains_key(loc) => called.push(loc.clone()),
+            ExpressionKind::Call(callee, _) => match &callee.kind {
+                ExpressionKind::Resource(..) | ExpressionKind::Builtin(_) => {}
+                // Without a method of that name, it's a lambda kept in a field or a built in one like List.forEach
+                ExpressionKind::Field(_, name) => {
+                    let methods: Vec<FireLocation> = project.functions.values()
+                        .filter(|m| m.loc.last() == Some(name) && m.params.first().is_some_and(|(p, _)| p == "self"))
+                        .map(|m| m.loc.clone())
+                        .collect();
+                    if methods.is_empty() {
+                        called.extend(makes_lambdas.iter().cloned());
+                    }
+                    called.extend(methods);
+                }
+                _ => called.extend(makes_lambdas.iter().cloned())
+            },
+            _ => {}
+        });
+        (f.loc.clone(), called)
+    }).collect();
+    calls.keys().map(|f| {
+        let mut reached = HashSet::new();
+        let mut pending = calls[f].clone();
+        while let Some(g) = pending.pop() {
+            if reached.insert(g.clone()) {
+                pending.extend(calls[&g].iter().cloned());
+            }
+        }
+        (f.clone(), reached)
+    }).collect()
+}
+
+struct Compiler<'a> {
+    project: &'a ParsedFireProject,
+    namespace: String,
+    functions: BTreeMap<String, CompiledCommands>,
+    constants: BTreeSet<i64>, // Numbers that need a #N score for operations that can't take a literal
+    enum_helpers: HashSet<FireLocation>, // Enums whose conversion functions have been made
+    globals: HashMap<FireLocation, (FireType, Value)>, // Where each global is stored, or its value if it's a const
+    tick: CompiledCommands,
+    raises: bool, // Whether anything can raise. If not, calls don't need to check for it.
+    testing: bool, // Whether @Test functions are being compiled along with everything else
+    reaches: HashMap<FireLocation, HashSet<FireLocation>>, // Used to find calls that can come back around to the function making them
+    events: BTreeSet<String>, // Events that something handles or subscribes to
+    compiling: FireLocation, // The function or global being compiled
+    members: Vec<MemberUse>
+}
+impl Compiler<'_> {
+    fn storage(&self) -> String {
+        format!("{}:data", self.namespace)
+    }
+    fn function_id(&self, path: &str) -> String {
+        format!("{}:{}", self.namespace, path)
+    }
+    fn constant(&mut self, c: i64) -> String {
+        self.constants.insert(c);
+        format!("#{}", c)
+    }
+
+    // Remembers which field or method a .name turned out to be
+    fn used(&mut self, member: FireLocation, at: &Expression) {
+        self.members.push(MemberUse { within: self.compiling.clone(), member, line: at.line, column: at.column });
+    }
+
+    // Methods called on parameters of a function over traits, which isn't compiled until it's used with a type
+    fn trait_members(&mut self, f: &ParsedFunction) {
+        let mut found = Vec::new();
+        walk_statements(&f.body, &mut |e| {
+            let ExpressionKind::Field(inner, name) = &e.kind else { return };
+            let ExpressionKind::Variable(param) = &inner.kind else { return };
+            if let Some((_, FireType::Trait(t))) = f.params.iter().find(|(n, _)| n == param) {
+                found.push(MemberUse { within: f.loc.clone(), member: t.with(name.clone()), line: e.line, column: e.column });
+            }
+        });
+        self.members.append(&mut found);
+    }
+
+    // Stops the current function if whatever it just called raised
+    fn raise_check(&self, out: &mut CompiledCommands) {
+        if self.raises {
+            out.push(format!("execute if score {} matches 1 run return 0", score(RAISED)));
+        }
+    }
+
+    // The command that ends a chain of calls, passing anything raised that nothing caught to the @Uncaught handler or logging it to operators
+    fn report_uncaught(&mut self) -> Result<String, CompileException> {
+        if !self.functions.contains_key("_uncaught") {
+            let storage = self.storage();
+            // Cleared first, so that the handler's own calls aren't stopped
+            let mut report = vec![format!("scoreboard players set {} 0", score(RAISED))];
+            // A raise in something a test scheduled would otherwise be gone before the test runner could see it
+            if self.testing {
+                report.push(format!("execute unless data storage {} {} run data modify storage {} {} set from storage {} {}", storage, FAILED, storage, FAILED, storage, RAISED));
+            }
+            match &self.project.uncaught {
+                Some(handler) => {
+                    let holder = holder_name(handler);
+                    report.push(format!("data modify storage {} {}.message set from storage {} {}.message", storage, holder, storage, RAISED));
+                    report.push(format!("data modify storage {} {}.at set from storage {} {}.at", storage, holder, storage, RAISED));
+                    report.push(format!("function {}", self.function_id(&function_path(handler))));
+                }
+                // Operators are marked with the fire.op tag, since selectors can't check for them
+                None => report.push(format!("tellraw @a[tag=fire.op] [{{\"text\":\"Uncaught raise in \",\"color\":\"red\"}},{{\"nbt\":\"{}.at\",\"storage\":\"{}\",\"color\":\"red\"}},{{\"text\":\": \",\"color\":\"red\"}},{{\"nbt\":\"{}.message\",\"storage\":\"{}\"}}]", RAISED, storage, RAISED, storage))
+            }
+            self.functions.insert("_uncaught".to_string(), report);
+        }
+        Ok(format!("execute if score {} matches 1 run function {}", score(RAISED), self.function_id("_uncaught")))
+    }
+
+    // The command that runs the lambda in _closure, making the macro that does it the first time it's needed
+    fn run_closure(&mut self) -> String {
+        if !self.functions.contains_key("_call") {
+            self.functions.insert("_call".to_string(), vec!["$function $(fn)".to_string()]);
+        }
+        format!("function {} with storage {} _closure", self.function_id("_call"), self.storage())
+    }
+
+    // The functions that start tasks from schedule once they're due. Each one starts a new chain of calls.
+    fn task_functions(&mut self) -> Result<(), CompileException> {
+        if self.functions.contains_key("_tasks/run") {
+            return Ok(());
+        }
+        let storage = self.storage();
+        let (now, wake) = (score("_tasks.now"), score("_tasks.wake"));
+        let next = self.function_id("_tasks/next");
+        self.functions.insert("_tasks/run".to_string(), vec![
+            format!("execute store result score {} run time query gametime", now),
+            format!("data modify storage {} _tasks.pending set from storage {} _tasks.waiting", storage, storage),
+            format!("data modify storage {} _tasks.waiting set value []", storage),
+            format!("function {}", next)
+        ]);
+        self.functions.insert("_tasks/next".to_string(), vec![
+            format!("execute unless data storage {} _tasks.pending[0] run return 0", storage),
+            format!("data modify storage {} _tasks.next set from storage {} _tasks.pending[0]", storage, storage),
+            format!("data remove storage {} _tasks.pending[0]", storage),
+            format!("execute store result score {} run data get storage {} _tasks.next.wake", wake, storage),
+            format!("execute unless score {} <= {} run data modify storage {} _tasks.waiting append from storage {} _tasks.next", wake, now, storage, storage),
+            format!("execute if score {} <= {} run function {}", wake, now, self.function_id("_tasks/start")),
+            format!("function {}", next)
+        ]);
+        let mut start = vec![format!("data modify storage {} _closure set from storage {} _tasks.next.closure", storage, storage)];
+        start.push(self.run_closure());
+        self.chain_root(&mut start)?;
+        self.functions.insert("_tasks/start".to_string(), start);
+        Ok(())
+    }
+
+    // Makes commands the start of a chain of calls, reporting anything they raise that isn't caught
+    fn chain_root(&mut self, commands: &mut CompiledCommands) -> Result<(), CompileException> {
+        if self.raises {
+            commands.insert(0, format!("scoreboard players set {} 0", score(RAISED)));
+            commands.push(self.report_uncaught()?);
+        }
+        Ok(())
+    }
+
+    // Checks for an event every tick, running each @EventHandler and subscribed lambda as the player it happened to
+    fn compile_event(&mut self, event: &str, load: &mut CompiledCommands) -> Result<(), CompileException> {
+        let (storage, id) = (self.storage(), event_id(event));
+        let path = format!("_events/{}", id);
+        let mut commands = Vec::new();
+        match event {
+            "PlayerJoinEvent" => {
+                // Players are marked once they've joined. Leaving adds to fire.left, which clears the mark for when they're back.
+                load.push("scoreboard objectives add fire.joined dummy".to_string());
+                load.push("scoreboard objectives add fire.left minecraft.custom:minecraft.leave_game".to_string());
+                self.tick.push("scoreboard players reset @a[scores={fire.left=1..}] fire.joined".to_string());
+                self.tick.push("scoreboard players reset @a[scores={fire.left=1..}] fire.left".to_string());
+                self.tick.push(format!("execute as @a unless score @s fire.joined matches 1 run function {}", self.function_id(&path)));
+                commands.push("scoreboard players set @s fire.joined 1".to_string());
+            }
+            _ => unreachable!()
+        }
+        // Lambdas can't be kept between loads, since the functions they run may have changed
+        load.push(format!("data modify storage {} _events.{} set value []", storage, id));
+        let mut handlers: Vec<&FireLocation> = self.project.event_handlers.iter().filter(|(e, _)| e == event).map(|(_, l)| l).collect();
+        handlers.sort_by_key(|l| l.to_string());
+        for handler in handlers {
+            let mut call = vec![format!("function {}", self.function_id(&function_path(handler)))];
+            self.chain_root(&mut call)?;
+            commands.append(&mut call);
+        }
+        let each = format!("{}_each", path);
+        let mut each_commands = vec![
+            format!("execute unless data storage {} _events.pending[0] run return 0", storage),
+            format!("data modify storage {} _closure set from storage {} _events.pending[0]", storage, storage),
+            format!("data remove storage {} _events.pending[0]", storage)
+        ];
+        let mut call = vec![self.run_closure()];
+        self.chain_root(&mut call)?;
+        each_commands.append(&mut call);
+        each_commands.push(format!("function {}", self.function_id(&each)));
+        commands.push(format!("data modify storage {} _events.pending set from storage {} _events.{}", storage, storage, id));
+        commands.push(format!("function {}", self.function_id(&each)));
+        self.functions.insert(each, each_commands);
+        self.functions.insert(path, commands);
+        Ok(())
+    }
+
+    // Sets up a global in the load function. Consts have to be known now, and are never stored.
+    fn compile_global(&mut self, ctx: &mut FunctionContext, loc: &FireLocation, out: &mut CompiledCommands) -> Result<(), CompileException> {
+        let global = &self.project.globals[loc];
+        let mut commands = Vec::new();
+        let (mut ty, value) = self.compile_expected(ctx, &global.value, global.ty.as_ref(), &mut commands)?;
+        if let Some(expected) = &global.ty {
+            if !fits(expected, &ty) {
+                return Err(CompileException::TypeError(format!("Expected {} but found {}", expected, ty), global.value.line, global.value.column));
+            }
+            ty = expected.clone();
+        }
+        if ty == FireType::Void {
+            return Err(CompileException::TypeError(format!("{} cannot be Void", loc), global.value.line, global.value.column));
+        }
+        if global.constant {
+            if !commands.is_empty() || !matches!(value, Value::Constant(_) | Value::ConstString(_)) {
+                return Err(CompileException::TypeError(format!("The value of {} must be known while compiling", loc), global.value.line, global.value.column));
+            }
+            self.globals.insert(loc.clone(), (ty, value));
+        } else if global.per_player {
+            // Every player's value is kept on an objective of its own, and read as @s by whoever runs the code
+            if matches!(ty, FireType::String | FireType::Struct(_) | FireType::List(_) | FireType::Map(..)) {
+                return Err(CompileException::Unsupported(format!("{} as a @PerPlayer value", ty), global.value.line, global.value.column));
+            }
+            let objective = holder_name(loc);
+            if !global.persistent {
+                out.push(format!("scoreboard objectives remove {}", objective));
+            }
+            out.push(format!("scoreboard objectives add {} dummy", objective));
+            out.append(&mut commands);
+            let default = format!("{}._default", objective);
+            self.copy_into(out, &ty, &default, &value)?;
+            // Players that don't have a value yet start with the default
+            self.tick.push(format!("execute as @a unless score @s {} = @s {} run scoreboard players operation @s {} = {}", objective, objective, objective, score(&default)));
+            self.globals.insert(loc.clone(), (ty, Value::Score(format!("@s {}", objective))));
+        } else {
+            let base = holder_name(loc);
+            if global.persistent {
+                // Only set up the first time the datapack loads in a world
+                let flag = score(&format!("{}._set", base));
+                self.copy_into(&mut commands, &ty, &base, &value)?;
+                commands.push(format!("scoreboard players set {} 1", flag));
+                let path = ctx.sub_path("init");
+                self.functions.insert(path.clone(), commands);
+                out.push(format!("execute unless score {} matches 1 run function {}", flag, self.function_id(&path)));
+            } else {
+                out.append(&mut commands);
+                self.copy_into(out, &ty, &base, &value)?;
+            }
+            self.globals.insert(loc.clone(), (ty.clone(), place(&ty, &base)));
+        }
+        Ok(())
+    }
+
+    fn compile_function(&mut self, loc: &FireLocation) -> Result<(), CompileException> {
+        let f = &self.project.functions[loc];
+        self.compiling = loc.clone();
+        let holder = holder_name(loc);
+        let mut ctx = FunctionContext::new(function_path(loc), holder.clone(), f.return_type.clone());
+        ctx.function = Some(loc.clone());
+        for (name, ty) in &f.params {
+            ctx.scopes[0].insert(name.clone(), (ty.clone(), format!("{}.{}", holder, name)));
+        }
+        let mut body = Vec::new();
+        if f.process && first_wait(&f.body).is_some() {
+            self.compile_async(&mut ctx, &f.body, None, &mut body)?;
+            self.compile_resume(&mut ctx)?;
+        } else {
+            self.compile_statements(&mut ctx, &f.body, &mut body)?;
+        }
+        if ctx.nested_return {
+            body.insert(0, format!("scoreboard players set {} 0", ctx.returned_flag()));
+        }
+        if f.process && self.raises {
+            // Processes start a chain of calls, so they report anything raised inside of them that wasn't caught
+            let inner = format!("{}/_body", ctx.path);
+            self.functions.insert(inner.clone(), body);
+            body = vec![
+                format!("scoreboard players set {} 0", score(RAISED)),
+                format!("function {}", self.function_id(&inner)),
+                self.report_uncaught()?
+            ];
+        }
+        self.functions.insert(ctx.path, body);
+        Ok(())
+    }
+
+    // Compiles statements into out, returning whether any of them can return from the function
+    fn compile_statements(&mut self, ctx: &mut FunctionContext, statements: &[Statement], out: &mut CompiledCommands) -> Result<bool, CompileException> {
+        ctx.scopes.push(HashMap::new());
+        let mut returns = false;
+        for (i, s) in statements.iter().enumerate() {
+            let temps = ctx.temps.len();
+            let result = match s {
+                // Everything after a select runs as the player, so it's compiled along with it
+                Statement::Select(player) => self.compile_select(ctx, player, &statements[i + 1..], out),
+                _ => self.compile_statement(ctx, s, out)
+            };
+            ctx.temps.truncate(temps);
+            match result {
+                Ok(r) => returns |= r,
+                Err(e) => {
+                    ctx.scopes.pop();
+                    return Err(e);
+                }
+            }
+            if matches!(s, Statement::Select(_)) {
+                break;
+            }
+        }
+        ctx.scopes.pop();
+        Ok(returns)
+    }
+
+    fn compile_select(&mut self, ctx: &mut FunctionContext, player: &Expression, rest: &[Statement], out: &mut CompiledCommands) -> Result<bool, CompileException> {
+        let (ty, value) = self.compile_expression(ctx, player, out)?;
+        let Value::Entity(selector) = value else {
+            return Err(CompileException::TypeError(format!("Expected Player but found {}", ty), player.line, player.column));
+        };
+        if selector == "@s" {
+            return self.compile_statements(ctx, rest, out);
+        }
+        let (id, returns) = self.compile_sub_function(ctx, "select", rest, Vec::new())?;
+        self.call_sub_function(ctx, out, &format!("execute as {} run ", selector), &id, returns);
+        Ok(returns)
+    }
+
+    // Compiles the statements of a pc that waits. Whatever comes after a wait is split off into functions that are run once the wait is over,
+    // and then is the function to run once these statements finish.
+    fn compile_async(&mut self, ctx: &mut FunctionContext, statements: &[Statement], then: Option<String>, out: &mut CompiledCommands) -> Result<(), CompileException> {
+        ctx.scopes.push(HashMap::new());
+        let result = self.compile_async_statements(ctx, statements, then, out);
+        ctx.scopes.pop();
+        result
+    }
+
+    fn compile_async_statements(&mut self, ctx: &mut FunctionContext, statements: &[Statement], then: Option<String>, out: &mut CompiledCommands) -> Result<(), CompileException> {
+        let Some(i) = statements.iter().position(|s| first_wait(std::slice::from_ref(s)).is_some()) else {
+            self.compile_statements(ctx, statements, out)?;
+            out.extend(then.map(|then| format!("function {}", then)));
+            return Ok(());
+        };
+        for s in &statements[..i] {
+            if let Statement::Select(player) = s {
+                return Err(CompileException::Unsupported("Waiting after a select".to_string(), player.line, player.column));
+            }
+            self.compile_statement(ctx, s, out)?;
+        }
+        // The statements after the one that waits are run once it's done, however long that takes
+        let rest = &statements[i + 1..];
+        let after = if rest.is_empty() { then } else { Some(self.compile_async_function(ctx, "then", rest, then)?) };
+        match &statements[i] {
+            Statement::Wait(ticks, _, _) => self.compile_wait(ctx, ticks, after, out),
+            Statement::Block(body) => self.compile_async(ctx, body, after, out),
+            Statement::If(condition, body, otherwise) => {
+                let holder = match self.compile_condition(ctx, condition, out)? {
+                    Value::Constant(c) => return self.compile_async(ctx, if c != 0 { body } else { otherwise }, after, out),
+                    Value::Score(h) => h,
+                    _ => unreachable!()
+                };
+                let id = self.compile_async_function(ctx, "if", body, after.clone())?;
+                out.push(format!("execute if score {} matches 1 run return run function {}", holder, id));
+                self.compile_async(ctx, otherwise, after, out)
+            }
+            Statement::While(condition, body) => {
+                let path = ctx.sub_path("while");
+                let id = self.function_id(&path);
+                let mut commands = Vec::new();
+                match self.compile_condition(ctx, condition, &mut commands)? {
+                    Value::Constant(0) => {
+                        out.extend(after.map(|after| format!("function {}", after)));
+                        return Ok(());
+                    }
+                    Value::Constant(_) => self.compile_async(ctx, body, Some(id.clone()), &mut commands)?,
+                    Value::Score(h) => {
+                        let body_id = self.compile_async_function(ctx, "do", body, Some(id.clone()))?;
+                        commands.push(format!("execute if score {} matches 1 run return run function {}", h, body_id));
+                        commands.extend(after.map(|after| format!("function {}", after)));
+                    }
+                    _ => unreachable!()
+                }
+                self.functions.insert(path, commands);
+                out.push(format!("function {}", id));
+                Ok(())
+            }
+            s => {
+                let (line, column) = first_wait(std::slice::from_ref(s)).unwrap();
+                Err(CompileException::Unsupported("Waiting inside of a for, match or try".to_string(), line, column))
+            }
+        }
+    }
+
+    // Compiles part of a pc into a function of its own. It's only ever the last thing its caller runs, so a return inside of it doesn't need a flag.
+    fn compile_async_function(&mut self, ctx: &mut FunctionContext, kind: &str, statements: &[Statement], then: Option<String>) -> Result<String, CompileException> {
+        let path = ctx.sub_path(kind);
+        let depth = std::mem::replace(&mut ctx.depth, 0);
+        let mut body = Vec::new();
+        let result = self.compile_async(ctx, statements, then, &mut body);
+        ctx.depth = depth;
+        result?;
+        self.functions.insert(path.clone(), body);
+        Ok(self.function_id(&path))
+    }
+
+    // Saves this instance of the pc into storage and schedules it to carry on later
+    fn compile_wait(&mut self, ctx: &mut FunctionContext, ticks: &Expression, after: Option<String>, out: &mut CompiledCommands) -> Result<(), CompileException> {
+        let storage = self.storage();
+        let (ty, ticks_value) = self.compile_expression(ctx, ticks, out)?;
+        if ty != FireType::Int {
+            return Err(CompileException::TypeError(format!("Expected to wait an Int but found {}", ty), ticks.line, ticks.column));
+        }
+        let frame = format!("{}._frame", ctx.holder);
+        let wake = score(&format!("{}._wake", ctx.holder));
+        let state = ctx.next_id() as i64;
+        out.push(format!("data modify storage {} {} set value {{state:{}}}", storage, frame, state));
+        out.push(format!("execute store result score {} run time query gametime", wake));
+        match &ticks_value {
+            Value::Constant(c) if *c < 1 => return Err(CompileException::TypeError("A wait must be at least 1 tick".to_string(), ticks.line, ticks.column)),
+            Value::Constant(c) => out.push(format!("scoreboard players add {} {}", wake, c)),
+            Value::Score(h) => {
+                out.push(format!("scoreboard players operation {} += {}", wake, h));
+                out.push(format!("execute store result storage {} {}.ticks int 1 run scoreboard players get {}", storage, frame, h));
+            }
+            _ => unreachable!()
+        }
+        out.push(format!("execute store result storage {} {}.wake int 1 run scoreboard players get {}", storage, frame, wake));
+        // Every local in scope is kept with the instance, since whatever comes next may use it
+        let mut locals: Vec<(FireType, String)> = ctx.scopes.iter().flat_map(|s| s.values().cloned()).collect();
+        locals.sort_by(|a, b| a.1.cmp(&b.1));
+        let mut restore = vec![format!("scoreboard players set {} 0", ctx.returned_flag())];
+        for (ty, base) in &locals {
+            self.save_local(ty, base, &frame, out, &mut restore)?;
+        }
+        out.push(format!("data modify storage {} {}._waiting append from storage {} {}", storage, ctx.holder, storage, frame));
+        let resume = self.function_id(&format!("{}/_resume", ctx.path));
+        match ticks_value {
+            Value::Constant(c) => out.push(format!("schedule function {} {}t append", resume, c)),
+            _ => {
+                // Schedule only takes a number written into the command, so a macro writes it in
+                let path = format!("{}/_wait", ctx.path);
+                self.functions.insert(path.clone(), vec![format!("$schedule function {} $(ticks)t append", resume)]);
+                out.push(format!("function {} with storage {} {}", self.function_id(&path), storage, frame));
+            }
+        }
+        restore.extend(after.map(|after| format!("function {}", after)));
+        let path = ctx.sub_path("state");
+        self.functions.insert(path.clone(), restore);
+        ctx.resumes.push((state, self.function_id(&path)));
+        Ok(())
+    }
+
+    // Copies a local into a frame in storage, and adds the command that copies it back out
+    fn save_local(&mut self, ty: &FireType, base: &str, frame: &str, out: &mut CompiledCommands, restore: &mut CompiledCommands) -> Result<(), CompileException> {
+        let storage = self.storage();
+        match ty {
+            FireType::Struct(loc) => {
+                for (field, field_type) in &self.project.structs[loc].fields {
+                    self.save_local(field_type, &format!("{}.{}", base, field), frame, out, restore)?;
+                }
+            }
+            FireType::String | FireType::List(_) | FireType::Map(..) | FireType::Function(..) => {
+                out.push(format!("data modify storage {} {}.{} set from storage {} {}", storage, frame, base, storage, base));
+                restore.push(format!("data modify storage {} {} set from storage {} {}.{}", storage, base, storage, frame, base));
+            }
+            FireType::Player => {
+                // The player is kept by a number they're given on fire.id the first time, since that's all storage can hold.
+                // Nobody has 0, so a local that wasn't anyone stays that way.
+                if !self.functions.contains_key("_player_id") {
+                    self.functions.insert("_player_id".to_string(), vec![
+                        format!("scoreboard players add {} 1", score("_player_ids")),
+                        format!("scoreboard players operation @s fire.id = {}", score("_player_ids"))
+                    ]);
+                }
+                let selector = format!("@e[tag={},limit=1]", base);
+                out.push(format!("execute as {} unless score @s fire.id matches 1.. run function {}", selector, self.function_id("_player_id")));
+                out.push(format!("execute store result storage {} {}.{} int 1 run scoreboard players get {} fire.id", storage, frame, base, selector));
+                restore.push(format!("tag @e remove {}", base));
+                restore.push(format!("execute store result score {} run data get storage {} {}.{}", score("_player"), storage, frame, base));
+                restore.push(format!("execute as @e if score @s fire.id = {} run tag @s add {}", score("_player"), base));
+            }
+            FireType::Event(_) => {} // Always whoever runs the function
+            _ => {
+                out.push(format!("execute store result storage {} {}.{} int 1 run scoreboard players get {}", storage, frame, base, score(base)));
+                restore.push(format!("execute store result score {} run data get storage {} {}.{}", score(base), storage, frame, base));
+            }
+        }
+        Ok(())
+    }
+
+    // The functions that wake a pc's waiting instances up. Each instance is checked when anything is scheduled,
+    // and the ones whose time has come carry on from where they waited while the rest keep waiting.
+    fn compile_resume(&mut self, ctx: &mut FunctionContext) -> Result<(), CompileException> {
+        let (storage, holder) = (self.storage(), ctx.holder.clone());
+        let (now, wake, state) = (score(&format!("{}._now", holder)), score(&format!("{}._wake", holder)), score(&format!("{}._state", holder)));
+        let next = self.function_id(&format!("{}/_next", ctx.path));
+        let restore = self.function_id(&format!("{}/_restore", ctx.path));
+        self.functions.insert(format!("{}/_resume", ctx.path), vec![
+            format!("execute store result score {} run time query gametime", now),
+            format!("data modify storage {} {}._pending set from storage {} {}._waiting", storage, holder, storage, holder),
+            format!("data modify storage {} {}._waiting set value []", storage, holder),
+            format!("function {}", next)
+        ]);
+        self.functions.insert(format!("{}/_next", ctx.path), vec![
+            format!("execute unless data storage {} {}._pending[0] run return 0", storage, holder),
+            format!("data modify storage {} {}._frame set from storage {} {}._pending[0]", storage, holder, storage, holder),
+            format!("data remove storage {} {}._pending[0]", storage, holder),
+            format!("execute store result score {} run data get storage {} {}._frame.wake", wake, storage, holder),
+            format!("execute unless score {} <= {} run data modify storage {} {}._waiting append from storage {} {}._frame", wake, now, storage, holder, storage, holder),
+            format!("execute if score {} <= {} run function {}", wake, now, restore),
+            format!("function {}", next)
+        ]);
+        let mut commands = Vec::new();
+        if self.raises {
+            commands.push(format!("scoreboard players set {} 0", score(RAISED)));
+        }
+        commands.push(format!("execute store result score {} run data get storage {} {}._frame.state", state, storage, holder));
+        let cases: Vec<(i64, i64, String)> = ctx.resumes.iter().map(|(s, id)| (*s, *s, format!("function {}", id))).collect();
+        self.compile_dispatch(ctx, &state, &cases, None, &mut commands);
+        // Each instance that wakes up starts a new chain of calls
+        if self.raises {
+            commands.push(self.report_uncaught()?);
+        }
+        self.functions.insert(format!("{}/_restore", ctx.path), commands);
+        Ok(())
+    }
+
+    // Compiles statements into a generated function of their own, returning its id and whether it can return
+    fn compile_sub_function(&mut self, ctx: &mut FunctionContext, kind: &str, statements: &[Statement], mut body: CompiledCommands) -> Result<(String, bool), CompileException> {
+        let path = ctx.sub_path(kind);
+        ctx.depth += 1;
+        let returns = self.compile_statements(ctx, statements, &mut body);
+        ctx.depth -= 1;
+        self.functions.insert(path.clone(), body);
+        Ok((self.function_id(&path), returns?))
+    }
+
+    // Calls a generated function, stopping this one afterwards if it returned
+    fn call_sub_function(&self, ctx: &FunctionContext, out: &mut CompiledCommands, prefix: &str, id: &str, returns: bool) {
+        out.push(format!("{}function {}", prefix, id));
+        if returns {
+            out.push(format!("execute if score {} matches 1 run return 0", ctx.returned_flag()));
+        }
+        self.raise_check(out);
+    }
+
+    fn compile_statement(&mut self, ctx: &mut FunctionContext, statement: &Statement, out: &mut CompiledCommands) -> Result<bool, CompileException> {
+        match statement {
+            Statement::Let(name, ty, expr) => {
+                let (mut value_type, value) = self.compile_expected(ctx, expr, ty.as_ref(), out)?;
+                if let Some(ty) = ty {
+                    if !fits(ty, &value_type) {
+                        return Err(CompileException::TypeError(format!("Expected {} but found {}", ty, value_type), expr.line, expr.column));
+                    }
+                    value_type = ty.clone();
+                }
+                if value_type == FireType::Void {
+                    return Err(CompileException::TypeError(format!("{} cannot be Void", name), expr.line, expr.column));
+                }
+                if matches!(&value_type, FireType::List(e) | FireType::Map(e, _) if **e == FireType::Void) {
+                    return Err(CompileException::TypeError(format!("The type of {} has to be written, such as List<Int>", name), expr.line, expr.column));
+                }
+                let base = ctx.declare(name, value_type.clone());
+                self.copy_into(out, &value_type, &base, &value)?;
+                Ok(false)
+            }
+            Statement::Assign(target, operator, value) => {
+                if let ExpressionKind::Index(collection, key) = &target.kind {
+                    let (value_type, value) = match operator {
+                        Some(o) => self.compile_binary(ctx, *o, target, value, out)?,
+                        None => self.compile_expression(ctx, value, out)?
+                    };
+                    let (ty, base) = self.collection(ctx, collection, out)?;
+                    let (element_type, element) = self.element(ctx, &ty, &base, key, out)?;
+                    if !fits(&element_type, &value_type) {
+                        return Err(CompileException::TypeError(format!("Expected {} but found {}", element_type, value_type), target.line, target.column));
+                    }
+                    self.write_element(out, &element_type, &element, &value, target)?;
+                    return Ok(false);
+                }
+                let (target_type, place) = self.place_of(ctx, target)?;
+                let (value_type, value) = match operator {
+                    Some(o) => self.compile_binary(ctx, *o, target, value, out)?,
+                    None => self.compile_expected(ctx, value, Some(&target_type), out)?
+                };
+                if !fits(&target_type, &value_type) {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", target_type, value_type), target.line, target.column));
+                }
+                self.store(out, &target_type, &place, &value)?;
+                Ok(false)
+            }
+            Statement::Expression(e) => {
+                self.compile_expression(ctx, e, out)?;
+                Ok(false)
+            }
+            Statement::Block(statements) => self.compile_statements(ctx, statements, out),
+            Statement::Select(player) => self.compile_select(ctx, player, &[], out),
+            Statement::Wait(_, line, column) => Err(CompileException::TypeError("Only a pc can wait".to_string(), *line, *column)),
+            Statement::Raise(message, line, column) => {
+                let storage = self.storage();
+                let message = match message {
+                    Some(m) => {
+                        let (ty, value) = self.compile_expression(ctx, m, out)?;
+                        if ty != FireType::String {
+                            return Err(CompileException::TypeError(format!("Expected to raise a String but found {}", ty), m.line, m.column));
+                        }
+                        value
+                    }
+                    None => Value::ConstString(format!("Raised at {}:{}", line, column))
+                };
+                // Replacing the whole raise clears anything an earlier one left behind. Holders are the function's location with . instead of ::
+                out.push(format!("data modify storage {} {} set value {{at:{}}}", storage, RAISED, quote(&ctx.holder.replace('.', "::"))));
+                self.copy_into(out, &FireType::String, &format!("{}.message", RAISED), &message)?;
+                out.push(format!("scoreboard players set {} 1", score(RAISED)));
+                out.push("return 0".to_string());
+                Ok(false)
+            }
+            Statement::Try(body, name, handler) => {
+                let (id, returns) = self.compile_sub_function(ctx, "try", body, Vec::new())?;
+                out.push(format!("function {}", id));
+                if returns {
+                    out.push(format!("execute if score {} matches 1 run return 0", ctx.returned_flag()));
+                }
+                // Whatever the body raised is cleared, so it stops here instead of in the callers
+                let mut start = vec![format!("scoreboard players set {} 0", score(RAISED))];
+                ctx.scopes.push(HashMap::new());
+                if let Some(name) = name {
+                    let base = ctx.declare(name, FireType::String);
+                    start.push(format!("data modify storage {} {} set from storage {} {}.message", self.storage(), base, self.storage(), RAISED));
+                }
+                let caught = self.compile_sub_function(ctx, "catch", handler, start);
+                ctx.scopes.pop();
+                let (caught_id, caught_returns) = caught?;
+                self.call_sub_function(ctx, out, &format!("execute if score {} matches 1 run ", score(RAISED)), &caught_id, caught_returns);
+                Ok(returns || caught_returns)
+            }
+            Statement::Return(value, line, column) => {
+                let return_type = ctx.return_type.clone();
+                match value {
+                    Some(v) => {
+                        let (ty, value) = self.compile_expected(ctx, v, Some(&return_type), out)?;
+                        if !fits(&return_type, &ty) {
+                            return Err(CompileException::TypeError(format!("Expected to return {} but found {}", return_type, ty), v.line, v.column));
+                        }
+                        let base = format!("{}._return", ctx.holder);
+                        self.copy_into(out, &return_type, &base, &value)?;
+                    }
+                    None if return_type != FireType::Void => return Err(CompileException::TypeError(format!("Expected to return {}", return_type), *line, *column)),
+                    None => {}
+                }
+                if ctx.depth > 0 {
+                    ctx.nested_return = true;
+                    out.push(format!("scoreboard players set {} 1", ctx.returned_flag()));
+                }
+                out.push("return 0".to_string());
+                Ok(true)
+            }
+            Statement::If(condition, body, otherwise) => {
+                let holder = match self.compile_condition(ctx, condition, out)? {
+                    Value::Constant(c) => return self.compile_statements(ctx, if c != 0 { body } else { otherwise }, out),
+                    Value::Score(h) if !otherwise.is_empty() && !h.starts_with(&format!("${}._t", ctx.holder)) => {
+                        // The body could change the condition before the else checks it
+                        let t = score(&ctx.temp(&FireType::Boolean));
+                        out.push(format!("scoreboard players operation {} = {}", t, h));
+                        t
+                    }
+                    Value::Score(h) => h,
+                    _ => unreachable!()
+                };
+                let (id, returns) = self.compile_sub_function(ctx, "if", body, Vec::new())?;
+                self.call_sub_function(ctx, out, &format!("execute if score {} matches 1 run ", holder), &id, returns);
+                if otherwise.is_empty() {
+                    return Ok(returns);
+                }
+                let (id, else_returns) = self.compile_sub_function(ctx, "else", otherwise, Vec::new())?;
+                self.call_sub_function(ctx, out, &format!("execute unless score {} matches 1 run ", holder), &id, else_returns);
+                Ok(returns || else_returns)
+            }
+            Statement::While(condition, body) => {
+                let path = ctx.sub_path("while");
+                let id = self.function_id(&path);
+                let mut commands = Vec::new();
+                ctx.depth += 1;
+                let result = (|| {
+                    match self.compile_condition(ctx, condition, &mut commands)? {
+                        Value::Constant(0) => return Ok(None),
+                        Value::Constant(_) => {}
+                        Value::Score(h) => commands.push(format!("execute unless score {} matches 1 run return 0", h)),
+                        _ => unreachable!()
+                    }
+                    let returns = self.compile_statements(ctx, body, &mut commands)?;
+                    Ok(Some(returns))
+                })();
+                ctx.depth -= 1;
+                let Some(returns) = result? else { return Ok(false) };
+                commands.push(format!("function {}", id));
+                self.functions.insert(path, commands);
+                self.call_sub_function(ctx, out, "", &id, returns);
+                Ok(returns)
+            }
+            Statement::For(name, iterable, body) => {
+                // Looping over an enum goes through each of its constants, anything else has to be a List
+                let loc = match &iterable.kind {
+                    ExpressionKind::Resource(loc, rest) if rest.is_empty() && self.project.enums.contains_key(loc) => loc,
+                    _ if is_range(iterable) => return self.compile_for_range(ctx, name, iterable, body, out),
+                    _ => return self.compile_for_list(ctx, name, iterable, body, out)
+                };
+                let constants = self.project.enums[loc].constants.clone();
+                ctx.scopes.push(HashMap::new());
+                let base = ctx.declare(name, FireType::Enum(loc.clone()));
+                let result = self.compile_sub_function(ctx, "for", body, Vec::new());
+                ctx.scopes.pop();
+                let (id, returns) = result?;
+                for (_, value) in constants {
+                    out.push(format!("scoreboard players set {} {}", score(&base), value));
+                    self.call_sub_function(ctx, out, "", &id, returns);
+                }
+                Ok(returns)
+            }
+            Statement::Match(value, arms) => self.compile_match(ctx, value, arms, out)
+        }
+    }
+
+    // A condition has to be a Boolean, and is either known now or in a score
+    fn compile_condition(&mut self, ctx: &mut FunctionContext, condition: &Expression, out: &mut CompiledCommands) -> Result<Value, CompileException> {
+        let (ty, value) = self.compile_expression(ctx, condition, out)?;
+        if ty != FireType::Boolean {
+            return Err(CompileException::TypeError(format!("Expected Boolean but found {}", ty), condition.line, condition.column));
+        }
+        Ok(value)
+    }
+
+    fn compile_match(&mut self, ctx: &mut FunctionContext, value: &Expression, arms: &[MatchArm], out: &mut CompiledCommands) -> Result<bool, CompileException> {
+        let (ty, matched) = self.compile_expression(ctx, value, out)?;
+        if !matches!(ty, FireType::Int | FireType::Enum(_)) {
+            return Err(CompileException::TypeError(format!("Cannot match on {}", ty), value.line, value.column));
+        }
+        // Every pattern has to be known now, so that the arms can be sorted
+        let mut cases: Vec<(i64, i64, usize)> = Vec::new();
+        let mut otherwise = None;
+        for (i, arm) in arms.iter().enumerate() {
+            if arm.patterns.is_empty() {
+                otherwise = Some(i);
+            }
+            for p in &arm.patterns {
+                let (low, high) = self.pattern_bounds(ctx, p, &ty)?;
+                if low > high {
+                    return Err(CompileException::TypeError("This range is empty".to_string(), p.line, p.column));
+                }
+                if cases.iter().any(|(min, max, _)| *min <= high && low <= *max) {
+                    return Err(CompileException::TypeError("This pattern is already matched by another arm".to_string(), p.line, p.column));
+                }
+                cases.push((low, high, i));
+            }
+        }
+        cases.sort_by_key(|(min, _, _)| *min);
+        if let Value::Constant(c) = matched { // Nothing to check at runtime
+            let arm = cases.iter().find(|(min, max, _)| *min <= c && c <= *max).map(|(_, _, i)| *i).or(otherwise);
+            return match arm {
+                Some(i) => self.compile_statements(ctx, &arms[i].body, out),
+                None => Ok(false)
+            };
+        }
+        let Value::Score(mut holder) = matched else { unreachable!() };
+        if !holder.starts_with(&format!("${}._t", ctx.holder)) { // An arm could change the value before the next one is checked
+            let t = score(&ctx.temp(&FireType::Int));
+            out.push(format!("scoreboard players operation {} = {}", t, holder));
+            holder = t;
+        }
+        let mut ids = Vec::new();
+        let mut returns = false;
+        for arm in arms {
+            let (id, r) = self.compile_sub_function(ctx, "arm", &arm.body, Vec::new())?;
+            returns |= r;
+            ids.push(format!("function {}", id));
+        }
+        let cases: Vec<(i64, i64, String)> = cases.into_iter().map(|(min, max, i)| (min, max, ids[i].clone())).collect();
+        self.compile_dispatch(ctx, &holder, &cases, otherwise.map(|i| ids[i].as_str()), out);
+        if returns {
+            out.push(format!("execute if score {} matches 1 run return 0", ctx.returned_flag()));
+        }
+        self.raise_check(out);
+        Ok(returns)
+    }
+
+    // The values a match pattern covers, which is a single constant or a range of them
+    fn pattern_bounds(&mut self, ctx: &mut FunctionContext, pattern: &Expression, ty: &FireType) -> Result<(i64, i64), CompileException> {
+        let (ends, inclusive) = match &pattern.kind {
+            ExpressionKind::Range(start, end, inclusive) => (vec![&**start, &**end], *inclusive),
+            _ => (vec![pattern], true)
+        };
+        let mut bounds = Vec::with_capacity(2);
+        for p in ends {
+            let (pattern_type, value) = self.compile_expression(ctx, p, &mut Vec::new())?;
+            let Value::Constant(c) = value else {
+                return Err(CompileException::TypeError("Match patterns must be constants".to_string(), p.line, p.column));
+            };
+            if pattern_type != *ty {
+                return Err(CompileException::TypeError(format!("Expected {} but found {}", ty, pattern_type), p.line, p.column));
+            }
+            bounds.push(c);
+        }
+        Ok((bounds[0], bounds[bounds.len() - 1] - !inclusive as i64))
+    }
+
+    // Runs the command of whichever case a score falls inside of, or otherwise if there are none.
+    // The cases must be sorted. They are split in half by generated functions, so only O(log n) checks run.
+    fn compile_dispatch(&mut self, ctx: &mut FunctionContext, holder: &str, cases: &[(i64, i64, String)], otherwise: Option<&str>, out: &mut CompiledCommands) {
+        if cases.len() <= 2 {
+            for (min, max, command) in cases {
+                out.push(format!("execute if score {} matches {} run {}", holder, range(*min, *max), command));
+            }
+            if let Some(command) = otherwise {
+                let checks: String = cases.iter().map(|(min, max, _)| format!("unless score {} matches {} ", holder, range(*min, *max))).collect();
+                out.push(if checks.is_empty() { command.to_string() } else { format!("execute {}run {}", checks, command) });
+            }
+            return;
+        }
+        let middle = cases.len() / 2;
+        let split = cases[middle].0;
+        for (half, matches) in [(&cases[..middle], format!("..{}", split - 1)), (&cases[middle..], format!("{}..", split))] {
+            let path = ctx.sub_path("node");
+            let mut commands = Vec::new();
+            self.compile_dispatch(ctx, holder, half, otherwise, &mut commands);
+            self.functions.insert(path.clone(), commands);
+            out.push(format!("execute if score {} matches {} run function {}", holder, matches, self.function_id(&path)));
+        }
+    }
+
+    // Finds where an assignable expression is stored
+    fn place_of(&mut self, ctx: &mut FunctionContext, expr: &Expression) -> Result<(FireType, Value), CompileException> {
+        let local = |name: &str| match ctx.lookup(name) {
+            Some((ty, base)) => Ok((ty.clone(), place(ty, base))),
+            None => Err(CompileException::UnknownName(name.to_string(), expr.line, expr.column))
+        };
+        match &expr.kind {
+            ExpressionKind::Variable(name) => local(name),
+            ExpressionKind::SelfValue => local("self"),
+            ExpressionKind::Field(inner, field) => {
+                let (ty, value) = self.place_of(ctx, inner)?;
+                let found = self.field_of(&ty, value, field, expr)?;
+                if let FireType::Struct(loc) = &ty {
+                    self.used(loc.with(field.clone()), expr);
+                }
+                Ok(found)
+            }
+            ExpressionKind::Resource(loc, rest) if rest.is_empty() && self.project.globals.contains_key(loc) => {
+                if self.project.globals[loc].constant {
+                    return Err(CompileException::TypeError(format!("Cannot change the const {}", loc), expr.line, expr.column));
+                }
+                Ok(self.globals[loc].clone())
+            }
+            _ => Err(CompileException::TypeError("Cannot assign to this".to_string(), expr.line, expr.column))
+        }
+    }
+
+    // A field of a struct, or a player's value of a @PerPlayer global
+    fn field_of(&self, ty: &FireType, value: Value, field: &str, at: &Expression) -> Result<(FireType, Value), CompileException> {
+        if let FireType::Event(_) = ty {
+            return match field {
+                "player" | "default" => Ok((FireType::Player, value)),
+                _ => Err(CompileException::TypeError(format!("{} has no field {}", ty, field), at.line, at.column))
+            };
+        }
+        if let Value::Entity(selector) = value {
+            let mut found: Vec<&FireLocation> = self.project.globals.values().filter(|g| g.per_player && g.loc.last().is_some_and(|l| l == field)).map(|g| &g.loc).collect();
+            found.sort_by_key(|l| l.to_string());
+            return match found[..] {
+                [loc] => match self.globals.get(loc) {
+                    Some((ty, _)) => Ok((ty.clone(), Value::Score(format!("{} {}", selector, holder_name(loc))))),
+                    None => Err(CompileException::TypeError(format!("{} is used before it is set up", loc), at.line, at.column))
+                }
+                [] => Err(CompileException::TypeError(format!("Player has no @PerPlayer value {}", field), at.line, at.column)),
+                _ => Err(CompileException::TypeError(format!("{} could be any of {}", field, found.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", ")), at.line, at.column))
+            };
+        }
+        let field_type = self.field_type(ty, field, at)?;
+        let Value::Struct(base) = value else { unreachable!() };
+        Ok((field_type.clone(), place(&field_type, &format!("{}.{}", base, field))))
+    }
+
+    fn field_type(&self, ty: &FireType, field: &str, at: &Expression) -> Result<FireType, CompileException> {
+        if let FireType::Struct(loc) = ty {
+            if let Some((_, t)) = self.project.structs[loc].fields.iter().find(|(n, _)| n == field) {
+                return Ok(t.clone());
+            }
+        }
+        Err(CompileException::TypeError(format!("{} has no field {}", ty, field), at.line, at.column))
+    }
+
+    // Copies a value into wherever a variable of the given type is stored
+    fn copy_into(&mut self, out: &mut CompiledCommands, ty: &FireType, base: &str, value: &Value) -> Result<(), CompileException> {
+        self.store(out, ty, &place(ty, base), value)
+    }
+
+    // Copies a value into a place that can hold it
+    fn store(&mut self, out: &mut CompiledCommands, ty: &FireType, to: &Value, value: &Value) -> Result<(), CompileException> {
+        let storage = self.storage();
+        match (ty, to, value) {
+            (FireType::Struct(loc), Value::Struct(base), Value::Struct(from)) if from != base => {
+                for (field, field_type) in self.project.structs[loc].fields.clone() {
+                    self.copy_into(out, &field_type, &format!("{}.{}", base, field), &place(&field_type, &format!("{}.{}", from, field)))?;
+                }
+            }
+            (_, Value::Storage(base), Value::ConstString(s)) => out.push(format!("data modify storage {} {} set value {}", storage, base, quote(s))),
+            (_, Value::Storage(base), Value::Storage(from)) if from != base => out.push(format!("data modify storage {} {} set from storage {} {}", storage, base, storage, from)),
+            (_, Value::Score(holder), Value::Constant(c)) => out.push(format!("scoreboard players set {} {}", holder, c)),
+            (_, Value::Score(holder), Value::Score(from)) if from != holder => out.push(format!("scoreboard players operation {} = {}", holder, from)),
+            (_, Value::Entity(to), Value::Entity(from)) if from != to => {
+                let tag = to.trim_start_matches("@e[tag=").trim_end_matches(",limit=1]");
+                out.push(format!("tag @e remove {}", tag));
+                out.push(format!("tag {} add {}", from, tag));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Makes sure a value is in a score, so that it can be used in scoreboard operations
+    fn score_of(&mut self, ctx: &mut FunctionContext, value: &Value, out: &mut CompiledCommands) -> String {
+        match value {
+            Value::Score(h) => h.clone(),
+            Value::Constant(c) => {
+                let t = score(&ctx.temp(&FireType::Int));
+                out.push(format!("scoreboard players set {} {}", t, c));
+                t
+            }
+            _ => unreachable!()
+        }
+    }
+
+    fn compile_expression(&mut self, ctx: &mut FunctionContext, expr: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        match &expr.kind {
+            ExpressionKind::Int(n) => Ok((FireType::Int, Value::Constant(*n))),
+            ExpressionKind::String(s) => Ok((FireType::String, Value::ConstString(s.clone()))),
+            ExpressionKind::Boolean(b) => Ok((FireType::Boolean, Value::Constant(*b as i64))),
+            ExpressionKind::Num => Err(CompileException::Unsupported("Num at runtime".to_string(), expr.line, expr.column)),
+            ExpressionKind::Variable(_) | ExpressionKind::SelfValue => {
+                self.place_of(ctx, expr)
+            }
+            ExpressionKind::Field(inner, field) => {
+                let (ty, value) = self.compile_expression(ctx, inner, out)?;
+                let found = self.field_of(&ty, value, field, expr)?;
+                if let FireType::Struct(loc) = &ty {
+                    self.used(loc.with(field.clone()), expr);
+                }
+                Ok(found)
+            }
+            ExpressionKind::Resource(loc, rest) if rest.is_empty() && self.globals.contains_key(loc) => Ok(self.globals[loc].clone()),
+            ExpressionKind::Resource(loc, rest) => {
+                let parent = loc.parent();
+                if let (Some(e), true) = (self.project.enums.get(&parent), rest.is_empty()) {
+                    if let Some((_, v)) = e.constants.iter().find(|(n, _)| Some(n) == loc.last()) {
+                        return Ok((FireType::Enum(parent), Value::Constant(*v)));
+                    }
+                }
+                Err(CompileException::TypeError(format!("{} is not a value", loc), expr.line, expr.column))
+            }
+            ExpressionKind::Builtin(name) => Err(CompileException::UnknownName(name.clone(), expr.line, expr.column)),
+            ExpressionKind::StructLiteral(loc, values) => {
+                let base = ctx.temp(&FireType::Struct(loc.clone()));
+                let fields = self.project.structs[loc].fields.clone();
+                for (name, v) in values {
+                    if !fields.iter().any(|(n, _)| n == name) {
+                        return Err(CompileException::TypeError(format!("{} has no field {}", loc, name), v.line, v.column));
+                    }
+                }
+                for (name, ty) in fields {
+                    let Some((_, v)) = values.iter().find(|(n, _)| *n == name) else {
+                        return Err(CompileException::TypeError(format!("Missing field {}", name), expr.line, expr.column));
+                    };
+                    let (value_type, value) = self.compile_expected(ctx, v, Some(&ty), out)?;
+                    if !fits(&ty, &value_type) {
+                        return Err(CompileException::TypeError(format!("Expected {} but found {}", ty, value_type), v.line, v.column));
+                    }
+                    self.copy_into(out, &ty, &format!("{}.{}", base, name), &value)?;
+                }
+                Ok((FireType::Struct(loc.clone()), Value::Struct(base)))
+            }
+            ExpressionKind::Call(callee, args) => self.compile_call(ctx, callee, args, expr, out),
+            ExpressionKind::Lambda(params, body) => self.compile_lambda(ctx, params, body, None, expr, out),
+            ExpressionKind::Interpolation(parts) => {
+                // Anything only known once the datapack runs can only be displayed, which text_component does
+                let mut text = String::new();
+                for part in parts {
+                    let (ty, value) = self.compile_expression(ctx, part, out)?;
+                    text += &self.constant_text(&ty, value, "Joining Strings outside of a message", part)?;
+                }
+                Ok((FireType::String, Value::ConstString(text)))
+            }
+            ExpressionKind::Range(..) => Err(CompileException::TypeError("A range can only be looped over, matched on or checked with in".to_string(), expr.line, expr.column)),
+            ExpressionKind::List(items) => self.compile_list(ctx, items, out),
+            ExpressionKind::Map(entries) => self.compile_map(ctx, entries, expr, out),
+            ExpressionKind::Index(collection, key) => {
+                let (ty, base) = self.collection(ctx, collection, out)?;
+                let (element_type, element) = self.element(ctx, &ty, &base, key, out)?;
+                let value = self.read_element(ctx, out, &element_type, &element, expr)?;
+                Ok((element_type, value))
+            }
+            ExpressionKind::Binary(operator, left, right) => self.compile_binary(ctx, *operator, left, right, out),
+            ExpressionKind::Unary(operator, inner) => {
+                let (ty, value) = self.compile_expression(ctx, inner, out)?;
+                match (operator, &ty, value) {
+                    (UnaryOperator::Negate, FireType::Int, Value::Constant(c)) => Ok((ty, fold((c as i32).checked_neg(), expr, || format!("-({})", c))?)),
+                    (UnaryOperator::Negate, FireType::Int, Value::Score(h)) => {
+                        let t = score(&ctx.temp(&FireType::Int));
+                        out.push(format!("scoreboard players set {} 0", t));
+                        out.push(format!("scoreboard players operation {} -= {}", t, h));
+                        Ok((ty, Value::Score(t)))
+                    }
+                    (UnaryOperator::Not, FireType::Boolean, Value::Constant(c)) => Ok((ty, Value::Constant(1 - c))),
+                    (UnaryOperator::Not, FireType::Boolean, Value::Score(h)) => {
+                        let t = score(&ctx.temp(&FireType::Boolean));
+                        out.push(format!("execute store result score {} if score {} matches 0", t, h));
+                        Ok((ty, Value::Score(t)))
+                    }
+                    _ => Err(CompileException::TypeError(format!("Cannot use {:?} on {}", operator, ty), expr.line, expr.column))
+                }
+            }
+        }
+    }
+
+    fn compile_call(&mut self, ctx: &mut FunctionContext, callee: &Expression, args: &[Expression], at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        match &callee.kind {
+            ExpressionKind::Resource(loc, rest) if rest.is_empty() && self.project.functions.contains_key(loc) => {
+                self.call_function(ctx, loc, None, args, at, out)
+            }
+            ExpressionKind::Resource(loc, rest) if rest.len() == 1 && self.project.enums.contains_key(loc) => {
+                let ty = FireType::Enum(loc.clone());
+                match (rest[0].as_str(), args) {
+                    ("fromInt", [arg]) => {
+                        let (arg_type, value) = self.compile_expression(ctx, arg, out)?;
+                        if arg_type != FireType::Int {
+                            return Err(CompileException::TypeError(format!("Expected Int but found {}", arg_type), arg.line, arg.column));
+                        }
+                        Ok((ty, value))
+                    }
+                    ("fromString", [arg]) => {
+                        let (arg_type, value) = self.compile_expression(ctx, arg, out)?;
+                        if arg_type != FireType::String {
+                            return Err(CompileException::TypeError(format!("Expected String but found {}", arg_type), arg.line, arg.column));
+                        }
+                        self.enum_helpers(loc);
+                        let holder = holder_name(loc);
+                        self.copy_into(out, &FireType::String, &format!("{}._arg", holder), &value)?;
+                        out.push(format!("function {}", self.function_id(&format!("{}/_from_string", function_path(loc)))));
+                        let t = ctx.temp(&ty);
+                        self.copy_into(out, &ty, &t, &Value::Score(score(&format!("{}._value", holder))))?;
+                        Ok((ty, Value::Score(score(&t))))
+                    }
+                    _ => Err(CompileException::UnknownName(format!("{}::{}", loc, rest[0]), callee.line, callee.column))
+                }
+            }
+            ExpressionKind::Field(object, name) => {
+                let (ty, value) = self.compile_expression(ctx, object, out)?;
+                match (&ty, name.as_str(), args) {
+                    (FireType::Enum(_), "toInt", []) => Ok((FireType::Int, value)),
+                    (FireType::Enum(loc), "toString", []) => {
+                        let loc = loc.clone();
+                        Ok((FireType::String, self.enum_to_string(ctx, &loc, value, out)?))
+                    }
+                    (FireType::String, _, _) => self.compile_string_method(ctx, value, name, args, at, out),
+                    (FireType::List(_) | FireType::Map(..), _, _) => {
+                        let Value::Storage(base) = value else { unreachable!() };
+                        self.compile_collection_method(ctx, (ty.clone(), base), name, args, at, out)
+                    }
+                    (FireType::Struct(loc), _, _) if matches!(self.field_type(&ty, name, callee), Ok(FireType::Function(..))) => {
+                        let loc = loc.clone();
+                        self.used(loc.with(name.clone()), callee);
+                        let (field_type, field) = self.field_of(&FireType::Struct(loc), value, name, callee)?;
+                        self.call_value(ctx, &field_type, &field, args, at, out)
+                    }
+                    (FireType::Struct(loc) | FireType::Enum(loc), _, _) => {
+                        let method = self.find_method(loc, name).ok_or_else(|| CompileException::UnknownName(format!("{}.{}", ty, name), callee.line, callee.column))?;
+                        self.used(method.clone(), callee);
+                        self.call_function(ctx, &method, Some((ty, value)), args, at, out)
+                    }
+                    _ => Err(CompileException::UnknownName(format!("{}.{}", ty, name), callee.line, callee.column))
+                }
+            }
+            ExpressionKind::Builtin(name) => self.compile_builtin(ctx, name, args, at, out),
+            _ => {
+                let (ty, value) = self.compile_expression(ctx, callee, out)?;
+                if !matches!(ty, FireType::Function(..)) {
+                    return Err(CompileException::TypeError(format!("{} cannot be called", ty), callee.line, callee.column));
+                }
+                self.call_value(ctx, &ty, &value, args, at, out)
+            }
+        }
+    }
+
+    // Finds a method on a type, either declared in it or in one of its impls
+    fn find_method(&self, type_loc: &FireLocation, name: &str) -> Option<FireLocation> {
+        let direct = type_loc.with(name.to_string());
+        if self.project.functions.contains_key(&direct) {
+            return Some(direct);
+        }
+        self.project.implementations.get(type_loc)?.iter().map(|i| i.with(name.to_string())).find(|l| self.project.functions.contains_key(l))
+    }
+
+    fn call_function(&mut self, ctx: &mut FunctionContext, loc: &FireLocation, self_value: Option<(FireType, Value)>, args: &[Expression], at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let f = &self.project.functions[loc];
+        let params = f.params.clone();
+        let return_type = f.return_type.clone();
+        let mut values = Vec::with_capacity(params.len());
+        values.extend(self_value);
+        for a in args {
+            let expected = params.get(values.len()).map(|(_, t)| t);
+            values.push(self.compile_expected(ctx, a, expected, out)?);
+        }
+        if values.len() != params.len() {
+            return Err(CompileException::TypeError(format!("{} takes {} arguments but was given {}", loc, params.len(), values.len()), at.line, at.column));
+        }
+        let holder = holder_name(loc);
+        for ((name, ty), (value_type, _)) in params.iter().zip(values.iter()) {
+            if !fits(ty, value_type) {
+                return Err(CompileException::TypeError(format!("Expected {} for {} but found {}", ty, name, value_type), at.line, at.column));
+            }
+        }
+        let mut restore = Vec::new();
+        if ctx.function.as_ref().is_some_and(|f| self.reaches[loc].contains(f)) {
+            restore = self.save_locals(ctx, out)?;
+            if ctx.holder == holder {
+                // Parameters are about to be overwritten, so arguments that read them are copied out first
+                let temps = ctx.temps.len();
+                for ((_, ty), (_, value)) in params.iter().zip(values.iter_mut()) {
+                    if !matches!(value, Value::Constant(_) | Value::ConstString(_)) {
+                        let t = ctx.temp(ty);
+                        self.copy_into(out, ty, &t, value)?;
+                        *value = place(ty, &t);
+                    }
+                }
+                ctx.temps.truncate(temps);
+            }
+        }
+        for ((name, ty), (_, value)) in params.iter().zip(values.iter()) {
+            self.copy_into(out, ty, &format!("{}.{}", holder, name), value)?;
+        }
+        out.push(format!("function {}", self.function_id(&function_path(loc))));
+        out.append(&mut restore);
+        self.raise_check(out);
+        if return_type == FireType::Void {
+            return Ok((FireType::Void, Value::Void));
+        }
+        // Copied out in case the function is called again before this value is used
+        let t = ctx.temp(&return_type);
+        self.copy_into(out, &return_type, &t, &place(&return_type, &format!("{}._return", holder)))?;
+        Ok((return_type.clone(), place(&return_type, &t)))
+    }
+
+    // Everything is stored in the same place each time a function runs, so a call that can come back around to this
+    // function would overwrite its locals. Those calls push them onto a stack in storage, and pop them off once it's done.
+    // Gives the commands that pop them back off.
+    fn save_locals(&mut self, ctx: &FunctionContext, out: &mut CompiledCommands) -> Result<CompiledCommands, CompileException> {
+        let mut locals: Vec<(FireType, String)> = ctx.scopes.iter().flat_map(|s| s.values().cloned()).chain(ctx.temps.iter().cloned()).collect();
+        locals.sort_by(|a, b| a.1.cmp(&b.1));
+        locals.dedup_by(|a, b| a.1 == b.1);
+        let mut restore = Vec::new();
+        out.push(format!("data modify storage {} _stack append value {{}}", self.storage()));
+        for (ty, base) in &locals {
+            self.save_local(ty, base, "_stack[-1]", out, &mut restore)?;
+        }
+        restore.push(format!("data remove storage {} _stack[-1]", self.storage()));
+        // The call may have returned from this same function, which isn't returning itself
+        restore.push(format!("scoreboard players set {} 0", ctx.returned_flag()));
+        Ok(restore)
+    }
+
+    // Compiles an expression that's about to be used as some type. Lambdas take the types of their parameters from it.
+    fn compile_expected(&mut self, ctx: &mut FunctionContext, expr: &Expression, expected: Option<&FireType>, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        match &expr.kind {
+            ExpressionKind::Lambda(params, body) => self.compile_lambda(ctx, params, body, expected, expr, out),
+            _ => self.compile_expression(ctx, expr, out)
+        }
+    }
+
+    // Compiles a lambda into a function of its own. Its value is a compound in storage with the function to run
+    // and a copy of every local it uses, which the function reads back out of _closure when it's called.
+    fn compile_lambda(&mut self, ctx: &mut FunctionContext, params: &[(String, Option<FireType>)], body: &[Statement], expected: Option<&FireType>, at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let storage = self.storage();
+        let (expected_params, expected_return) = match expected {
+            Some(FireType::Function(p, r)) if p.len() == params.len() => (Some(p), Some((**r).clone())),
+            Some(FireType::Function(p, _)) => return Err(CompileException::TypeError(format!("Expected a lambda taking {} arguments but this one takes {}", p.len(), params.len()), at.line, at.column)),
+            _ => (None, None)
+        };
+        let mut param_types = Vec::with_capacity(params.len());
+        for (i, (name, ty)) in params.iter().enumerate() {
+            param_types.push(match (ty, expected_params.map(|p| &p[i])) {
+                (Some(ty), Some(e)) if ty != e => return Err(CompileException::TypeError(format!("Expected {} for {} but found {}", e, name, ty), at.line, at.column)),
+                (Some(ty), _) | (None, Some(ty)) => ty.clone(),
+                (None, None) => return Err(CompileException::TypeError(format!("The type of {} has to be written, such as ({}: Int) =>", name, name), at.line, at.column))
+            });
+        }
+        let path = ctx.sub_path("lambda");
+        let holder = format!("{}.{}", ctx.holder, path.rsplit('/').next().unwrap());
+        // Locals from outside of the lambda are copied in when it's made
+        let mut captures = Vec::new();
+        walk_statements(body, &mut |e| {
+            let name = match &e.kind {
+                ExpressionKind::Variable(name) => name.as_str(),
+                ExpressionKind::SelfValue => "self",
+                _ => return
+            };
+            if let Some((ty, base)) = ctx.lookup(name) {
+                if !params.iter().any(|(p, _)| p == name) && !captures.iter().any(|(n, _, _)| n == name) {
+                    captures.push((name.to_string(), ty.clone(), base.clone()));
+                }
+            }
+        });
+        captures.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut inner = FunctionContext::new(path.clone(), holder.clone(), expected_return.clone().unwrap_or(FireType::Void));
+        inner.function = ctx.function.clone();
+        let mut commands = Vec::new();
+        for (i, (name, ty)) in params.iter().map(|(n, _)| n).zip(&param_types).enumerate() {
+            let base = format!("{}.{}", holder, name);
+            self.copy_into(&mut commands, ty, &base, &place(ty, &format!("_lambda.p{}", i)))?;
+            inner.scopes[0].insert(name.clone(), (ty.clone(), base));
+        }
+        for (name, ty, _) in &captures {
+            if matches!(ty, FireType::Player | FireType::Event(_)) {
+                return Err(CompileException::Unsupported(format!("Using a {} from outside of a lambda", ty), at.line, at.column));
+            }
+            let base = format!("{}.{}", holder, name);
+            self.read_nbt(&mut commands, ty, &format!("_closure.{}", name), &base, at)?;
+            inner.scopes[0].insert(name.clone(), (ty.clone(), base));
+        }
+        let return_type = match body {
+            // (x) => x + 1 is copied straight into the result
+            [Statement::Return(Some(e), ..)] => {
+                let (ty, value) = self.compile_expected(&mut inner, e, expected_return.as_ref(), &mut commands)?;
+                let return_type = match expected_return {
+                    Some(FireType::Void) => FireType::Void, // Whatever it gives back is left unused
+                    Some(r) if !fits(&r, &ty) => return Err(CompileException::TypeError(format!("Expected to return {} but found {}", r, ty), e.line, e.column)),
+                    Some(r) => r,
+                    None => ty
+                };
+                self.copy_into(&mut commands, &return_type, "_lambda._return", &value)?;
+                return_type
+            }
+            _ => {
+                let mut statements = Vec::new();
+                self.compile_statements(&mut inner, body, &mut statements)?;
+                if inner.nested_return {
+                    statements.insert(0, format!("scoreboard players set {} 0", inner.returned_flag()));
+                }
+                if inner.return_type == FireType::Void {
+                    commands.append(&mut statements);
+                } else {
+                    // A return stops the body, so copying the result out happens after it
+                    let body_path = format!("{}/_body", path);
+                    self.functions.insert(body_path.clone(), statements);
+                    commands.push(format!("function {}", self.function_id(&body_path)));
+                    let return_type = inner.return_type.clone();
+                    self.copy_into(&mut commands, &return_type, "_lambda._return", &place(&return_type, &format!("{}._return", holder)))?;
+                }
+                inner.return_type
+            }
+        };
+        self.functions.insert(path.clone(), commands);
+        let ty = FireType::Function(param_types, Box::new(return_type));
+        let t = ctx.temp(&ty);
+        out.push(format!("data modify storage {} {} set value {{fn:{}}}", storage, t, quote(&self.function_id(&path))));
+        for (name, captured_type, base) in &captures {
+            self.write_nbt(out, captured_type, &format!("{}.{}", t, name), &place(captured_type, base), at)?;
+        }
+        Ok((ty, Value::Storage(t)))
+    }
+
+    // Calls a lambda stored in a value
+    fn call_value(&mut self, ctx: &mut FunctionContext, ty: &FireType, closure: &Value, args: &[Expression], at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let FireType::Function(params, _) = ty else { unreachable!() };
+        let mut values = Vec::with_capacity(args.len());
+        for (i, a) in args.iter().enumerate() {
+            values.push(self.compile_expected(ctx, a, params.get(i), out)?);
+        }
+        self.call_closure(ctx, ty, closure, values, at, out)
+    }
+
+    fn call_closure(&mut self, ctx: &mut FunctionContext, ty: &FireType, closure: &Value, values: Vec<(FireType, Value)>, at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let storage = self.storage();
+        let FireType::Function(params, return_type) = ty else { unreachable!() };
+        if values.len() != params.len() {
+            return Err(CompileException::TypeError(format!("This lambda takes {} arguments but was given {}", params.len(), values.len()), at.line, at.column));
+        }
+        // Every argument is worked out before any are copied in, since working one out could call another lambda
+        for (i, (param, (value_type, value))) in params.iter().zip(values.iter()).enumerate() {
+            if !fits(param, value_type) {
+                return Err(CompileException::TypeError(format!("Expected {} but found {}", param, value_type), at.line, at.column));
+            }
+            self.copy_into(out, param, &format!("_lambda.p{}", i), value)?;
+        }
+        // Which lambda this is isn't known until it runs, so any that could lead back to this function save its locals
+        let mut restore = Vec::new();
+        if ctx.function.as_ref().is_some_and(|f| self.reaches[f].contains(f)) {
+            restore = self.save_locals(ctx, out)?;
+        }
+        let Value::Storage(closure) = closure else { unreachable!() };
+        out.push(format!("data modify storage {} _closure set from storage {} {}", storage, storage, closure));
+        out.push(self.run_closure());
+        out.append(&mut restore);
+        self.raise_check(out);
+        if **return_type == FireType::Void {
+            return Ok((FireType::Void, Value::Void));
+        }
+        let t = ctx.temp(return_type);
+        self.copy_into(out, return_type, &t, &place(return_type, "_lambda._return"))?;
+        Ok(((**return_type).clone(), place(return_type, &t)))
+    }
+
+    // Works out where a range starts, stops and how far each step goes, along with any .step(n) and .reverse() on it
+    fn compile_range(&mut self, ctx: &mut FunctionContext, expr: &Expression, out: &mut CompiledCommands) -> Result<RangeLoop, CompileException> {
+        let (inner, name, args) = match &expr.kind {
+            ExpressionKind::Range(start, end, inclusive) => {
+                let (start_type, first) = self.compile_expression(ctx, start, out)?;
+                let (end_type, limit) = self.compile_expression(ctx, end, out)?;
+                if start_type != FireType::Int || end_type != FireType::Int {
+                    return Err(CompileException::TypeError(format!("Expected a range of Int but found {} to {}", start_type, end_type), expr.line, expr.column));
+                }
+                // The body could change whatever the end was read from
+                let limit = self.fixed(ctx, limit, out);
+                return Ok(RangeLoop { first, step: 1, limit, inclusive: *inclusive });
+            }
+            ExpressionKind::Call(callee, args) => match &callee.kind {
+                ExpressionKind::Field(inner, name) => (inner, name.as_str(), args),
+                _ => unreachable!()
+            },
+            _ => unreachable!()
+        };
+        let mut range = self.compile_range(ctx, inner, out)?;
+        match (name, &args[..]) {
+            ("step", [n]) => match self.compile_expression(ctx, n, out)? {
+                (FireType::Int, Value::Constant(c)) if c >= 1 => range.step *= c,
+                (FireType::Int, Value::Constant(_)) => return Err(CompileException::TypeError("A range's step must be at least 1".to_string(), n.line, n.column)),
+                (FireType::Int, _) => return Err(CompileException::NotConstant("Stepping a range".to_string(), n.line, n.column)),
+                (ty, _) => return Err(CompileException::TypeError(format!("Expected Int but found {}", ty), n.line, n.column))
+            },
+            ("reverse", []) => {
+                // The last value is first + (limit - first) / step * step, where the limit is the last value that would be let through
+                let sign = range.step.signum();
+                let last = match (&range.first, &range.limit) {
+                    (Value::Constant(f), Value::Constant(l)) => {
+                        let l = if range.inclusive { *l } else { l - sign };
+                        Value::Constant(f + floor_div(l - f, range.step) * range.step)
+                    }
+                    _ => {
+                        range.first = self.fixed(ctx, range.first, out);
+                        let t = ctx.temp(&FireType::Int);
+                        self.copy_into(out, &FireType::Int, &t, &range.limit)?;
+                        if !range.inclusive {
+                            out.push(format!("scoreboard players {} {} 1", if sign > 0 { "remove" } else { "add" }, score(&t)));
+                        }
+                        let first = self.score_of(ctx, &range.first, out);
+                        let step = self.constant(range.step);
+                        out.push(format!("scoreboard players operation {} -= {}", score(&t), first));
+                        out.push(format!("scoreboard players operation {} /= {} {}", score(&t), step, OBJECTIVE));
+                        out.push(format!("scoreboard players operation {} *= {} {}", score(&t), step, OBJECTIVE));
+                        out.push(format!("scoreboard players operation {} += {}", score(&t), first));
+                        Value::Score(score(&t))
+                    }
+                };
+                range = RangeLoop { first: last, step: -range.step, limit: range.first, inclusive: true };
+            }
+            _ => return Err(CompileException::UnknownName(format!("Range.{}", name), expr.line, expr.column))
+        }
+        Ok(range)
+    }
+
+    // Copies a score that something else could change into a temp
+    fn fixed(&mut self, ctx: &mut FunctionContext, value: Value, out: &mut CompiledCommands) -> Value {
+        match value {
+            Value::Score(h) if !h.starts_with(&format!("${}._t", ctx.holder)) => {
+                let t = score(&ctx.temp(&FireType::Int));
+                out.push(format!("scoreboard players operation {} = {}", t, h));
+                Value::Score(t)
+            }
+            value => value
+        }
+    }
+
+    // Every value of a range, if they're all known now and there aren't too many
+    fn unrolled(range: &RangeLoop) -> Option<Vec<i64>> {
+        let (Value::Constant(mut v), Value::Constant(limit)) = (&range.first, &range.limit) else { return None };
+        let mut values = Vec::new();
+        while match (range.step > 0, range.inclusive) {
+            (true, true) => v <= *limit,
+            (true, false) => v < *limit,
+            (false, true) => v >= *limit,
+            (false, false) => v > *limit
+        } {
+            if values.len() == UNROLL_LIMIT {
+                return None;
+            }
+            values.push(v);
+            v += range.step;
+        }
+        Some(values)
+    }
+
+    // Loops over a range. Small constant ones set the variable and call the body for each value, anything else counts up in a score.
+    fn compile_for_range(&mut self, ctx: &mut FunctionContext, name: &str, iterable: &Expression, body: &[Statement], out: &mut CompiledCommands) -> Result<bool, CompileException> {
+        let range = self.compile_range(ctx, iterable, out)?;
+        ctx.scopes.push(HashMap::new());
+        let base = ctx.declare(name, FireType::Int);
+        if let Some(values) = Compiler::unrolled(&range) {
+            let result = self.compile_sub_function(ctx, "for", body, Vec::new());
+            ctx.scopes.pop();
+            let (id, returns) = result?;
+            for v in values {
+                out.push(format!("scoreboard players set {} {}", score(&base), v));
+                self.call_sub_function(ctx, out, "", &id, returns);
+            }
+            return Ok(returns);
+        }
+        self.copy_into(out, &FireType::Int, &base, &range.first)?;
+        let path = ctx.sub_path("for");
+        let id = self.function_id(&path);
+        let counter = score(&base);
+        let check = match (&range.limit, range.step > 0) {
+            (Value::Constant(l), true) => format!("score {} matches ..{}", counter, if range.inclusive { *l } else { l - 1 }),
+            (Value::Constant(l), false) => format!("score {} matches {}..", counter, if range.inclusive { *l } else { l + 1 }),
+            (Value::Score(h), up) => format!("score {} {}{} {}", counter, if up { "<" } else { ">" }, if range.inclusive { "=" } else { "" }, h),
+            _ => unreachable!()
+        };
+        let mut commands = vec![format!("execute unless {} run return 0", check)];
+        ctx.depth += 1;
+        let result = self.compile_statements(ctx, body, &mut commands);
+        ctx.depth -= 1;
+        ctx.scopes.pop();
+        let returns = result?;
+        let (command, step) = if range.step > 0 { ("add", range.step) } else { ("remove", -range.step) };
+        commands.push(format!("scoreboard players {} {} {}", command, counter, step));
+        commands.push(format!("function {}", id));
+        self.functions.insert(path, commands);
+        self.call_sub_function(ctx, out, "", &id, returns);
+        Ok(returns)
+    }
+
+    // x in 0..10, which works on Ints and enums
+    fn compile_in(&mut self, ctx: &mut FunctionContext, left: &Expression, right: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let ExpressionKind::Range(start, end, inclusive) = &right.kind else {
+            return Err(CompileException::TypeError("Expected a range such as 0..10 after in".to_string(), right.line, right.column));
+        };
+        let (ty, value) = self.compile_expression(ctx, left, out)?;
+        let (start_type, low) = self.compile_expression(ctx, start, out)?;
+        let (end_type, high) = self.compile_expression(ctx, end, out)?;
+        if !matches!(ty, FireType::Int | FireType::Enum(_)) || start_type != ty || end_type != ty {
+            return Err(CompileException::TypeError(format!("Cannot check if {} is in a range of {} to {}", ty, start_type, end_type), left.line, left.column));
+        }
+        let high = match high {
+            Value::Constant(c) if !inclusive => Value::Constant(c - 1),
+            high => high
+        };
+        if let (Value::Constant(v), Value::Constant(l), Value::Constant(h)) = (&value, &low, &high) {
+            return Ok((FireType::Boolean, Value::Constant((l <= v && v <= h) as i64)));
+        }
+        let value = self.score_of(ctx, &value, out);
+        let t = score(&ctx.temp(&FireType::Boolean));
+        let condition = match (&low, &high) {
+            (Value::Constant(l), Value::Constant(h)) if l > h => {
+                out.push(format!("scoreboard players set {} 0", t));
+                return Ok((FireType::Boolean, Value::Score(t)));
+            }
+            (Value::Constant(l), Value::Constant(h)) => format!("if score {} matches {}", value, range(*l, *h)),
+            _ => {
+                let low = match &low {
+                    Value::Constant(l) => format!("if score {} matches {}..", value, l),
+                    Value::Score(l) => format!("if score {} >= {}", value, l),
+                    _ => unreachable!()
+                };
+                let high = match &high {
+                    Value::Constant(h) => format!("if score {} matches ..{}", value, h),
+                    Value::Score(h) => format!("if score {} {} {}", value, if *inclusive { "<=" } else { "<" }, h),
+                    _ => unreachable!()
+                };
+                format!("{} {}", low, high)
+            }
+        };
+        out.push(format!("execute store result score {} {}", t, condition));
+        Ok((FireType::Boolean, Value::Score(t)))
+    }
+
+    // Loops over a List, going through a copy of it so the body can change the List without changing what's looped over
+    fn compile_for_list(&mut self, ctx: &mut FunctionContext, name: &str, iterable: &Expression, body: &[Statement], out: &mut CompiledCommands) -> Result<bool, CompileException> {
+        let (ty, value) = self.compile_expression(ctx, iterable, out)?;
+        let FireType::List(element_type) = &ty else {
+            return Err(CompileException::TypeError(format!("{} cannot be looped over", ty), iterable.line, iterable.column));
+        };
+        let storage = self.storage();
+        let copy = ctx.temp(&ty);
+        self.copy_into(out, &ty, &copy, &value)?;
+        let path = ctx.sub_path("for");
+        let id = self.function_id(&path);
+        let mut commands = vec![format!("execute unless data storage {} {}[0] run return 0", storage, copy)];
+        ctx.scopes.push(HashMap::new());
+        ctx.depth += 1;
+        let base = ctx.declare(name, (**element_type).clone());
+        let result = (|| {
+            self.read_nbt(&mut commands, element_type, &format!("{}[0]", copy), &base, iterable)?;
+            commands.push(format!("data remove storage {} {}[0]", storage, copy));
+            self.compile_statements(ctx, body, &mut commands)
+        })();
+        ctx.depth -= 1;
+        ctx.scopes.pop();
+        let returns = result?;
+        commands.push(format!("function {}", id));
+        self.functions.insert(path, commands);
+        self.call_sub_function(ctx, out, "", &id, returns);
+        Ok(returns)
+    }
+
+    // Builds a List in a temp, all at once if every item is known now
+    fn compile_list(&mut self, ctx: &mut FunctionContext, items: &[Expression], out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let storage = self.storage();
+        let mut element_type = FireType::Void;
+        let mut values = Vec::with_capacity(items.len());
+        for item in items {
+            let (ty, value) = self.compile_expression(ctx, item, out)?;
+            if values.is_empty() {
+                element_type = ty;
+            } else if !fits(&element_type, &ty) {
+                return Err(CompileException::TypeError(format!("Expected {} but found {}", element_type, ty), item.line, item.column));
+            }
+            values.push(value);
+        }
+        let ty = FireType::List(Box::new(element_type.clone()));
+        let t = ctx.temp(&ty);
+        let known: Option<Vec<String>> = values.iter().map(|v| match v {
+            Value::Constant(c) => Some(c.to_string()),
+            Value::ConstString(s) => Some(quote(s)),
+            _ => None
+        }).collect();
+        match known {
+            Some(known) => out.push(format!("data modify storage {} {} set value [{}]", storage, t, known.join(", "))),
+            None => {
+                out.push(format!("data modify storage {} {} set value []", storage, t));
+                for (value, item) in values.iter().zip(items) {
+                    self.append(out, &element_type, &t, value, item)?;
+                }
+            }
+        }
+        Ok((ty, Value::Storage(t)))
+    }
+
+    // Builds a Map in a temp. Its types come from the first entry.
+    fn compile_map(&mut self, ctx: &mut FunctionContext, entries: &[(Expression, Expression)], at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let mut compiled = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let key = self.compile_expression(ctx, key, out)?;
+            compiled.push((key, self.compile_expression(ctx, value, out)?));
+        }
+        let (key_type, value_type) = match compiled.first() {
+            Some(((k, _), (v, _))) => (k.clone(), v.clone()),
+            None => (FireType::Void, FireType::Void)
+        };
+        if !matches!(key_type, FireType::Int | FireType::Boolean | FireType::String | FireType::Enum(_) | FireType::Void) {
+            return Err(CompileException::TypeError(format!("{} cannot be the key of a Map", key_type), at.line, at.column));
+        }
+        let ty = FireType::Map(Box::new(key_type), Box::new(value_type.clone()));
+        let t = ctx.temp(&ty);
+        out.push(format!("data modify storage {} {} set value {{}}", self.storage(), t));
+        for ((key, (found, value)), (key_expr, value_expr)) in compiled.into_iter().zip(entries) {
+            if !fits(&value_type, &found) {
+                return Err(CompileException::TypeError(format!("Expected {} but found {}", value_type, found), value_expr.line, value_expr.column));
+            }
+            let (_, element) = self.element_at(&ty, &t, key, key_expr, out)?;
+            self.write_element(out, &value_type, &element, &value, value_expr)?;
+        }
+        Ok((ty, Value::Storage(t)))
+    }
+
+    // Compiles an expression that has to be a List or Map, giving where it's stored
+    fn collection(&mut self, ctx: &mut FunctionContext, expr: &Expression, out: &mut CompiledCommands) -> Result<(FireType, String), CompileException> {
+        let (ty, value) = self.compile_expression(ctx, expr, out)?;
+        match (&ty, value) {
+            (FireType::List(_) | FireType::Map(..), Value::Storage(base)) => Ok((ty, base)),
+            _ => Err(CompileException::TypeError(format!("{} cannot be indexed", ty), expr.line, expr.column))
+        }
+    }
+
+    // Finds the element of a List or Map that a key refers to, and its type
+    fn element(&mut self, ctx: &mut FunctionContext, ty: &FireType, base: &str, key: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Element), CompileException> {
+        let value = self.compile_expression(ctx, key, out)?;
+        self.element_at(ty, base, value, key, out)
+    }
+
+    fn element_at(&mut self, ty: &FireType, base: &str, key: (FireType, Value), at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Element), CompileException> {
+        let (key_type, element_type, kind) = match ty {
+            FireType::List(e) => (FireType::Int, (**e).clone(), "list"),
+            FireType::Map(k, v) => ((**k).clone(), (**v).clone(), "map"),
+            _ => unreachable!()
+        };
+        if !fits(&key_type, &key.0) {
+            return Err(CompileException::TypeError(format!("Expected {} but found {}", key_type, key.0), at.line, at.column));
+        }
+        let element = match key.1 {
+            Value::Constant(c) if kind == "list" => Element::Path(format!("{}[{}]", base, c)),
+            Value::Constant(c) => Element::Path(format!("{}.\"{}\"", base, c)),
+            Value::ConstString(k) => Element::Path(format!("{}.{}", base, quote(&k))),
+            value => {
+                let storage = self.storage();
+                out.push(format!("data modify storage {} _args.{} set value {}", storage, kind, quote(base)));
+                let arg = if kind == "list" { "_args.index" } else { "_args.key" };
+                self.write_nbt(out, &key_type, arg, &value, at)?;
+                Element::Macro(kind)
+            }
+        };
+        Ok((element_type, element))
+    }
+
+    // Runs one of the macros that write a key only known at runtime into a path, making it the first time it's needed
+    fn run_macro(&mut self, kind: &str, operation: &str, out: &mut CompiledCommands) {
+        let storage = self.storage();
+        let path = format!("_{}/{}", kind, operation);
+        if !self.functions.contains_key(&path) {
+            let element = if kind == "list" { "$(list)[$(index)]" } else { "$(map).\"$(key)\"" };
+            let command = match operation {
+                "get" => format!("$data modify storage {} _args.value set from storage {} {}", storage, storage, element),
+                "set" => format!("$data modify storage {} {} set from storage {} _args.value", storage, element, storage),
+                "contains" => format!("$execute store result score {} if data storage {} {}", score("_args.found"), storage, element),
+                _ => format!("$data remove storage {} {}", storage, element)
+            };
+            self.functions.insert(path.clone(), vec![command]);
+        }
+        out.push(format!("function {} with storage {} _args", self.function_id(&path), storage));
+    }
+
+    // Copies an element out of a List or Map into a temp
+    fn read_element(&mut self, ctx: &mut FunctionContext, out: &mut CompiledCommands, ty: &FireType, element: &Element, at: &Expression) -> Result<Value, CompileException> {
+        let path = match element {
+            Element::Path(p) => p.clone(),
+            Element::Macro(kind) => {
+                // Cleared first, so that a missing element isn't read as whatever was there before
+                out.push(format!("data remove storage {} _args.value", self.storage()));
+                self.run_macro(kind, "get", out);
+                "_args.value".to_string()
+            }
+        };
+        let t = ctx.temp(ty);
+        self.read_nbt(out, ty, &path, &t, at)?;
+        Ok(place(ty, &t))
+    }
+
+    fn write_element(&mut self, out: &mut CompiledCommands, ty: &FireType, element: &Element, value: &Value, at: &Expression) -> Result<(), CompileException> {
+        match element {
+            Element::Path(p) => self.write_nbt(out, ty, p, value, at),
+            Element::Macro(kind) => {
+                self.write_nbt(out, ty, "_args.value", value, at)?;
+                self.run_macro(kind, "set", out);
+                Ok(())
+            }
+        }
+    }
+
+    // Adds a value to the end of a List
+    fn append(&mut self, out: &mut CompiledCommands, ty: &FireType, base: &str, value: &Value, at: &Expression) -> Result<(), CompileException> {
+        let storage = self.storage();
+        let from = match value {
+            Value::Constant(c) => format!("value {}", c),
+            Value::ConstString(s) => format!("value {}", quote(s)),
+            Value::Storage(p) => format!("from storage {} {}", storage, p),
+            _ => {
+                self.write_nbt(out, ty, "_args.value", value, at)?;
+                format!("from storage {} _args.value", storage)
+            }
+        };
+        out.push(format!("data modify storage {} {} append {}", storage, base, from));
+        Ok(())
+    }
+
+    // Writes a value into storage the way it's kept inside of a List or Map. Structs become compounds.
+    fn write_nbt(&mut self, out: &mut CompiledCommands, ty: &FireType, path: &str, value: &Value, at: &Expression) -> Result<(), CompileException> {
+        let storage = self.storage();
+        match (ty, value) {
+            (FireType::Struct(loc), Value::Struct(from)) => {
+                out.push(format!("data modify storage {} {} set value {{}}", storage, path));
+                for (field, field_type) in self.project.structs[loc].fields.clone() {
+                    self.write_nbt(out, &field_type, &format!("{}.{}", path, field), &place(&field_type, &format!("{}.{}", from, field)), at)?;
+                }
+            }
+            (_, Value::Constant(c)) => out.push(format!("data modify storage {} {} set value {}", storage, path, c)),
+            (_, Value::Score(h)) => out.push(format!("execute store result storage {} {} int 1 run scoreboard players get {}", storage, path, h)),
+            (_, Value::ConstString(_) | Value::Storage(_)) => self.store(out, ty, &Value::Storage(path.to_string()), value)?,
+            _ => return Err(CompileException::Unsupported(format!("Keeping a {} in a List or Map", ty), at.line, at.column))
+        }
+        Ok(())
+    }
+
+    // Reads a value written by write_nbt back into wherever a variable of its type is stored
+    fn read_nbt(&mut self, out: &mut CompiledCommands, ty: &FireType, path: &str, base: &str, at: &Expression) -> Result<(), CompileException> {
+        match ty {
+            FireType::Struct(loc) => {
+                for (field, field_type) in self.project.structs[loc].fields.clone() {
+                    self.read_nbt(out, &field_type, &format!("{}.{}", path, field), &format!("{}.{}", base, field), at)?;
+                }
+            }
+            FireType::String | FireType::List(_) | FireType::Map(..) | FireType::Function(..) => self.copy_into(out, ty, base, &Value::Storage(path.to_string()))?,
+            FireType::Player | FireType::Event(_) | FireType::Void => return Err(CompileException::Unsupported(format!("Keeping a {} in a List or Map", ty), at.line, at.column)),
+            _ => out.push(format!("execute store result score {} run data get storage {} {}", score(base), self.storage(), path))
+        }
+        Ok(())
+    }
+
+    // The methods every List and Map has, which are the ones of Accessible and Container along with a few for Lists
+    fn compile_collection_method(&mut self, ctx: &mut FunctionContext, collection: (FireType, String), name: &str, args: &[Expression], at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let storage = self.storage();
+        let (ty, base) = collection;
+        let list_element = match &ty {
+            FireType::List(e) => Some((**e).clone()),
+            _ => None
+        };
+        match (name, args, list_element) {
+            ("get", [key], _) => {
+                let (element_type, element) = self.element(ctx, &ty, &base, key, out)?;
+                let value = self.read_element(ctx, out, &element_type, &element, at)?;
+                Ok((element_type, value))
+            }
+            ("set", [key, value], _) => {
+                let expected = match &ty {
+                    FireType::List(e) | FireType::Map(_, e) => (**e).clone(),
+                    _ => unreachable!()
+                };
+                let (value_type, value_value) = self.compile_expected(ctx, value, Some(&expected), out)?;
+                let (element_type, element) = self.element(ctx, &ty, &base, key, out)?;
+                if !fits(&element_type, &value_type) {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", element_type, value_type), value.line, value.column));
+                }
+                self.write_element(out, &element_type, &element, &value_value, value)?;
+                Ok((FireType::Void, Value::Void))
+            }
+            ("remove", [key], _) => {
+                match self.element(ctx, &ty, &base, key, out)?.1 {
+                    Element::Path(p) => out.push(format!("data remove storage {} {}", storage, p)),
+                    Element::Macro(kind) => self.run_macro(kind, "remove", out)
+                }
+                Ok((FireType::Void, Value::Void))
+            }
+            ("length", [], _) => {
+                let t = score(&ctx.temp(&FireType::Int));
+                out.push(format!("execute store result score {} run data get storage {} {}", t, storage, base));
+                Ok((FireType::Int, Value::Score(t)))
+            }
+            ("contains", [key], None) => {
+                let t = score(&ctx.temp(&FireType::Boolean));
+                match self.element(ctx, &ty, &base, key, out)?.1 {
+                    Element::Path(p) => out.push(format!("execute store result score {} if data storage {} {}", t, storage, p)),
+                    Element::Macro(kind) => {
+                        self.run_macro(kind, "contains", out);
+                        out.push(format!("scoreboard players operation {} = {}", t, score("_args.found")));
+                    }
+                }
+                Ok((FireType::Boolean, Value::Score(t)))
+            }
+            ("contains", [value], Some(element_type)) => {
+                let (value_type, value_value) = self.compile_expression(ctx, value, out)?;
+                if !fits(&element_type, &value_type) {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", element_type, value_type), value.line, value.column));
+                }
+                if !self.functions.contains_key("_list/contains") {
+                    // Goes through the items one at a time. Setting the value to an item fails when they're the same.
+                    let found = score("_args.found");
+                    self.functions.insert("_list/contains".to_string(), vec![
+                        format!("execute unless data storage {} _args.items[0] run return 0", storage),
+                        format!("data modify storage {} _args.check set from storage {} _args.value", storage, storage),
+                        format!("execute store success score {} run data modify storage {} _args.check set from storage {} _args.items[0]", found, storage, storage),
+                        format!("execute if score {} matches 0 run return run scoreboard players set {} 1", found, found),
+                        format!("scoreboard players set {} 0", found),
+                        format!("data remove storage {} _args.items[0]", storage),
+                        format!("function {}", self.function_id("_list/contains"))
+                    ]);
+                }
+                self.write_nbt(out, &element_type, "_args.value", &value_value, value)?;
+                out.push(format!("data modify storage {} _args.items set from storage {} {}", storage, storage, base));
+                out.push(format!("scoreboard players set {} 0", score("_args.found")));
+                out.push(format!("function {}", self.function_id("_list/contains")));
+                let t = score(&ctx.temp(&FireType::Boolean));
+                out.push(format!("scoreboard players operation {} = {}", t, score("_args.found")));
+                Ok((FireType::Boolean, Value::Score(t)))
+            }
+            ("push", [value], Some(element_type)) => {
+                let (value_type, value_value) = self.compile_expected(ctx, value, Some(&element_type), out)?;
+                if !fits(&element_type, &value_type) {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", element_type, value_type), value.line, value.column));
+                }
+                self.append(out, &element_type, &base, &value_value, value)?;
+                Ok((FireType::Void, Value::Void))
+            }
+            ("forEach", [callback], Some(element_type)) => {
+                let expected = FireType::Function(vec![element_type.clone()], Box::new(FireType::Void));
+                let (callback_type, closure) = self.compile_expected(ctx, callback, Some(&expected), out)?;
+                if !matches!(&callback_type, FireType::Function(params, _) if *params == [element_type.clone()]) {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", expected, callback_type), callback.line, callback.column));
+                }
+                // Goes through a copy, the same as a for loop, calling the lambda with each item
+                let copy = ctx.temp(&ty);
+                self.copy_into(out, &ty, &copy, &Value::Storage(base))?;
+                let path = ctx.sub_path("each");
+                let id = self.function_id(&path);
+                let mut commands = vec![format!("execute unless data storage {} {}[0] run return 0", storage, copy)];
+                let item = ctx.temp(&element_type);
+                self.read_nbt(&mut commands, &element_type, &format!("{}[0]", copy), &item, at)?;
+                commands.push(format!("data remove storage {} {}[0]", storage, copy));
+                self.call_closure(ctx, &callback_type, &closure, vec![(element_type.clone(), place(&element_type, &item))], at, &mut commands)?;
+                commands.push(format!("function {}", id));
+                self.functions.insert(path, commands);
+                self.call_sub_function(ctx, out, "", &id, false);
+                Ok((FireType::Void, Value::Void))
+            }
+            ("pop", [], Some(element_type)) => {
+                let value = self.read_element(ctx, out, &element_type, &Element::Path(format!("{}[-1]", base)), at)?;
+                out.push(format!("data remove storage {} {}[-1]", storage, base));
+                Ok((element_type, value))
+            }
+            _ => Err(CompileException::UnknownName(format!("{}.{}", ty, name), at.line, at.column))
+        }
+    }
+
+    // Minecraft can only copy, compare and display a String, so anything else has to be done to one known while compiling
+    fn compile_string_method(&mut self, ctx: &mut FunctionContext, value: Value, name: &str, args: &[Expression], at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        match (name, args) {
+            ("length", []) => {
+                let text = self.constant_text(&FireType::String, value, "Finding the length of a String", at)?;
+                Ok((FireType::Int, Value::Constant(text.chars().count() as i64)))
+            }
+            ("slice", [start, rest @ ..]) if rest.len() <= 1 => {
+                let text: Vec<char> = self.constant_text(&FireType::String, value, "Slicing a String", at)?.chars().collect();
+                let mut bounds = Vec::with_capacity(2);
+                for bound in args {
+                    match self.compile_expression(ctx, bound, out)? {
+                        (FireType::Int, Value::Constant(c)) => bounds.push(c),
+                        (FireType::Int, _) => return Err(CompileException::NotConstant("Slicing a String".to_string(), bound.line, bound.column)),
+                        (ty, _) => return Err(CompileException::TypeError(format!("Expected Int but found {}", ty), bound.line, bound.column))
+                    }
+                }
+                let (from, to) = (bounds[0], bounds.get(1).copied().unwrap_or(text.len() as i64));
+                if from < 0 || from > to || to > text.len() as i64 {
+                    return Err(CompileException::TypeError(format!("Cannot slice {} to {} of a String of length {}", from, to, text.len()), start.line, start.column));
+                }
+                Ok((FireType::String, Value::ConstString(text[from as usize..to as usize].iter().collect())))
+            }
+            _ => Err(CompileException::UnknownName(format!("String.{}", name), at.line, at.column))
+        }
+    }
+
+    // The text of a value known while compiling, for joining and formatting Strings
+    fn constant_text(&self, ty: &FireType, value: Value, what: &str, at: &Expression) -> Result<String, CompileException> {
+        match (ty, value) {
+            (_, Value::ConstString(s)) => Ok(s),
+            (FireType::Boolean, Value::Constant(c)) => Ok((c != 0).to_string()),
+            (FireType::Enum(loc), Value::Constant(c)) => Ok(self.project.enums[loc].constants.iter().find(|(_, v)| *v == c).map(|(n, _)| n.clone()).unwrap_or_default()),
+            (FireType::Int, Value::Constant(c)) => Ok(c.to_string()),
+            (FireType::String | FireType::Int | FireType::Boolean | FireType::Enum(_), _) => Err(CompileException::NotConstant(what.to_string(), at.line, at.column)),
+            _ => Err(CompileException::TypeError(format!("{} cannot be turned into a String", ty), at.line, at.column))
+        }
+    }
+
+    fn compile_builtin(&mut self, ctx: &mut FunctionContext, name: &str, args: &[Expression], at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        match (name, args) {
+            ("broadcast", [message]) => {
+                let component = self.text_component(ctx, message, out)?;
+                out.push(format!("tellraw @a {}", component));
+                Ok((FireType::Void, Value::Void))
+            }
+            ("sendMessage", [message]) => {
+                let component = self.text_component(ctx, message, out)?;
+                out.push(format!("tellraw @s {}", component));
+                Ok((FireType::Void, Value::Void))
+            }
+            ("selected", []) => Ok((FireType::Player, Value::Entity("@s".to_string()))),
+            ("assert", [_]) | ("assertEq", [_, _]) => self.compile_assertion(ctx, name, args, at, out),
+            ("schedule", [ticks, task]) => {
+                let storage = self.storage();
+                let (ty, ticks_value) = self.compile_expression(ctx, ticks, out)?;
+                if ty != FireType::Int {
+                    return Err(CompileException::TypeError(format!("Expected to schedule after an Int but found {}", ty), ticks.line, ticks.column));
+                }
+                let expected = FireType::Function(Vec::new(), Box::new(FireType::Void));
+                let (task_type, closure) = self.compile_expected(ctx, task, Some(&expected), out)?;
+                let (FireType::Function(params, _), Value::Storage(closure)) = (&task_type, closure) else {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", expected, task_type), task.line, task.column));
+                };
+                if !params.is_empty() {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", expected, task_type), task.line, task.column));
+                }
+                // Every task waits in one list along with the game time it's due, the same way a pc waits
+                let wake = score("_task.wake");
+                out.push(format!("data modify storage {} _task set value {{}}", storage));
+                out.push(format!("data modify storage {} _task.closure set from storage {} {}", storage, storage, closure));
+                out.push(format!("execute store result score {} run time query gametime", wake));
+                match &ticks_value {
+                    Value::Constant(c) if *c < 1 => return Err(CompileException::TypeError("A task must be scheduled at least 1 tick later".to_string(), ticks.line, ticks.column)),
+                    Value::Constant(c) => out.push(format!("scoreboard players add {} {}", wake, c)),
+                    Value::Score(h) => {
+                        out.push(format!("scoreboard players operation {} += {}", wake, h));
+                        out.push(format!("execute store result storage {} _task.ticks int 1 run scoreboard players get {}", storage, h));
+                    }
+                    _ => unreachable!()
+                }
+                out.push(format!("execute store result storage {} _task.wake int 1 run scoreboard players get {}", storage, wake));
+                out.push(format!("data modify storage {} _tasks.waiting append from storage {} _task", storage, storage));
+                self.task_functions()?;
+                let run = self.function_id("_tasks/run");
+                match ticks_value {
+                    Value::Constant(c) => out.push(format!("schedule function {} {}t append", run, c)),
+                    _ => {
+                        if !self.functions.contains_key("_tasks/wait") {
+                            self.functions.insert("_tasks/wait".to_string(), vec![format!("$schedule function {} $(ticks)t append", run)]);
+                        }
+                        out.push(format!("function {} with storage {} _task", self.function_id("_tasks/wait"), storage));
+                    }
+                }
+                Ok((FireType::Void, Value::Void))
+            }
+            ("subscribe", [event, handler]) => {
+                let name = match &event.kind {
+                    ExpressionKind::Builtin(name) if name == "PlayerJoinEvent" => name.clone(),
+                    _ => return Err(CompileException::TypeError("Expected an event, such as PlayerJoinEvent".to_string(), event.line, event.column))
+                };
+                let expected = FireType::Function(vec![FireType::Event(name.clone())], Box::new(FireType::Void));
+                let (handler_type, closure) = self.compile_expected(ctx, handler, Some(&expected), out)?;
+                let (FireType::Function(params, _), Value::Storage(closure)) = (&handler_type, closure) else {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", expected, handler_type), handler.line, handler.column));
+                };
+                if *params != [FireType::Event(name.clone())] {
+                    return Err(CompileException::TypeError(format!("Expected {} but found {}", expected, handler_type), handler.line, handler.column));
+                }
+                let storage = self.storage();
+                out.push(format!("data modify storage {} _events.{} append from storage {} {}", storage, event_id(&name), storage, closure));
+                self.events.insert(name);
+                Ok((FireType::Void, Value::Void))
+            }
+            ("format", [template, values @ ..]) => {
+                let (ty, value) = self.compile_expression(ctx, template, out)?;
+                if ty != FireType::String {
+                    return Err(CompileException::TypeError(format!("Expected String but found {}", ty), template.line, template.column));
+                }
+                let template_text = self.constant_text(&ty, value, "Formatting a String", template)?;
+                let mut parts = Vec::with_capacity(values.len());
+                for v in values {
+                    let (ty, value) = self.compile_expression(ctx, v, out)?;
+                    parts.push(self.constant_text(&ty, value, "Formatting a String", v)?);
+                }
+                // Each {} is the next value, and {{ and }} are a single brace
+                let mut parts = parts.into_iter();
+                let mut formatted = String::new();
+                let mut chars = template_text.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match (c, chars.peek()) {
+                        ('{', Some('{')) | ('}', Some('}')) => {
+                            chars.next();
+                            formatted.push(c);
+                        }
+                        ('{', Some('}')) => {
+                            chars.next();
+                            formatted += &parts.next().ok_or_else(|| CompileException::TypeError("There are more {} than values to format".to_string(), template.line, template.column))?;
+                        }
+                        _ => formatted.push(c)
+                    }
+                }
+                if parts.next().is_some() {
+                    return Err(CompileException::TypeError(format!("Given {} values to format but there are fewer {{}}", values.len()), at.line, at.column));
+                }
+                Ok((FireType::String, Value::ConstString(formatted)))
+            }
+            _ => Err(CompileException::UnknownName(name.to_string(), at.line, at.column))
+        }
+    }
+
+    // A failed assertion raises, keeping where it is along with the values assertEq compared so the test runner can show them
+    fn compile_assertion(&mut self, ctx: &mut FunctionContext, name: &str, args: &[Expression], at: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let storage = self.storage();
+        let mut failed = vec![format!(
+            "data modify storage {} {} set value {{message:{},at:{},line:{},column:{}}}",
+            storage, RAISED, quote(&format!("Assertion failed at {}:{}", at.line, at.column)), quote(&self.compiling.to_string()), at.line, at.column
+        )];
+        let passed = match args {
+            [expected, actual] if name == "assertEq" => {
+                let (expected_type, expected_value) = self.compile_expression(ctx, expected, out)?;
+                let (actual_type, actual_value) = self.compile_expression(ctx, actual, out)?;
+                let (_, equal) = self.compile_operator(ctx, BinaryOperator::Equal, (expected, (expected_type.clone(), expected_value.clone())), (actual, (actual_type.clone(), actual_value.clone())), out)?;
+                self.write_nbt(&mut failed, &expected_type, &format!("{}.expected", RAISED), &expected_value, expected)?;
+                self.write_nbt(&mut failed, &actual_type, &format!("{}.actual", RAISED), &actual_value, actual)?;
+                equal
+            }
+            [condition] => self.compile_condition(ctx, condition, out)?,
+            _ => unreachable!()
+        };
+        failed.push(format!("scoreboard players set {} 1", score(RAISED)));
+        failed.push("return 0".to_string());
+        match passed {
+            Value::Constant(0) => out.append(&mut failed),
+            Value::Constant(_) => {}
+            Value::Score(h) => {
+                let path = ctx.sub_path("assert");
+                self.functions.insert(path.clone(), failed);
+                out.push(format!("execute unless score {} matches 1 run function {}", h, self.function_id(&path)));
+                self.raise_check(out);
+            }
+            _ => unreachable!()
+        }
+        Ok((FireType::Void, Value::Void))
+    }
+
+    // The JSON text component that displays a value. A String joined with & or with {values} in it displays each part,
+    // with the parts known while compiling joined together.
+    fn text_component(&mut self, ctx: &mut FunctionContext, expr: &Expression, out: &mut CompiledCommands) -> Result<String, CompileException> {
+        fn parts<'a>(expr: &'a Expression, found: &mut Vec<&'a Expression>) {
+            match &expr.kind {
+                ExpressionKind::Binary(BinaryOperator::And, a, b) => {
+                    parts(a, found);
+                    parts(b, found);
+                }
+                ExpressionKind::Interpolation(items) => items.iter().for_each(|e| parts(e, found)),
+                _ => found.push(expr)
+            }
+        }
+        let mut found = Vec::new();
+        parts(expr, &mut found);
+        let mut values = Vec::with_capacity(found.len());
+        for part in &found {
+            values.push(self.compile_expression(ctx, part, out)?);
+        }
+        if matches!(expr.kind, ExpressionKind::Binary(..)) && values.iter().all(|(ty, _)| *ty == FireType::Boolean) {
+            // Just Booleans being and-ed together
+            let mut values = values.into_iter().map(|(_, v)| v);
+            let first = values.next().unwrap();
+            let value = values.try_fold(first, |a, b| self.compile_logic(ctx, BinaryOperator::And, a, b, out))?;
+            return self.component(ctx, &FireType::Boolean, value, expr, out);
+        }
+        let mut components = Vec::with_capacity(values.len());
+        let mut text: Option<String> = None;
+        for ((ty, value), part) in values.into_iter().zip(found) {
+            if let Ok(t) = self.constant_text(&ty, value.clone(), "", part) {
+                text = Some(text.unwrap_or_default() + &t);
+                continue;
+            }
+            components.extend(text.take().map(|t| format!("{{\"text\":{}}}", quote(&t))));
+            components.push(self.component(ctx, &ty, value, part, out)?);
+        }
+        components.extend(text.take().map(|t| format!("{{\"text\":{}}}", quote(&t))));
+        Ok(match &components[..] {
+            [component] => component.clone(),
+            _ => format!("[{}]", components.join(","))
+        })
+    }
+
+    fn component(&mut self, ctx: &mut FunctionContext, ty: &FireType, value: Value, expr: &Expression, out: &mut CompiledCommands) -> Result<String, CompileException> {
+        let value = match ty {
+            FireType::Enum(loc) => self.enum_to_string(ctx, loc, value, out)?,
+            _ => value
+        };
+        Ok(match (ty, value) {
+            (FireType::Boolean, Value::Constant(c)) => format!("{{\"text\":\"{}\"}}", c != 0),
+            (_, Value::Constant(c)) => format!("{{\"text\":\"{}\"}}", c),
+            (_, Value::ConstString(s)) => format!("{{\"text\":{}}}", quote(&s)),
+            (_, Value::Score(h)) => {
+                let (name, objective) = h.split_once(' ').unwrap();
+                format!("{{\"score\":{{\"name\":\"{}\",\"objective\":\"{}\"}}}}", name, objective)
+            }
+            (_, Value::Storage(p)) => format!("{{\"nbt\":\"{}\",\"storage\":\"{}\"}}", p, self.storage()),
+            (_, Value::Entity(selector)) => format!("{{\"selector\":{}}}", quote(&selector)),
+            _ => return Err(CompileException::TypeError(format!("Cannot display {}", ty), expr.line, expr.column))
+        })
+    }
+
+    fn enum_to_string(&mut self, ctx: &mut FunctionContext, loc: &FireLocation, value: Value, out: &mut CompiledCommands) -> Result<Value, CompileException> {
+        let constants = &self.project.enums[loc].constants;
+        if let Value::Constant(c) = value {
+            let name = constants.iter().find(|(_, v)| *v == c).map(|(n, _)| n.clone()).unwrap_or_default();
+            return Ok(Value::ConstString(name));
+        }
+        self.enum_helpers(loc);
+        let holder = holder_name(loc);
+        self.copy_into(out, &FireType::Int, &format!("{}._value", holder), &value)?;
+        out.push(format!("function {}", self.function_id(&format!("{}/_to_string", function_path(loc)))));
+        let t = ctx.temp(&FireType::String);
+        self.copy_into(out, &FireType::String, &t, &Value::Storage(format!("{}._string", holder)))?;
+        Ok(Value::Storage(t))
+    }
+
+    // Generates the functions that turn an enum to and from a String
+    fn enum_helpers(&mut self, loc: &FireLocation) {
+        if !self.enum_helpers.insert(loc.clone()) {
+            return;
+        }
+        let path = function_path(loc);
+        let holder = holder_name(loc);
+        let storage = self.storage();
+        let constants = self.project.enums[loc].constants.clone();
+        let mut ctx = FunctionContext::new(format!("{}/_to_string", path), holder.clone(), FireType::Void);
+        let mut cases: Vec<(i64, i64, String)> = constants.iter()
+            .map(|(n, v)| (*v, *v, format!("data modify storage {} {}._string set value {}", storage, holder, quote(n))))
+            .collect();
+        cases.sort_by_key(|(v, _, _)| *v);
+        let mut to_string = Vec::new();
+        self.compile_dispatch(&mut ctx, &score(&format!("{}._value", holder)), &cases, None, &mut to_string);
+        self.functions.insert(ctx.path, to_string);
+        let mut from_string = Vec::new();
+        let different = score(&format!("{}._different", holder));
+        if let Some((_, first)) = constants.first() {
+            from_string.push(format!("scoreboard players set {} {}", score(&format!("{}._value", holder)), first));
+        }
+        for (name, value) in &constants {
+            from_string.push(format!("data modify storage {} {}._compare set from storage {} {}._arg", storage, holder, storage, holder));
+            from_string.push(format!("execute store success score {} run data modify storage {} {}._compare set value {}", different, storage, holder, quote(name)));
+            from_string.push(format!("execute if score {} matches 0 run scoreboard players set {} {}", different, score(&format!("{}._value", holder)), value));
+        }
+        self.functions.insert(format!("{}/_from_string", path), from_string);
+    }
+
+    fn compile_binary(&mut self, ctx: &mut FunctionContext, operator: BinaryOperator, left: &Expression, right: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        if matches!(operator, BinaryOperator::StrictAnd | BinaryOperator::StrictOr) {
+            return self.compile_short_circuit(ctx, operator, left, right, out);
+        }
+        if operator == BinaryOperator::In {
+            return self.compile_in(ctx, left, right, out);
+        }
+        let left_compiled = self.compile_expression(ctx, left, out)?;
+        let right_compiled = self.compile_expression(ctx, right, out)?;
+        self.compile_operator(ctx, operator, (left, left_compiled), (right, right_compiled), out)
+    }
+
+    // Applies an operator to two values that have already been compiled from the expressions on either side of it
+    fn compile_operator(&mut self, ctx: &mut FunctionContext, operator: BinaryOperator, (left, (left_type, left_value)): (&Expression, (FireType, Value)), (right, (right_type, right_value)): (&Expression, (FireType, Value)), out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        use BinaryOperator::*;
+        match (operator, &left_type, &right_type) {
+            (Add | Subtract | Multiply | Divide | Modulo, FireType::Int, FireType::Int) => {
+                Ok((FireType::Int, self.compile_arithmetic(ctx, operator, left_value, right_value, right, out)?))
+            }
+            (Greater | Less | GreaterEqual | LessEqual, FireType::Int, FireType::Int) => {
+                Ok((FireType::Boolean, self.compile_comparison(ctx, operator, left_value, right_value, out)))
+            }
+            (Equal | NotEqual, FireType::Int | FireType::Boolean | FireType::Enum(_), _) if left_type == right_type => {
+                Ok((FireType::Boolean, self.compile_comparison(ctx, operator, left_value, right_value, out)))
+            }
+            (Equal | NotEqual, FireType::String | FireType::List(_) | FireType::Map(..), _) if left_type == right_type => {
+                if let (Value::ConstString(a), Value::ConstString(b)) = (&left_value, &right_value) {
+                    return Ok((FireType::Boolean, Value::Constant(((a == b) == (operator == Equal)) as i64)));
+                }
+                let t = ctx.temp(&FireType::String);
+                let storage = self.storage();
+                self.copy_into(out, &FireType::String, &t, &left_value)?;
+                let from = match right_value {
+                    Value::ConstString(s) => format!("value {}", quote(&s)),
+                    Value::Storage(p) => format!("from storage {} {}", storage, p),
+                    _ => unreachable!()
+                };
+                // Setting storage to the value it already has fails, which is the only way to compare two strings
+                out.push(format!("execute store success score {} run data modify storage {} {} set {}", score(&t), storage, t, from));
+                let check = if operator == Equal { "if" } else { "unless" };
+                out.push(format!("execute store result score {} {} score {} matches 0", score(&t), check, score(&t)));
+                Ok((FireType::Boolean, Value::Score(score(&t))))
+            }
+            // Minecraft can't join strings, so both sides have to be known now
+            (Add, FireType::String, other) | (Add, other, FireType::String) if matches!(other, FireType::String | FireType::Int | FireType::Boolean | FireType::Enum(_)) => {
+                let joined = self.constant_text(&left_type, left_value, "Joining Strings", left)? + &self.constant_text(&right_type, right_value, "Joining Strings", right)?;
+                Ok((FireType::String, Value::ConstString(joined)))
+            }
+            (And, FireType::String, _) | (And, _, FireType::String) => {
+                let joined = self.constant_text(&left_type, left_value, "Joining Strings outside of a message", left)? + &self.constant_text(&right_type, right_value, "Joining Strings outside of a message", right)?;
+                Ok((FireType::String, Value::ConstString(joined)))
+            }
+            (And | StrictAnd | Or | StrictOr | Xor, FireType::Boolean, FireType::Boolean) => {
+                Ok((FireType::Boolean, self.compile_logic(ctx, operator, left_value, right_value, out)?))
+            }
+            _ => Err(CompileException::TypeError(format!("Cannot use {:?} on {} and {}", operator, left_type, right_type), left.line, left.column))
+        }
+    }
+
+    // && and || only run their right side when it can change the result, while & and | always run both.
+    // A right side that's a single check is added onto the execute that guards it, so a chain of them is one command each.
+    fn compile_short_circuit(&mut self, ctx: &mut FunctionContext, operator: BinaryOperator, left: &Expression, right: &Expression, out: &mut CompiledCommands) -> Result<(FireType, Value), CompileException> {
+        let (left_type, left_value) = self.compile_expression(ctx, left, out)?;
+        let mut rest = Vec::new();
+        let (right_type, right_value) = self.compile_expression(ctx, right, &mut rest)?;
+        if left_type != FireType::Boolean || right_type != FireType::Boolean {
+            return Err(CompileException::TypeError(format!("Cannot use {:?} on {} and {}", operator, left_type, right_type), left.line, left.column));
+        }
+        let and = operator == BinaryOperator::StrictAnd;
+        match left_value {
+            Value::Constant(c) if (c != 0) != and => return Ok((FireType::Boolean, Value::Constant(c))), // false && or true ||, so the right side never runs
+            Value::Constant(_) => {
+                out.append(&mut rest);
+                return Ok((FireType::Boolean, right_value));
+            }
+            _ => {}
+        }
+        if rest.is_empty() { // Nothing to skip
+            return Ok((FireType::Boolean, self.compile_logic(ctx, operator, left_value, right_value, out)?));
+        }
+        let Value::Score(l) = left_value else { unreachable!() };
+        let t = if l.starts_with(&format!("${}._t", ctx.holder)) {
+            l
+        } else {
+            let t = score(&ctx.temp(&FireType::Boolean));
+            out.push(format!("scoreboard players operation {} = {}", t, l));
+            t
+        };
+        let guard = format!("execute {} score {} matches 1 ", if and { "if" } else { "unless" }, t);
+        let check = match (&right_value, &rest[..]) {
+            (Value::Score(r), [command]) => command.strip_prefix(&format!("execute store result score {} ", r)),
+            _ => None
+        };
+        match check {
+            Some(check) => out.push(format!("{}store result score {} {}", guard, t, check)),
+            None => {
+                self.store(&mut rest, &FireType::Boolean, &Value::Score(t.clone()), &right_value)?;
+                let path = ctx.sub_path(if and { "and" } else { "or" });
+                self.functions.insert(path.clone(), rest);
+                let id = self.function_id(&path);
+                self.call_sub_function(ctx, out, &format!("{}run ", guard), &id, false);
+            }
+        }
+        Ok((FireType::Boolean, Value::Score(t)))
+    }
+
+    fn compile_arithmetic(&mut self, ctx: &mut FunctionContext, operator: BinaryOperator, left: Value, right: Value, at: &Expression, out: &mut CompiledCommands) -> Result<Value, CompileException> {
+        if let (Value::Constant(a), Value::Constant(b)) = (&left, &right) {
+            let (a, b) = (*a as i32, *b as i32);
+            if b == 0 && matches!(operator, BinaryOperator::Divide | BinaryOperator::Modulo) {
+                return Err(CompileException::TypeError("Division by zero".to_string(), at.line, at.column));
+            }
+            let (folded, symbol) = match operator {
+                BinaryOperator::Add => (a.checked_add(b), "+"),
+                BinaryOperator::Subtract => (a.checked_sub(b), "-"),
+                BinaryOperator::Multiply => (a.checked_mul(b), "*"),
+                // Only the smallest Int divided by -1 can overflow here, which floor_div's i64 handles
+                BinaryOperator::Divide => (i32::try_from(floor_div(a as i64, b as i64)).ok(), "/"),
+                _ => (i32::try_from(a as i64 - b as i64 * floor_div(a as i64, b as i64)).ok(), "%")
+            };
+            return fold(folded, at, || format!("{} {} {}", a, symbol, b));
+        }
+        let t = ctx.temp(&FireType::Int);
+        self.copy_into(out, &FireType::Int, &t, &left)?;
+        let symbol = match operator {
+            BinaryOperator::Add => "+=",
+            BinaryOperator::Subtract => "-=",
+            BinaryOperator::Multiply => "*=",
+            BinaryOperator::Divide => "/=",
+            _ => "%="
+        };
+        match right {
+            // The smallest Int can't be flipped into an amount to add or remove
+            Value::Constant(c) if (operator == BinaryOperator::Add || operator == BinaryOperator::Subtract) && c != i32::MIN as i64 => {
+                let c = if operator == BinaryOperator::Add { c } else { -c };
+                let (command, c) = if c < 0 { ("remove", -c) } else { ("add", c) };
+                out.push(format!("scoreboard players {} {} {}", command, score(&t), c));
+            }
+            Value::Constant(c) => {
+                let holder = self.constant(c);
+                out.push(format!("scoreboard players operation {} {} {} {}", score(&t), symbol, holder, OBJECTIVE));
+            }
+            Value::Score(h) => out.push(format!("scoreboard players operation {} {} {}", score(&t), symbol, h)),
+            _ => unreachable!()
+        }
+        Ok(Value::Score(score(&t)))
+    }
+
+    fn compile_comparison(&mut self, ctx: &mut FunctionContext, operator: BinaryOperator, left: Value, right: Value, out: &mut CompiledCommands) -> Value {
+        use BinaryOperator::*;
+        if let (Value::Constant(a), Value::Constant(b)) = (&left, &right) {
+            return Value::Constant(match operator {
+                Equal => a == b,
+                NotEqual => a != b,
+                Greater => a > b,
+                Less => a < b,
+                GreaterEqual => a >= b,
+                _ => a <= b
+            } as i64);
+        }
+        // Keep any constant on the right, where matches can use it
+        let (operator, left, right) = match (&left, operator) {
+            (Value::Constant(_), Greater) => (Less, right, left),
+            (Value::Constant(_), Less) => (Greater, right, left),
+            (Value::Constant(_), GreaterEqual) => (LessEqual, right, left),
+            (Value::Constant(_), LessEqual) => (GreaterEqual, right, left),
+            (Value::Constant(_), _) => (operator, right, left),
+            _ => (operator, left, right)
+        };
+        let Value::Score(left) = left else { unreachable!() };
+        let check = if operator == NotEqual { "unless" } else { "if" };
+        let condition = match right {
+            Value::Constant(c) => format!("score {} matches {}", left, match operator {
+                Greater => format!("{}..", c + 1),
+                Less => format!("..{}", c - 1),
+                GreaterEqual => format!("{}..", c),
+                LessEqual => format!("..{}", c),
+                _ => c.to_string()
+            }),
+            Value::Score(right) => format!("score {} {} {}", left, match operator {
+                Greater => ">",
+                Less => "<",
+                GreaterEqual => ">=",
+                LessEqual => "<=",
+                _ => "="
+            }, right),
+            _ => unreachable!()
+        };
+        let t = score(&ctx.temp(&FireType::Boolean));
+        out.push(format!("execute store result score {} {} {}", t, check, condition));
+        Value::Score(t)
+    }
+
+    fn compile_logic(&mut self, ctx: &mut FunctionContext, operator: BinaryOperator, left: Value, right: Value, out: &mut CompiledCommands) -> Result<Value, CompileException> {
+        use BinaryOperator::*;
+        if let (Value::Constant(a), Value::Constant(b)) = (&left, &right) {
+            return Ok(Value::Constant(match operator {
+                And | StrictAnd => a & b,
+                Or | StrictOr => a | b,
+                _ => a ^ b
+            }));
+        }
+        let right = self.score_of(ctx, &right, out);
+        let t = ctx.temp(&FireType::Boolean);
+        self.copy_into(out, &FireType::Boolean, &t, &left)?;
+        // Booleans are 0 or 1, so and is the smaller of the two and or is the larger
+        match operator {
+            And | StrictAnd => out.push(format!("scoreboard players operation {} < {}", score(&t), right)),
+            Or | StrictOr => out.push(format!("scoreboard players operation {} > {}", score(&t), right)),
+            _ => {
+                out.push(format!("scoreboard players operation {} -= {}", score(&t), right));
+                out.push(format!("execute store result score {} unless score {} matches 0", score(&t), score(&t)));
+            }
+        }
+        Ok(Value::Score(score(&t)))
+    }
+}
@@ -1,6 +1,6 @@
 use std::{path::{Path, PathBuf}, fmt::Display, fs::{self}};
 
-use crate::{parser::{FireLocation, StructuredFireProject}, compiler::CompileException};
+use crate::{parser::{self, FireLocation, StructuredFireProject}, compiler::{CompileException, CompiledDatapack}, tokenizer};
 
 
 pub fn verify_project_format(path: &Path) -> Result<(), ProjectStructureException> {
@@ -17,7 +17,10 @@ pub fn verify_project_format(path: &Path) -> Result<(), ProjectStructureExceptio
     }
     let mut target_check = path.to_path_buf();
     target_check.push("target");
-    if !target_check.exists() || !target_check.is_dir() { // Make sure the target folder exists
+    if !target_check.exists() && fs::create_dir(&target_check).is_err() { // Make the target folder if it's not there yet, since it's never checked in
+        return Err(ProjectStructureException::NoTarget);
+    };
+    if !target_check.is_dir() {
         return Err(ProjectStructureException::NoTarget);
     };
     Ok(())
@@ -27,23 +30,49 @@ pub fn verify_project_format(path: &Path) -> Result<(), ProjectStructureExceptio
 pub fn get_all_project_files(path: &Path) -> Vec<UncompiledFireFile> {
     let mut src_folder = path.to_path_buf();
     src_folder.push("src");
-    let mut check_files = src_folder.read_dir().unwrap();
-
     let mut out = Vec::new();
     scan_load_files(&mut out, src_folder, FireLocation::new());
     out
 }
 
+// The namespace a project's functions go in, made from the name of its folder
+pub fn project_namespace(path: &Path) -> String {
+    let name = path.canonicalize().ok().and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string())).unwrap_or_default();
+    let namespace: String = name.to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' }).collect();
+    if namespace.is_empty() { "fire".to_string() } else { namespace }
+}
+
+// Writes a datapack into the project's target folder, replacing what was compiled there before
+pub fn write_datapack(path: &Path, datapack: &CompiledDatapack) -> Result<(), CompileException> {
+    let mut target = path.to_path_buf();
+    target.push("target");
+    let write = |file: PathBuf, contents: String| -> Result<(), CompileException> {
+        fs::create_dir_all(file.parent().unwrap()).map_err(|_| CompileException::NoFileAccess(file.clone()))?;
+        fs::write(&file, contents).map_err(|_| CompileException::NoFileAccess(file.clone()))
+    };
+    write(target.join("pack.mcmeta"), "{\"pack\":{\"pack_format\":26,\"description\":\"Compiled with Fire\"}}\n".to_string())?;
+    let functions = target.join("data").join(&datapack.namespace).join("functions");
+    if functions.exists() {
+        fs::remove_dir_all(&functions).map_err(|_| CompileException::NoFileAccess(functions.clone()))?;
+    }
+    for (name, commands) in &datapack.functions {
+        write(functions.join(format!("{}.mcfunction", name)), commands.join("\n") + "\n")?;
+    }
+    let load: Vec<String> = datapack.load.iter().map(|f| format!("\"{}\"", f)).collect();
+    write(target.join("data/minecraft/tags/functions/load.json"), format!("{{\"values\":[{}]}}\n", load.join(",")))?;
+    let tick: Vec<String> = datapack.tick.iter().map(|f| format!("\"{}\"", f)).collect();
+    write(target.join("data/minecraft/tags/functions/tick.json"), format!("{{\"values\":[{}]}}\n", tick.join(",")))?;
+    Ok(())
+}
+
 fn scan_load_files(files: &mut Vec<UncompiledFireFile>, path: PathBuf, loc: FireLocation) {
-    for uf in path.read_dir().unwrap() {
-        if let Ok(f) = uf {
-            if f.path().is_dir() {
-                scan_load_files(files, f.path().to_path_buf(), loc.with(f.file_name().to_str().unwrap().to_string()));
-            } else if f.path().is_file() {
-                if let Some(n) = f.path().extension() {
-                    if n == "fire" {
-                        files.push(UncompiledFireFile::new(loc.with(f.path().file_stem().unwrap().to_str().unwrap().to_string()), f.path()));
-                    };
+    for f in path.read_dir().unwrap().flatten() {
+        if f.path().is_dir() {
+            scan_load_files(files, f.path().to_path_buf(), loc.with(f.file_name().to_str().unwrap().to_string()));
+        } else if f.path().is_file() {
+            if let Some(n) = f.path().extension() {
+                if n == "fire" {
+                    files.push(UncompiledFireFile::new(loc.with(f.path().file_stem().unwrap().to_str().unwrap().to_string()), f.path()));
                 };
             };
         };
@@ -71,24 +100,28 @@ impl UncompiledFireFile {
     pub fn new(location: FireLocation, file_location: PathBuf) -> UncompiledFireFile {
         UncompiledFireFile { location, file_location }
     }
+    pub fn location(&self) -> &FireLocation {
+        &self.location
+    }
+    pub fn path(&self) -> &Path {
+        &self.file_location
+    }
     pub fn read_all(&self) -> Result<String, CompileException> {
         if let Ok(s) = fs::read_to_string(self.file_location.clone()) {
             Ok(s)
         } else {
-            Err(CompileException::NoFileAccess(&self.file_location))
+            Err(CompileException::NoFileAccess(self.file_location.clone()))
         }
     }
-    pub fn structure_into(self, proj: StructuredFireProject) -> Result<StructuredFireProject, CompileException<'static>> {
-        unimplemented!();
+    // Splits the file into its resources and adds them to the project
+    pub fn structure_into(&self, mut proj: StructuredFireProject) -> Result<StructuredFireProject, CompileException> {
+        let text = self.read_all()?;
+        let tokens = tokenizer::tokenize(&text).map_err(|e| CompileException::FileException(self.file_location.clone(), Box::new(e)))?;
+        parser::structure_file(&mut proj, &self.location, tokens).map_err(|e| CompileException::FileException(self.file_location.clone(), Box::new(e)))?;
+        Ok(proj)
     }
 }
 
-// A fire file that contains uncompiled headers. 
-#[derive(Debug)]
-pub struct StructuredFireFile {
-    location: FireLocation
-}
-
 pub enum ProjectStructureException {
     NotDir, NoSrc, NoTarget, EmptySrc
 }
@@ -97,7 +130,7 @@ impl Display for ProjectStructureException {
         match self {
             ProjectStructureException::NotDir => f.write_str("Project must be a directory.")?,
             ProjectStructureException::NoSrc => f.write_str("Project does not contain a src subdirectory.")?,
-            ProjectStructureException::NoTarget => f.write_str("Project does not contain a target subdirectory.")?,
+            ProjectStructureException::NoTarget => f.write_str("Project's target subdirectory is not a directory and could not be made.")?,
             ProjectStructureException::EmptySrc => f.write_str("Project src folder does not contain any modules.")?,
         };
         Ok(())
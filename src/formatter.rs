@@ -0,0 +1,361 @@
+use crate::{compiler::CompileException, syntax::{parse_lossless, SyntaxElement, SyntaxKind, SyntaxNode}, tokenizer::{LosslessToken, Token, Trivia}};
+
+// How long a line can get before lists in ( ) and [ ] are split up, one item per line
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+// Formats a .fire file: four spaces of indentation, one statement per line, braces on the same line as what they belong to,
+// spaces around operators and at most one blank line in a row. Comments are kept where they were.
+pub fn format_source(input: &str) -> Result<String, CompileException> {
+    let tree = parse_lossless(input)?;
+    let mut writer = Writer::default();
+    writer.items(&tree.root);
+    writer.comments(&tree.trailing);
+    if !writer.out.is_empty() {
+        writer.out.push('\n');
+    }
+    Ok(writer.out)
+}
+
+#[derive(Clone, Default)]
+struct Writer {
+    out: String,
+    indent: usize,
+    pending: usize, // Line breaks to write before the next token, where 2 leaves a blank line
+    previous: Option<Token>,
+    before_previous: Option<Token>,
+    unary: bool, // Whether the previous token was a - that goes with the value after it
+    generics: usize, // How many of the < in List<Int> and Map<String, Int> are open
+    line_comments: usize
+}
+
+impl Writer {
+    // The items of a file, with a blank line around anything that has a body
+    fn items(&mut self, file: &SyntaxNode) {
+        let mut had_body = false;
+        for child in &file.children {
+            let SyntaxElement::Node(item) = child else { continue };
+            let has_body = item.children.iter().any(|c| matches!(c, SyntaxElement::Node(n) if n.kind == SyntaxKind::Block));
+            self.pending = if had_body || has_body { 2 } else { 1 };
+            had_body = has_body;
+            self.statement(item, false);
+        }
+    }
+
+    // An item or statement. In a compact block a , at the end is left out.
+    fn statement(&mut self, statement: &SyntaxNode, compact: bool) {
+        let keywords: Vec<&Token> = statement.tokens().map(|t| &t.token.token).collect();
+        // Only a struct being made has a block straight after a Type, since the others start with a keyword, as in if x == MAX {
+        let literal_allowed = !keywords.iter().any(|t| matches!(t,
+            Token::StructDef | Token::EnumDef | Token::TraitDef | Token::Implementation | Token::GroupDef | Token::FuncDef | Token::ProcDef |
+            Token::If | Token::Else | Token::For | Token::While | Token::Match | Token::Try | Token::Catch | Token::Select
+        ));
+        let listed = keywords.iter().any(|t| matches!(t, Token::StructDef | Token::EnumDef)); // Fields and variants are split by ,
+        let mut annotations = true;
+        for (i, child) in statement.children.iter().enumerate() {
+            match child {
+                SyntaxElement::Token(t) => {
+                    // if (x) return a; else return b; keeps else on a line of its own
+                    if matches!(t.token.token, Token::Else | Token::Catch) && self.previous == Some(Token::Semicolon) {
+                        self.pending = self.pending.max(1);
+                    }
+                    // The , after a match arm like 1 => return 4;, is left out since the ; already ends it
+                    let after_arm = i == 0 && self.previous == Some(Token::Semicolon);
+                    if t.token.token == Token::Comma && (after_arm || compact && i + 1 == statement.children.len()) {
+                        self.comments(&t.leading);
+                        continue;
+                    }
+                    self.token(t);
+                    annotations &= matches!(t.token.token, Token::Annotation(_));
+                    if annotations {
+                        self.pending = 1;
+                    }
+                }
+                SyntaxElement::Node(n) if n.kind == SyntaxKind::Block => {
+                    let literal = literal_allowed && self.at_literal();
+                    self.block(n, literal, literal || listed);
+                }
+                SyntaxElement::Node(n) => self.group(n)
+            }
+        }
+    }
+
+    // Whether a block here is a struct being made, like Thing { a: 3 }, which is kept on one line if it fits
+    fn at_literal(&self) -> bool {
+        matches!(self.previous, Some(Token::Type(_))) && self.before_previous != Some(Token::Colon)
+    }
+
+    // A { } block. Most put each statement on its own line, but compact ones are kept on one line if they fit. In blocks that
+    // are split by , such as the fields of a struct, the last one gets a , too when they're on their own lines.
+    fn block(&mut self, block: &SyntaxNode, compact: bool, listed: bool) {
+        let (open, statements, close) = parts(block);
+        if compact {
+            let saved = self.clone();
+            self.token(open);
+            for (i, statement) in statements.iter().enumerate() {
+                self.statement(statement, i + 1 == statements.len());
+            }
+            self.token(close);
+            let line = self.out[saved.out.len()..].to_string();
+            if !line.contains('\n') && self.line_comments == saved.line_comments && self.column() <= MAX_WIDTH {
+                return;
+            }
+            *self = saved;
+        }
+        self.token(open);
+        self.indent += 1;
+        for statement in &statements {
+            self.pending = self.pending.max(1);
+            self.statement(statement, false);
+            let ends = statement.children.last().is_some_and(|c| match c {
+                SyntaxElement::Token(t) => matches!(t.token.token, Token::Comma | Token::Semicolon),
+                SyntaxElement::Node(n) => n.kind == SyntaxKind::Block
+            });
+            if listed && !ends {
+                self.text(",", false);
+            }
+        }
+        self.comments(&close.leading);
+        self.indent -= 1;
+        if !statements.is_empty() || self.previous != Some(Token::OpenBrace) {
+            self.pending = self.pending.max(1);
+        }
+        self.bare(close);
+    }
+
+    // A ( ) or [ ] list, which is split up one item per line if it makes the line too long or has a comment in it
+    fn group(&mut self, group: &SyntaxNode) {
+        let saved = self.clone();
+        self.elements(group, false);
+        let first_line = self.out[saved.out.len()..].split('\n').next().unwrap_or_default().chars().count();
+        let start = saved.out.rfind('\n').map_or(0, |i| i + 1);
+        let width = saved.out[start..].chars().count() + first_line;
+        if width <= MAX_WIDTH && self.line_comments == saved.line_comments {
+            return;
+        }
+        *self = saved;
+        self.elements(group, true);
+    }
+
+    // What's in a ( ) or [ ] list, either on one line or split up with a , after every item
+    fn elements(&mut self, group: &SyntaxNode, split: bool) {
+        let children = &group.children;
+        let last = children.len() - 1;
+        let empty = children.len() == 2;
+        for (i, child) in children.iter().enumerate() {
+            match child {
+                SyntaxElement::Token(t) if i == 0 => {
+                    self.token(t);
+                    if split && !empty {
+                        self.indent += 1;
+                        self.pending = 1;
+                    }
+                }
+                SyntaxElement::Token(t) if i == last => {
+                    if split && !empty {
+                        if self.previous != Some(Token::Comma) {
+                            self.text(",", false);
+                        }
+                        self.comments(&t.leading);
+                        self.indent -= 1;
+                        self.pending = 1;
+                        self.bare(t);
+                    } else {
+                        self.token(t);
+                    }
+                }
+                // A , right before the end is only kept when the list is split up
+                SyntaxElement::Token(t) if t.token.token == Token::Comma && i + 1 == last && !split => self.comments(&t.leading),
+                SyntaxElement::Token(t) => {
+                    self.token(t);
+                    if split && t.token.token == Token::Comma && self.generics == 0 {
+                        self.pending = 1;
+                    }
+                }
+                SyntaxElement::Node(n) if n.kind == SyntaxKind::Block => {
+                    let literal = self.at_literal();
+                    self.block(n, literal, literal);
+                }
+                SyntaxElement::Node(n) => self.group(n)
+            }
+        }
+    }
+
+    fn token(&mut self, token: &LosslessToken) {
+        self.comments(&token.leading);
+        self.bare(token);
+    }
+
+    // Writes a token without the trivia before it
+    fn bare(&mut self, token: &LosslessToken) {
+        let next = &token.token.token;
+        let generic = matches!(next, Token::Less) && matches!(self.previous, Some(Token::Type(_))) && token.leading.is_empty();
+        let space = self.spaced(next, generic);
+        if matches!(next, Token::Greater) && self.generics > 0 {
+            self.generics -= 1;
+        }
+        if generic {
+            self.generics += 1;
+        }
+        self.unary = matches!(next, Token::Minus) && self.previous.as_ref().is_none_or(before_value);
+        self.text(&token.text, space);
+        self.previous = Some(next.clone());
+    }
+
+    // Whether a space goes between the previous token and this one
+    fn spaced(&self, next: &Token, generic: bool) -> bool {
+        let Some(previous) = &self.previous else { return false };
+        match (previous, next) {
+            (_, Token::Comma | Token::Semicolon | Token::Dot | Token::Accesser | Token::CloseParen | Token::CloseBracket |
+                Token::Range | Token::RangeInclusive | Token::Increment | Token::Decrement) => false,
+            (Token::OpenParen | Token::OpenBracket | Token::Dot | Token::Accesser | Token::Range | Token::RangeInclusive | Token::Not, _) => false,
+            (Token::Identifier(_) | Token::Type(_) | Token::SelfIdent | Token::SelfType | Token::CloseParen | Token::CloseBracket,
+                Token::OpenParen | Token::OpenBracket) => false,
+            (Token::Select | Token::Wait, Token::OpenParen) => false, // select(player) and wait(20) are written like calls
+            (Token::OpenBrace, Token::CloseBrace) => false,
+            (_, Token::Less) if generic => false,
+            (Token::Less, _) | (_, Token::Greater) if self.generics > 0 => false,
+            (Token::Minus, _) if self.unary => false,
+            // struct Pair : Comparable, but x: Int
+            (_, Token::Colon) => matches!((&self.before_previous, previous), (Some(Token::StructDef), Token::Type(_))),
+            _ => true
+        }
+    }
+
+    // Writes text after any line breaks that are waiting
+    fn text(&mut self, text: &str, space: bool) {
+        if self.pending > 0 && !self.out.is_empty() {
+            // No blank lines straight after an opening brace or before a closing one
+            let closing = matches!(text, "}" | ")" | "]");
+            let lines = if closing || self.previous == Some(Token::OpenBrace) { 1 } else { self.pending };
+            self.out.push_str(&"\n".repeat(lines));
+            self.out.push_str(&INDENT.repeat(self.indent));
+        } else if space && !self.out.is_empty() {
+            self.out.push(' ');
+        }
+        self.pending = 0;
+        self.out.push_str(text);
+        if text == "," {
+            self.before_previous = self.previous.take();
+            self.previous = Some(Token::Comma);
+        } else {
+            self.before_previous = self.previous.clone();
+        }
+    }
+
+    // Comments from the trivia before a token. Ones that were at the end of a line stay there, and the others go on lines of
+    // their own, keeping a blank line before them if there was one.
+    fn comments(&mut self, trivia: &[Trivia]) {
+        let mut newlines = 0;
+        let mut wrote = false;
+        for piece in trivia {
+            let comment = match piece {
+                Trivia::Whitespace(s) => {
+                    newlines += s.matches('\n').count();
+                    continue;
+                }
+                Trivia::LineComment(c) | Trivia::BlockComment(c) => c
+            };
+            if newlines == 0 && !self.out.is_empty() {
+                self.out.push(' ');
+                self.out.push_str(comment);
+            } else {
+                self.pending = self.pending.max(if newlines >= 2 { 2 } else { 1 });
+                let (previous, before_previous) = (self.previous.clone(), self.before_previous.clone());
+                self.text(comment, true);
+                (self.previous, self.before_previous) = (previous, before_previous);
+            }
+            if let Trivia::LineComment(_) = piece {
+                self.line_comments += 1;
+                self.pending = self.pending.max(1);
+            }
+            newlines = 0;
+            wrote = true;
+        }
+        if wrote && newlines > 0 || self.pending > 0 && newlines >= 2 {
+            self.pending = self.pending.max(newlines.min(2));
+        }
+    }
+
+    // How long the line being written is
+    fn column(&self) -> usize {
+        self.out[self.out.rfind('\n').map_or(0, |i| i + 1)..].chars().count()
+    }
+}
+
+// Splits a block into its braces and the statements between them
+fn parts(block: &SyntaxNode) -> (&LosslessToken, Vec<&SyntaxNode>, &LosslessToken) {
+    let tokens: Vec<&LosslessToken> = block.tokens().collect();
+    let statements = block.children.iter().filter_map(|c| match c {
+        SyntaxElement::Node(n) => Some(n),
+        SyntaxElement::Token(_) => None
+    }).collect();
+    (tokens[0], statements, tokens[tokens.len() - 1])
+}
+
+// Whether a value can come after this token, making a - after it a negative sign rather than a subtraction
+fn before_value(token: &Token) -> bool {
+    !matches!(token, Token::Identifier(_) | Token::Type(_) | Token::Int(_) | Token::Num(_) | Token::String(_) |
+        Token::Interpolated(_) | Token::True | Token::False | Token::SelfIdent | Token::CloseParen | Token::CloseBracket |
+        Token::CloseBrace | Token::Increment | Token::Decrement)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::{Path, PathBuf}};
+    use super::{format_source, MAX_WIDTH};
+
+    fn format(input: &str) -> String {
+        format_source(input).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Every .fire file under a folder
+    fn fire_files(path: &Path, found: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(path).unwrap().map(|e| e.unwrap().path()) {
+            if entry.is_dir() {
+                fire_files(&entry, found);
+            } else if entry.extension().is_some_and(|e| e == "fire") {
+                found.push(entry);
+            }
+        }
+    }
+
+    #[test]
+    fn comma_after_arm() {
+        let input = "fn f(x: Int): Int {\n    match x {\n        3..=5 => return 4;, else => {}\n    }\n    return 0;\n}\n";
+        let formatted = format_source(input).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(formatted, "fn f(x: Int): Int {\n    match x {\n        3..=5 => return 4;\n        else => {}\n    }\n    return 0;\n}\n");
+        assert_eq!(format_source(&formatted).ok(), Some(formatted));
+    }
+
+    #[test]
+    fn comments() {
+        let input = "// Leading\nfn   f(a:Int):Int{ // Trailing\n    /* Block */ let x=a+1;\n        return x;// End\n}\n/* Last */";
+        assert_eq!(format(input), "// Leading\nfn f(a: Int): Int { // Trailing\n    /* Block */ let x = a + 1;\n    return x; // End\n}\n/* Last */\n");
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(format("fn f() { let list = [1, 2, 3]; }"), "fn f() {\n    let list = [1, 2, 3];\n}\n");
+        let numbers: Vec<String> = (1..=9).map(|n| n.to_string().repeat(10)).collect();
+        let formatted = format(&format!("fn f() {{ let list = [{}]; }}", numbers.join(", ")));
+        let items: String = numbers.iter().map(|n| format!("        {},\n", n)).collect();
+        assert_eq!(formatted, format!("fn f() {{\n    let list = [\n{}    ];\n}}\n", items));
+        assert!(formatted.lines().all(|l| l.len() <= MAX_WIDTH));
+    }
+
+    #[test]
+    fn idempotent() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut files = Vec::new();
+        fire_files(&root.join("tests"), &mut files);
+        fire_files(&root.join("std_lib"), &mut files);
+        let mut inputs: Vec<String> = files.iter().map(|f| fs::read_to_string(f).unwrap()).collect();
+        inputs.push("fn g() { broadcast(main::a::f(111111111, 222222222) + main::a::f(333333333, 444444444) + main::a::f(5, 6) + 1); }".to_string());
+        inputs.push("fn   f(a:Int,b:Int):Int{ // Trailing\n\n\n\n    if a>b{return a;}else{return -b;}\n}".to_string());
+        for input in inputs {
+            let once = format(&input);
+            assert_eq!(format(&once), once);
+        }
+    }
+}
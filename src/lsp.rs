@@ -0,0 +1,770 @@
+use std::{collections::{BTreeMap, HashMap}, fs, io::{self, BufRead, Write}, path::{Path, PathBuf}};
+
+use crate::{compiler::{self, CompileException, MemberUse}, files, formatter, json::{self, object, Json}, parser::{self, FireLocation, StructuredFireProject, UncompiledFireResource, UncompiledResourceType}, parser_pass_two, refactor::{declared, name_token, ProjectIndex, Reference, Rename, SourceFile}, tokenizer::{self, LosslessToken, Token, Trivia}};
+
+// The kinds of semantic tokens, in the order that their numbers refer to
+const TOKEN_TYPES: [&str; 11] = ["keyword", "variable", "function", "property", "type", "enumMember", "number", "string", "decorator", "operator", "comment"];
+
+// Functions that are built into the language, and how they're called
+const BUILTINS: [(&str, &str); 7] = [
+    ("broadcast", "broadcast(message)"),
+    ("sendMessage", "sendMessage(message)"),
+    ("selected", "selected(): Player"),
+    ("schedule", "schedule(ticks: Int, task: Fn())"),
+    ("subscribe", "subscribe(event, handler: Fn(event))"),
+    ("assert", "assert(condition: Boolean)"),
+    ("assertEq", "assertEq(expected, actual)")
+];
+
+const KEYWORDS: [&str; 29] = [
+    "fn", "pc", "struct", "enum", "trait", "impl", "group", "private", "internal", "const", "let", "select", "raise", "import",
+    "for", "in", "while", "if", "else", "match", "return", "try", "catch", "wait", "yield", "true", "false", "self", "Self"
+];
+
+// Runs a language server over stdin and stdout until the editor tells it to exit
+pub fn run() -> io::Result<()> {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut server = Server::default();
+    while let Some(message) = read_message(&mut input)? {
+        for reply in server.handle(&message) {
+            let text = reply.to_string();
+            write!(output, "Content-Length: {}\r\n\r\n{}", text.len(), text)?;
+            output.flush()?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Reads one message, which is a Content-Length header followed by that many bytes of JSON
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(n) = line.strip_prefix("Content-Length:") {
+                length = n.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(length) = length else { continue };
+        let mut body = vec![0; length];
+        input.read_exact(&mut body)?;
+        if let Some(message) = json::parse(&String::from_utf8_lossy(&body)) {
+            return Ok(Some(message));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<PathBuf, String>, // Files open in the editor, which may not be saved yet
+    exited: bool
+}
+
+impl Server {
+    // Answers a request or notification, giving back the response along with anything else to send
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or_default();
+        let params = message.get("params");
+        let id = message.get("id");
+        let document = params.get("textDocument");
+        let path = document.get("uri").as_str().and_then(uri_to_path);
+        let result = match method {
+            "initialize" => object(vec![
+                ("capabilities", object(vec![
+                    ("textDocumentSync", 1.into()), // The whole file is sent on every change
+                    ("definitionProvider", true.into()),
+                    ("hoverProvider", true.into()),
+                    ("completionProvider", object(vec![("triggerCharacters", vec![":".into(), ".".into()].into())])),
+                    ("documentSymbolProvider", true.into()),
+                    ("referencesProvider", true.into()),
+                    ("renameProvider", true.into()),
+                    ("semanticTokensProvider", object(vec![
+                        ("legend", object(vec![("tokenTypes", TOKEN_TYPES.iter().map(|t| (*t).into()).collect::<Vec<Json>>().into()), ("tokenModifiers", Json::Array(Vec::new()))])),
+                        ("full", true.into())
+                    ])),
+                    // Renaming a file or folder in the editor changes the paths that lead to it
+                    ("workspace", object(vec![("fileOperations", object(vec![("willRename", object(vec![("filters", vec![
+                        object(vec![("pattern", object(vec![("glob", "**/*.fire".into()), ("matches", "file".into())]))]),
+                        object(vec![("pattern", object(vec![("glob", "**".into()), ("matches", "folder".into())]))])
+                    ].into())]))]))]))
+                ])),
+                ("serverInfo", object(vec![("name", "fire_compiler".into())]))
+            ]),
+            "shutdown" => Json::Null,
+            "exit" => {
+                self.exited = true;
+                return Vec::new();
+            }
+            "textDocument/didOpen" | "textDocument/didChange" | "textDocument/didSave" | "textDocument/didClose" => {
+                let Some(path) = path else { return Vec::new() };
+                match method {
+                    "textDocument/didOpen" => {
+                        self.documents.insert(path.clone(), document.get("text").as_str().unwrap_or_default().to_string());
+                    }
+                    "textDocument/didChange" => {
+                        if let Some(text) = params.get("contentChanges").as_array().last().and_then(|c| c.get("text").as_str()) {
+                            self.documents.insert(path.clone(), text.to_string());
+                        }
+                    }
+                    "textDocument/didClose" => {
+                        self.documents.remove(&path);
+                    }
+                    _ => {}
+                }
+                return self.diagnostics(&path);
+            }
+            "workspace/willRenameFiles" => {
+                let renamed: Vec<(PathBuf, PathBuf)> = params.get("files").as_array().iter()
+                    .filter_map(|f| Some((uri_to_path(f.get("oldUri").as_str()?)?, uri_to_path(f.get("newUri").as_str()?)?)))
+                    .collect();
+                let Some(snapshot) = renamed.first().and_then(|(from, _)| self.snapshot(from)) else {
+                    return id_response(id, Json::Null);
+                };
+                let index = snapshot.index();
+                let mut edits = Rename { edits: Vec::new(), moves: Vec::new() };
+                for (from, to) in &renamed {
+                    // Anything that can't be renamed is left as it is, since the files are being renamed either way
+                    if let Ok(mut rename) = index.rename_path(from, to) {
+                        edits.edits.append(&mut rename.edits);
+                    }
+                }
+                snapshot.workspace_edit(&edits)
+            }
+            "textDocument/definition" | "textDocument/hover" | "textDocument/completion" | "textDocument/documentSymbol" | "textDocument/semanticTokens/full" |
+                "textDocument/references" | "textDocument/rename" => {
+                let snapshot = path.as_deref().and_then(|p| self.snapshot(p));
+                let found = snapshot.as_ref().zip(path.as_deref()).and_then(|(s, p)| Some((s, s.file(p)?)));
+                let Some((snapshot, file)) = found else {
+                    return id_response(id, Json::Null);
+                };
+                let position = params.get("position");
+                let at = from_lsp(&file.text, position);
+                match method {
+                    "textDocument/definition" => snapshot.definition(file, at),
+                    "textDocument/hover" => snapshot.hover(file, at),
+                    "textDocument/completion" => snapshot.completion(file, at),
+                    "textDocument/documentSymbol" => snapshot.symbols(file),
+                    "textDocument/references" => {
+                        let index = snapshot.index();
+                        let declarations = params.get("context").get("includeDeclaration") != &Json::Bool(false);
+                        match index.target_at(&file.path, at.0, at.1) {
+                            Some(target) => index.references(target).iter()
+                                .filter(|r| declarations || !r.declaration)
+                                .filter_map(|r| snapshot.location(r))
+                                .collect::<Vec<Json>>().into(),
+                            None => Json::Null
+                        }
+                    }
+                    "textDocument/rename" => {
+                        let index = snapshot.index();
+                        let new_name = params.get("newName").as_str().unwrap_or_default();
+                        // Renaming needs to know where every field and method is used, which takes a project that compiles
+                        let renamed = match (&snapshot.error, index.target_at(&file.path, at.0, at.1)) {
+                            (Some(_), _) => Err("Fix the errors in the project before renaming".to_string()),
+                            (None, None) => Err("There is nothing here to rename".to_string()),
+                            (None, Some(target)) => index.rename(target, new_name).map_err(|e| e.to_string())
+                        };
+                        match renamed {
+                            Ok(rename) => snapshot.workspace_edit(&rename),
+                            Err(message) => return error_response(id, -32803, message)
+                        }
+                    }
+                    _ => semantic_tokens(&file.text)
+                }
+            }
+            _ if id.is_null() => return Vec::new(), // Notifications that aren't needed
+            _ => return error_response(id, -32601, format!("{} is not supported", method))
+        };
+        id_response(id, result)
+    }
+
+    // Compiles the project a file is in and reports the error, if there is one, clearing it from every other file
+    fn diagnostics(&self, path: &Path) -> Vec<Json> {
+        let Some(snapshot) = self.snapshot(path) else { return Vec::new() };
+        let mut found = BTreeMap::new();
+        if let Some((file, e)) = &snapshot.error {
+            let file = file.as_ref().and_then(|f| snapshot.file(f)).or_else(|| snapshot.error_file(e)).or_else(|| snapshot.file(path));
+            if let Some(file) = file {
+                let (line, column) = e.position().unwrap_or((1, 1));
+                let mut inner = e;
+                while let CompileException::FileException(_, e) | CompileException::InResource(_, e) = inner {
+                    inner = e;
+                }
+                let diagnostic = object(vec![
+                    ("range", token_range(&file.text, line, column)),
+                    ("severity", 1.into()),
+                    ("source", "fire".into()),
+                    ("message", inner.to_string().into())
+                ]);
+                found.insert(file.path.clone(), vec![diagnostic]);
+            }
+        }
+        snapshot.files.iter().map(|f| object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            ("params", object(vec![
+                ("uri", path_to_uri(&f.path).into()),
+                ("diagnostics", found.remove(&f.path).unwrap_or_default().into())
+            ]))
+        ])).collect()
+    }
+
+    // Reads the project that a file is in, using what's open in the editor over what's saved
+    fn snapshot(&self, path: &Path) -> Option<Snapshot> {
+        let root = path.ancestors().find(|a| a.file_name().is_some_and(|n| n == "src"))?.parent()?;
+        let mut project = StructuredFireProject::new();
+        let mut error = None;
+        let mut files = Vec::new();
+        for file in files::get_all_project_files(root) {
+            let path = file.path().to_path_buf();
+            let text = self.documents.get(&path).cloned().or_else(|| fs::read_to_string(&path).ok()).unwrap_or_default();
+            let structured = tokenizer::tokenize(&text).and_then(|tokens| parser::structure_file(&mut project, file.location(), tokens));
+            if let Err(e) = structured {
+                error.get_or_insert((Some(path.clone()), e));
+            }
+            files.push(SourceFile { location: file.location().clone(), path, text });
+        }
+        let mut members = Vec::new();
+        if error.is_none() {
+            let compiled = project.register_implementations()
+                .and_then(|_| parser_pass_two::parse_project(&project))
+                .and_then(|parsed| compiler::member_uses(&parsed));
+            match compiled {
+                Ok(found) => members = found,
+                Err(e) => error = Some((None, e))
+            }
+        }
+        Some(Snapshot { files, project, members, error })
+    }
+}
+
+fn id_response(id: &Json, result: Json) -> Vec<Json> {
+    vec![object(vec![("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)])]
+}
+
+fn error_response(id: &Json, code: i32, message: String) -> Vec<Json> {
+    vec![object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        ("error", object(vec![("code", Json::Number(code as f64)), ("message", message.into())]))
+    ])]
+}
+
+// A project as it is right now, along with the first error in it
+struct Snapshot {
+    files: Vec<SourceFile>,
+    project: StructuredFireProject,
+    members: Vec<MemberUse>, // Only known once the project compiles
+    error: Option<(Option<PathBuf>, CompileException)>
+}
+
+// What a name in a file refers to
+enum Target<'a> {
+    Resource(&'a UncompiledFireResource),
+    File(&'a SourceFile),
+    Local(usize, usize, String), // Where a local or parameter was declared
+    Builtin(&'static str)
+}
+
+impl Snapshot {
+    fn index(&self) -> ProjectIndex<'_> {
+        ProjectIndex::new(&self.project, &self.files, &self.members)
+    }
+
+    fn file(&self, path: &Path) -> Option<&SourceFile> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    fn file_at(&self, location: &FireLocation) -> Option<&SourceFile> {
+        self.files.iter().find(|f| f.location == *location)
+    }
+
+    // The file that a resource was written in
+    fn source(&self, res: &UncompiledFireResource) -> Option<&SourceFile> {
+        self.file_at(self.project.source_of(&res.loc)?)
+    }
+
+    // The file an error happened in, from the resource it happened inside of
+    fn error_file(&self, e: &CompileException) -> Option<&SourceFile> {
+        match e {
+            CompileException::InResource(loc, _) | CompileException::DuplicateResource(loc) => {
+                self.project.get_resource(loc).and_then(|r| self.source(r)).or_else(|| self.file_at(loc))
+            }
+            _ => None
+        }
+    }
+
+    // The resources written in a file, widest first
+    fn resources_in(&self, file: &SourceFile) -> Vec<&UncompiledFireResource> {
+        let mut found: Vec<&UncompiledFireResource> = self.project.resources()
+            .filter(|r| !r.tokens.is_empty() && self.project.source_of(&r.loc) == Some(&file.location))
+            .collect();
+        found.sort_by_key(|r| (r.tokens[0].line, r.tokens[0].column, std::cmp::Reverse(r.tokens.len())));
+        found
+    }
+
+    // The innermost resource around a position, if any
+    fn resource_at(&self, file: &SourceFile, at: (usize, usize)) -> Option<&UncompiledFireResource> {
+        self.resources_in(file).into_iter().rev().find(|r| {
+            let (first, last) = (&r.tokens[0], &r.tokens[r.tokens.len() - 1]);
+            (first.line, first.column) <= at && at <= (last.line, last.column)
+        })
+    }
+
+    // Where names written at a position are looked up from, which is the file or the group around it
+    fn scope_at(&self, file: &SourceFile, at: (usize, usize)) -> FireLocation {
+        match self.resource_at(file, at) {
+            Some(r) if matches!(r.resource_type, UncompiledResourceType::Group) => r.loc.clone(),
+            Some(r) => r.file.clone(),
+            None => file.location.clone()
+        }
+    }
+
+    // Finds what the name at a position refers to, along with the path written there
+    fn lookup(&self, file: &SourceFile, at: (usize, usize)) -> Option<Target<'_>> {
+        let (tokens, _) = tokenizer::tokenize_lossless(&file.text).ok()?;
+        let i = tokens.iter().rposition(|t| contains(t, at))?;
+        let (Token::Identifier(name) | Token::Type(name)) = &tokens[i].token.token else { return None };
+        // Take in the rest of a path such as main::things::Thing, up to the name being looked at
+        let mut start = i;
+        while start >= 2 && tokens[start - 1].token.token == Token::Accesser && matches!(tokens[start - 2].token.token, Token::Identifier(_) | Token::Type(_)) {
+            start -= 2;
+        }
+        let path: Vec<String> = tokens[start..=i].iter().step_by(2).map(|t| t.text.clone()).collect();
+        if start > 0 && tokens[start - 1].token.token == Token::Dot {
+            // Methods need the types that compiling finds
+            let index = self.index();
+            return index.target_at(&file.path, tokens[i].token.line, tokens[i].token.column)
+                .and_then(|loc| self.project.get_resource(loc))
+                .map(Target::Resource);
+        }
+        if path.len() == 1 {
+            if let Some(local) = self.local(file, at, name) {
+                return Some(local);
+            }
+            if let Some((builtin, _)) = BUILTINS.iter().find(|(b, _)| b == name) {
+                return Some(Target::Builtin(builtin));
+            }
+        }
+        let scope = self.scope_at(file, at);
+        if let Some(res) = self.project.resolve(&scope, &path).and_then(|loc| self.project.get_resource(&loc)) {
+            return Some(Target::Resource(res));
+        }
+        self.file_at(&FireLocation::from_path(&path)).map(Target::File)
+    }
+
+    // Finds where a local or parameter was declared, looking back from a position inside of a function
+    fn local(&self, file: &SourceFile, at: (usize, usize), name: &str) -> Option<Target<'_>> {
+        let res = self.resource_at(file, at).filter(|r| matches!(r.resource_type, UncompiledResourceType::Function | UncompiledResourceType::Method | UncompiledResourceType::Process))?;
+        let before = res.tokens.iter().take_while(|t| (t.line, t.column) <= at).count();
+        (0..before).rev()
+            .find(|i| declared(&res.tokens, *i) == Some(name))
+            .map(|i| Target::Local(res.tokens[i].line, res.tokens[i].column, name.to_string()))
+    }
+
+    // Where a reference is, as an LSP Location
+    fn location(&self, r: &Reference) -> Option<Json> {
+        let file = self.file(&r.path)?;
+        Some(object(vec![
+            ("uri", path_to_uri(&r.path).into()),
+            ("range", object(vec![("start", to_lsp(&file.text, r.line, r.column)), ("end", to_lsp(&file.text, r.line, r.column + r.length))]))
+        ]))
+    }
+
+    // The edits of a rename for the editor to make. Files and folders are renamed after their text is changed.
+    fn workspace_edit(&self, rename: &Rename) -> Json {
+        let mut changes: BTreeMap<&Path, Vec<Json>> = BTreeMap::new();
+        for (r, text) in &rename.edits {
+            if let Some(location) = self.location(r) {
+                changes.entry(&r.path).or_default().push(object(vec![("range", location.get("range").clone()), ("newText", text.as_str().into())]));
+            }
+        }
+        if rename.moves.is_empty() {
+            return object(vec![("changes", Json::Object(changes.into_iter().map(|(path, edits)| (path_to_uri(path), edits.into())).collect()))]);
+        }
+        let mut document_changes: Vec<Json> = changes.into_iter().map(|(path, edits)| object(vec![
+            ("textDocument", object(vec![("uri", path_to_uri(path).into()), ("version", Json::Null)])),
+            ("edits", edits.into())
+        ])).collect();
+        for (from, to) in &rename.moves {
+            document_changes.push(object(vec![("kind", "rename".into()), ("oldUri", path_to_uri(from).into()), ("newUri", path_to_uri(to).into())]));
+        }
+        object(vec![("documentChanges", document_changes.into())])
+    }
+
+    fn definition(&self, file: &SourceFile, at: (usize, usize)) -> Json {
+        let (target, line, column, name) = match self.lookup(file, at) {
+            Some(Target::Resource(res)) => {
+                let Some(source) = self.source(res) else { return Json::Null };
+                let (line, column) = name_token(res).map_or((res.tokens[0].line, res.tokens[0].column), |t| (t.line, t.column));
+                (source, line, column, res.loc.last().cloned().unwrap_or_default())
+            }
+            Some(Target::File(f)) => (f, 1, 1, String::new()),
+            Some(Target::Local(line, column, name)) => (file, line, column, name),
+            _ => return Json::Null
+        };
+        let end = column + name.chars().count();
+        object(vec![
+            ("uri", path_to_uri(&target.path).into()),
+            ("range", object(vec![("start", to_lsp(&target.text, line, column)), ("end", to_lsp(&target.text, line, end))]))
+        ])
+    }
+
+    fn hover(&self, file: &SourceFile, at: (usize, usize)) -> Json {
+        let text = match self.lookup(file, at) {
+            Some(Target::Resource(res)) => {
+                let mut text = format!("```fire\n{}\n```\n{}", self.signature(res), res.loc);
+                if let Some(docs) = self.docs(res) {
+                    text = format!("{}\n\n{}", text, docs);
+                }
+                text
+            }
+            Some(Target::File(f)) => format!("```fire\n{}\n```\nFile {}", f.location, f.path.display()),
+            Some(Target::Local(line, _, name)) => format!("```fire\n{}\n```\nDeclared on line {}", name, line),
+            Some(Target::Builtin(name)) => format!("```fire\n{}\n```\nBuilt into Fire", BUILTINS.iter().find(|(b, _)| *b == name).unwrap().1),
+            None => return Json::Null
+        };
+        object(vec![("contents", object(vec![("kind", "markdown".into()), ("value", text.into())]))])
+    }
+
+    // How a resource was declared, up to its body, such as fn add(a: Int, b: Int): Int
+    fn signature(&self, res: &UncompiledFireResource) -> String {
+        let mut header = Vec::new();
+        for t in &res.tokens {
+            if matches!(t.token, Token::OpenBrace | Token::Assign | Token::Semicolon) && header.len() > 1 {
+                break;
+            }
+            header.push(token_text(&t.token));
+        }
+        let annotations: String = res.modifiers.annotations.iter().map(|a| format!("@{}\n", a)).collect();
+        let header = header.join(" ");
+        let formatted = formatter::format_source(&header).map(|f| f.trim().to_string()).unwrap_or(header);
+        format!("{}{}", annotations, formatted)
+    }
+
+    // The // comments on the lines right above a resource
+    fn docs(&self, res: &UncompiledFireResource) -> Option<String> {
+        let source = self.source(res)?;
+        let (tokens, _) = tokenizer::tokenize_lossless(&source.text).ok()?;
+        let mut index = tokens.iter().position(|t| t.token == res.tokens[0])?;
+        while index > 0 && matches!(tokens[index - 1].token.token, Token::Annotation(_) | Token::Private | Token::Internal) {
+            index -= 1;
+        }
+        let mut lines = Vec::new();
+        for trivia in tokens[index].leading.iter().rev() {
+            match trivia {
+                Trivia::Whitespace(w) if w.matches('\n').count() > 1 => break,
+                Trivia::LineComment(c) => lines.push(c.trim_start_matches('/').trim().to_string()),
+                _ => {}
+            }
+        }
+        lines.reverse();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    fn completion(&self, file: &SourceFile, at: (usize, usize)) -> Json {
+        let line = file.text.lines().nth(at.0 - 1).unwrap_or_default();
+        let before: String = line.chars().take(at.1 - 1).collect();
+        let partial = before.len() - before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_').len();
+        let mut written = &before[..before.len() - partial];
+        // A path such as main::things:: lists what's inside of it
+        let mut path = Vec::new();
+        while let Some(rest) = written.strip_suffix("::") {
+            let segment = rest.len() - rest.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_').len();
+            path.insert(0, rest[rest.len() - segment..].to_string());
+            written = &rest[..rest.len() - segment];
+        }
+        let mut items = BTreeMap::new();
+        if !path.is_empty() {
+            let scope = self.scope_at(file, at);
+            let base = self.project.resolve(&scope, &path).unwrap_or_else(|| FireLocation::from_path(&path));
+            let locations = self.project.resources().map(|r| &r.loc).chain(self.files.iter().map(|f| &f.location));
+            for loc in locations.filter(|l| l.segments().len() > base.segments().len() && l.segments().starts_with(base.segments())) {
+                let name = &loc.segments()[base.segments().len()];
+                let kind = match self.project.get_resource(&base.with(name.clone())) {
+                    Some(res) if matches!(res.resource_type, UncompiledResourceType::Implementation(_)) => continue,
+                    Some(res) => completion_kind(&res.resource_type),
+                    None => 9 // A folder or file
+                };
+                items.insert(name.clone(), kind);
+            }
+        } else {
+            let scope = self.scope_at(file, at);
+            for res in self.project.resources() {
+                let visible = res.loc.parent() == scope || self.project.get_resource(&res.loc.parent()).is_none() && res.loc.parent() == file.location;
+                if visible && !matches!(res.resource_type, UncompiledResourceType::Implementation(_)) {
+                    items.insert(res.loc.last().cloned().unwrap_or_default(), completion_kind(&res.resource_type));
+                }
+            }
+            for f in &self.files {
+                items.entry(f.location.segments()[0].clone()).or_insert(9);
+            }
+            for (import_scope, import) in self.project.imports() {
+                if let (Some(name), true) = (&import.name, scope.segments().starts_with(import_scope.segments())) {
+                    items.entry(name.clone()).or_insert(9);
+                }
+            }
+            if let Some(res) = self.resource_at(file, at) {
+                for i in (0..res.tokens.len()).filter(|i| (res.tokens[*i].line, res.tokens[*i].column) < at) {
+                    if let Some(name) = declared(&res.tokens, i) {
+                        items.entry(name.to_string()).or_insert(6);
+                    }
+                }
+            }
+            for (builtin, _) in BUILTINS {
+                items.insert(builtin.to_string(), 3);
+            }
+            for keyword in KEYWORDS {
+                items.insert(keyword.to_string(), 14);
+            }
+        }
+        items.into_iter().map(|(label, kind)| object(vec![("label", label.into()), ("kind", kind.into())])).collect::<Vec<Json>>().into()
+    }
+
+    // The fn, pc, struct, trait and enum declarations of a file, with what's declared inside of them
+    fn symbols(&self, file: &SourceFile) -> Json {
+        let resources: Vec<&UncompiledFireResource> = self.resources_in(file).into_iter()
+            .filter(|r| symbol_kind(&r.resource_type).is_some())
+            .collect();
+        nest_symbols(&resources, &file.text).into()
+    }
+}
+
+// Turns resources, sorted by where they start, into symbols with the ones inside of each as its children
+fn nest_symbols(resources: &[&UncompiledFireResource], text: &str) -> Vec<Json> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < resources.len() {
+        let res = resources[i];
+        let last = &res.tokens[res.tokens.len() - 1];
+        let inside = resources[i + 1..].iter().take_while(|r| (r.tokens[0].line, r.tokens[0].column) <= (last.line, last.column)).count();
+        let name = name_token(res).unwrap_or(&res.tokens[0]);
+        let name_length = res.loc.last().map_or(0, |n| n.chars().count());
+        out.push(object(vec![
+            ("name", res.loc.last().cloned().unwrap_or_default().into()),
+            ("kind", symbol_kind(&res.resource_type).unwrap_or(13).into()),
+            ("range", object(vec![
+                ("start", to_lsp(text, res.tokens[0].line, res.tokens[0].column)),
+                ("end", to_lsp(text, last.line, last.column + token_text(&last.token).chars().count()))
+            ])),
+            ("selectionRange", object(vec![
+                ("start", to_lsp(text, name.line, name.column)),
+                ("end", to_lsp(text, name.line, name.column + name_length))
+            ])),
+            ("children", nest_symbols(&resources[i + 1..i + 1 + inside], text).into())
+        ]));
+        i += 1 + inside;
+    }
+    out
+}
+
+// Whether a position is inside of a token, or right at its end, where the cursor is after typing it
+fn contains(token: &LosslessToken, at: (usize, usize)) -> bool {
+    let (line, column) = (token.token.line, token.token.column);
+    at.0 == line && column <= at.1 && at.1 <= column + token.text.chars().count()
+}
+
+// How a token is written, for rebuilding the header of a resource from its tokens
+fn token_text(token: &Token) -> String {
+    let text = match token {
+        Token::Identifier(s) | Token::Type(s) => return s.clone(),
+        Token::Annotation(s) => return format!("@{}", s),
+        Token::String(s) => return format!("{:?}", s),
+        Token::Int(n) => return n.to_string(),
+        Token::Num(n) => return n.to_string(),
+        Token::Interpolated(_) => "\"...\"",
+        Token::Plus => "+", Token::Minus => "-", Token::Multiply => "*", Token::Divide => "/", Token::Mod => "%",
+        Token::And => "&", Token::Or => "|", Token::Not => "!", Token::Xor => "^", Token::StrictAnd => "&&", Token::StrictOr => "||",
+        Token::Assign => "=", Token::PlusAssign => "+=", Token::MinusAssign => "-=", Token::MultiplyAssign => "*=",
+        Token::DivideAssign => "/=", Token::ModAssign => "%=", Token::Increment => "++", Token::Decrement => "--",
+        Token::Equals => "==", Token::NotEqual => "!=", Token::Greater => ">", Token::Less => "<", Token::GreaterEqual => ">=",
+        Token::LessEqual => "<=", Token::Colon => ":", Token::Semicolon => ";", Token::Comma => ",", Token::OpenParen => "(",
+        Token::CloseParen => ")", Token::OpenBrace => "{", Token::CloseBrace => "}", Token::OpenBracket => "[",
+        Token::CloseBracket => "]", Token::Dot => ".", Token::Accesser => "::", Token::Range => "..", Token::RangeInclusive => "..=",
+        Token::True => "true", Token::False => "false", Token::FuncDef => "fn", Token::ProcDef => "pc", Token::StructDef => "struct",
+        Token::EnumDef => "enum", Token::TraitDef => "trait", Token::Implementation => "impl", Token::GroupDef => "group",
+        Token::Private => "private", Token::Internal => "internal", Token::Const => "const", Token::Let => "let",
+        Token::Select => "select", Token::Raise => "raise", Token::Import => "import", Token::FatArrow => "=>",
+        Token::SelfIdent => "self", Token::SelfType => "Self", Token::For => "for", Token::While => "while", Token::If => "if",
+        Token::In => "in", Token::Else => "else", Token::Match => "match", Token::Return => "return", Token::Try => "try",
+        Token::Catch => "catch", Token::Wait => "wait", Token::Yield => "yield"
+    };
+    text.to_string()
+}
+
+// LSP's CompletionItemKind
+fn completion_kind(resource_type: &UncompiledResourceType) -> usize {
+    match resource_type {
+        UncompiledResourceType::Function | UncompiledResourceType::Process => 3,
+        UncompiledResourceType::Method | UncompiledResourceType::AbstractMethod => 2,
+        UncompiledResourceType::Struct => 22,
+        UncompiledResourceType::Trait => 8,
+        UncompiledResourceType::Enum => 13,
+        UncompiledResourceType::EnumConst => 20,
+        UncompiledResourceType::Var => 6,
+        UncompiledResourceType::Const => 21,
+        UncompiledResourceType::Group | UncompiledResourceType::Implementation(_) => 9
+    }
+}
+
+// LSP's SymbolKind, or None for what isn't shown in the outline of a file
+fn symbol_kind(resource_type: &UncompiledResourceType) -> Option<usize> {
+    Some(match resource_type {
+        UncompiledResourceType::Function | UncompiledResourceType::Process => 12,
+        UncompiledResourceType::Method | UncompiledResourceType::AbstractMethod => 6,
+        UncompiledResourceType::Struct => 23,
+        UncompiledResourceType::Trait => 11,
+        UncompiledResourceType::Enum => 10,
+        UncompiledResourceType::EnumConst => 22,
+        UncompiledResourceType::Var => 13,
+        UncompiledResourceType::Const => 14,
+        UncompiledResourceType::Group => 3,
+        UncompiledResourceType::Implementation(_) => return None
+    })
+}
+
+// Colors every token of a file. Each token is five numbers: the lines since the last token, its column (counted from the last
+// token if it's on the same line), its length, its type and its modifiers, with columns and lengths in UTF-16.
+fn semantic_tokens(text: &str) -> Json {
+    let Ok((tokens, trailing)) = tokenizer::tokenize_lossless(text) else { return Json::Null };
+    let mut pieces = Vec::new(); // (line, column, text, type), with 0-based lines and UTF-16 columns
+    let (mut line, mut column) = (0, 0);
+    let mut add = |piece: &str, kind: Option<usize>, pieces: &mut Vec<(usize, usize, usize, usize)>| {
+        for (i, part) in piece.split('\n').enumerate() {
+            if i > 0 {
+                line += 1;
+                column = 0;
+            }
+            let length = part.encode_utf16().count();
+            if let Some(kind) = kind.filter(|_| length > 0) {
+                pieces.push((line, column, length, kind));
+            }
+            column += length;
+        }
+    };
+    let mut previous: Option<&Token> = None;
+    for (i, t) in tokens.iter().enumerate() {
+        for trivia in &t.leading {
+            let kind = match trivia {
+                Trivia::Whitespace(_) => None,
+                _ => Some(10)
+            };
+            add(trivia.text(), kind, &mut pieces);
+        }
+        let next = tokens.get(i + 1).map(|n| &n.token.token);
+        add(&t.text, token_kind(&t.token.token, previous, next), &mut pieces);
+        previous = Some(&t.token.token);
+    }
+    for trivia in &trailing {
+        let kind = match trivia {
+            Trivia::Whitespace(_) => None,
+            _ => Some(10)
+        };
+        add(trivia.text(), kind, &mut pieces);
+    }
+    let mut data = Vec::new();
+    let (mut last_line, mut last_column) = (0, 0);
+    for (line, column, length, kind) in pieces {
+        let delta_column = if line == last_line { column - last_column } else { column };
+        data.extend([line - last_line, delta_column, length, kind, 0].map(Json::from));
+        (last_line, last_column) = (line, column);
+    }
+    object(vec![("data", data.into())])
+}
+
+// Which of TOKEN_TYPES a token is colored as
+fn token_kind(token: &Token, previous: Option<&Token>, next: Option<&Token>) -> Option<usize> {
+    Some(match token {
+        Token::Identifier(_) if previous == Some(&Token::Dot) && next != Some(&Token::OpenParen) => 3,
+        Token::Identifier(_) if matches!(previous, Some(Token::FuncDef | Token::ProcDef)) || next == Some(&Token::OpenParen) => 2,
+        Token::Identifier(_) | Token::SelfIdent => 1,
+        Token::Type(_) if previous == Some(&Token::Accesser) && next != Some(&Token::Accesser) && next != Some(&Token::OpenBrace) && next != Some(&Token::OpenParen) => 5,
+        Token::Type(_) | Token::SelfType => 4,
+        Token::Int(_) | Token::Num(_) => 6,
+        Token::String(_) | Token::Interpolated(_) => 7,
+        Token::Annotation(_) => 8,
+        Token::True | Token::False | Token::FuncDef | Token::ProcDef | Token::StructDef | Token::EnumDef | Token::TraitDef |
+        Token::Implementation | Token::GroupDef | Token::Private | Token::Internal | Token::Const | Token::Let | Token::Select |
+        Token::Raise | Token::Import | Token::For | Token::While | Token::If | Token::In | Token::Else | Token::Match |
+        Token::Return | Token::Try | Token::Catch | Token::Wait | Token::Yield => 0,
+        Token::OpenParen | Token::CloseParen | Token::OpenBrace | Token::CloseBrace | Token::OpenBracket | Token::CloseBracket |
+        Token::Comma | Token::Semicolon | Token::Colon | Token::Dot | Token::Accesser => return None,
+        _ => 9
+    })
+}
+
+// Turns a file:// URI into a path, decoding any %XX in it
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::new();
+    let mut iter = encoded.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex: String = iter.by_ref().take(2).map(char::from).collect();
+            bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // file:///C:/project on Windows
+    let path = match path.strip_prefix('/') {
+        Some(rest) if rest.get(1..2) == Some(":") => rest.to_string(),
+        _ => path
+    };
+    Some(PathBuf::from(path))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    let path = path.to_string_lossy().replace('\\', "/");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => uri.push(b as char),
+            b => uri.push_str(&format!("%{:02X}", b))
+        }
+    }
+    uri
+}
+
+// Turns a 1-based line and column in characters, as Fire counts them, into an LSP position: 0-based and in UTF-16 units
+fn to_lsp(text: &str, line: usize, column: usize) -> Json {
+    let written = text.lines().nth(line.saturating_sub(1)).unwrap_or_default();
+    let character: usize = written.chars().take(column.saturating_sub(1)).map(char::len_utf16).sum();
+    object(vec![("line", line.saturating_sub(1).into()), ("character", character.into())])
+}
+
+// Turns an LSP position back into a Fire line and column
+fn from_lsp(text: &str, position: &Json) -> (usize, usize) {
+    let line = position.get("line").as_usize().unwrap_or(0);
+    let character = position.get("character").as_usize().unwrap_or(0);
+    let written = text.lines().nth(line).unwrap_or_default();
+    let mut units = 0;
+    let column = written.chars().take_while(|c| {
+        units += c.len_utf16();
+        units <= character
+    }).count();
+    (line + 1, column + 1)
+}
+
+// The range of the token at a position, or of the rest of the line if there isn't one
+fn token_range(text: &str, line: usize, column: usize) -> Json {
+    let length = tokenizer::tokenize_lossless(text).ok()
+        .and_then(|(tokens, _)| tokens.into_iter().find(|t| (t.token.line, t.token.column) == (line, column)))
+        .map_or(1, |t| t.text.chars().count());
+    object(vec![("start", to_lsp(text, line, column)), ("end", to_lsp(text, line, column + length))])
+}
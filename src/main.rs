@@ -1,13 +1,78 @@
-use std::{env, path::Path, fmt::Display};
+use std::{env, ffi::OsStr, fmt::Display, fs, io, path::{Path, PathBuf}, process};
+use compiler::CompileException;
 mod files;
 mod parser;
 mod tokenizer;
 mod compiler;
+mod parser_pass_two;
+mod syntax;
+mod formatter;
+mod json;
+mod lsp;
+mod refactor;
+mod simulator;
+mod testing;
 
 fn main() {
     let mut args = env::args_os();
     args.next(); // Skip the first argument
     let arg = &args.next();
+    // fire_compiler lsp runs a language server for editors, talking over stdin and stdout
+    if arg.as_deref() == Some(OsStr::new("lsp")) {
+        if let Err(e) = lsp::run() {
+            eprintln!("The language server stopped: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    // fire_compiler format <project> formats every file, or only lists the ones that aren't formatted with --check
+    if arg.as_deref() == Some(OsStr::new("format")) {
+        let Some(path) = args.next() else {
+            println!("Please provide the location of the Fire project to format.");
+            return;
+        };
+        let check = args.next().as_deref() == Some(OsStr::new("--check"));
+        process::exit(format_command(Path::new(&path), check));
+    }
+    // fire_compiler test <project> runs every @Test in a simulator, failing if any of them do
+    if arg.as_deref() == Some(OsStr::new("test")) {
+        let Some(path) = args.next() else {
+            println!("Please provide the location of the Fire project to test.");
+            return;
+        };
+        match testing::run_tests(Path::new(&path)) {
+            Ok(results) => {
+                let failed = results.iter().filter(|r| r.failure.is_some()).count();
+                for result in &results {
+                    match &result.failure {
+                        Some(reason) => println!("{} failed. {}", result.test, reason),
+                        None => println!("{} passed.", result.test)
+                    }
+                }
+                println!("{} passed, {} failed.", results.len() - failed, failed);
+                if failed > 0 {
+                    process::exit(1);
+                }
+            }
+            Err(d) => {
+                println!("{}", d);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    // fire_compiler simulate <project> compiles a project, then runs a script from stdin against it without the game
+    if arg.as_deref() == Some(OsStr::new("simulate")) {
+        let Some(path) = args.next() else {
+            println!("Please provide the location of the Fire project to simulate.");
+            return;
+        };
+        if let Err(d) = simulate_project(Path::new(&path)) {
+            println!("{}", d);
+            process::exit(1);
+        }
+        return;
+    }
     let path = match arg {
         Some(n) => Path::new(n),
         None => {
@@ -16,16 +81,120 @@ fn main() {
         }
     };
     match compile_project_normal(path) {
-        Ok(_) => todo!(),
+        Ok(d) => println!("Compiled {} functions into {:?}.", d.functions.len(), path.join("target")),
         Err(d) => println!("{}", d)
     }
 }
 
 // Compile a normal project
-fn compile_project_normal(path: &Path) -> Result<crate::compiler::CompiledCommands, Box<dyn Display>> {
+fn compile_project_normal(path: &Path) -> Result<crate::compiler::CompiledDatapack, Box<dyn Display>> {
     if let Err(r) = files::verify_project_format(path) {
         return Err(Box::new(r));
     }
-    println!("{:?}", files::get_all_project_files(path));
-    todo!();
-}
\ No newline at end of file
+    let mut project = parser::StructuredFireProject::new();
+    for file in files::get_all_project_files(path) {
+        project = file.structure_into(project)?;
+    }
+    project.register_implementations()?;
+    let parsed = parser_pass_two::parse_project(&project)?;
+    for warning in project.unused_imports() {
+        println!("Warning: {}", warning);
+    }
+    let datapack = compiler::compile_project(&parsed, &files::project_namespace(path))?;
+    files::write_datapack(path, &datapack)?;
+    Ok(datapack)
+}
+
+// Compiles a project and loads what was written to its target folder, running each line of stdin as a simulation script.
+// Chat is printed as it's sent, and a line that fails stops the simulation.
+fn simulate_project(path: &Path) -> Result<(), Box<dyn Display>> {
+    compile_project_normal(path)?;
+    let mut simulator = simulator::Simulator::load(&path.join("target"))?;
+    simulator.reload()?;
+    let mut shown = 0;
+    for (i, line) in io::stdin().lines().enumerate() {
+        let line = line.map_err(|e| Box::new(e.to_string()) as Box<dyn Display>)?;
+        let printed = simulator.script(&line).map_err(|e| Box::new(format!("Line {}: {}", i + 1, e)) as Box<dyn Display>);
+        for message in &simulator.chat()[shown..] {
+            println!("[{}] {}", message.recipients.join(", "), message.text);
+        }
+        shown = simulator.chat().len();
+        if let Some(text) = printed? {
+            println!("{}", text);
+        }
+    }
+    Ok(())
+}
+
+// Runs the format mode, giving its exit status: 1 if it failed, or if checking found files that aren't formatted
+fn format_command(path: &Path, check: bool) -> i32 {
+    match format_project(path, check) {
+        Ok(files) if check && !files.is_empty() => {
+            for file in files {
+                println!("{:?} is not formatted.", file);
+            }
+            1
+        }
+        Ok(_) if check => {
+            println!("Every file is formatted.");
+            0
+        }
+        Ok(files) => {
+            println!("Formatted {} files.", files.len());
+            0
+        }
+        Err(d) => {
+            println!("{}", d);
+            1
+        }
+    }
+}
+
+// Formats the files of a project, returning the ones that changed. With check, nothing is written.
+fn format_project(path: &Path, check: bool) -> Result<Vec<PathBuf>, Box<dyn Display>> {
+    if !path.join("src").is_dir() {
+        return Err(Box::new(files::ProjectStructureException::NoSrc));
+    }
+    let mut changed = Vec::new();
+    for file in files::get_all_project_files(path) {
+        let text = file.read_all()?;
+        let formatted = formatter::format_source(&text).map_err(|e| CompileException::FileException(file.path().to_path_buf(), Box::new(e)))?;
+        if formatted != text {
+            if !check {
+                fs::write(file.path(), &formatted).map_err(|_| CompileException::NoFileAccess(file.path().to_path_buf()))?;
+            }
+            changed.push(file.path().to_path_buf());
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+    use super::format_command;
+
+    // A project in a folder of its own with a single file
+    fn project(name: &str, source: &str) -> (PathBuf, PathBuf) {
+        let path = env::temp_dir().join(format!("fire_{}_{}", name, std::process::id()));
+        let file = path.join("src/main/main.fire");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, source).unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn format_check_status() {
+        let (formatted, _) = project("formatted", "fn f() {\n    return;\n}\n");
+        assert_eq!(format_command(&formatted, true), 0);
+        let (unformatted, file) = project("unformatted", "fn f(){return;}");
+        assert_eq!(format_command(&unformatted, true), 1);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fn f(){return;}");
+        assert_eq!(format_command(&unformatted, false), 0);
+        assert_eq!(format_command(&unformatted, true), 0);
+        assert_eq!(format_command(&formatted.join("missing"), true), 1);
+        for path in [formatted, unformatted] {
+            fs::remove_dir_all(path).unwrap();
+        }
+    }
+}
@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::{cell::Cell, collections::HashMap, fmt::Display};
 
-use crate::compiler::CompileException;
+use crate::{compiler::CompileException, tokenizer::{PositionedToken, Token}};
 
 // Represents a fire resource location such as myGame::Struct::Func
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
@@ -11,6 +11,9 @@ impl FireLocation {
     pub fn new() -> FireLocation {
         FireLocation { path: Vec::with_capacity(16) }
     }
+    pub fn from_path(path: &[String]) -> FireLocation {
+        FireLocation { path: path.to_vec() }
+    }
     pub fn with(&self, s: String) -> FireLocation {
         let mut path = self.path.clone();
         path.push(s);
@@ -27,36 +30,217 @@ impl FireLocation {
     pub fn pop(&mut self) {
         self.path.pop();
     }
+    pub fn last(&self) -> Option<&String> {
+        self.path.last()
+    }
+    pub fn segments(&self) -> &[String] {
+        &self.path
+    }
+}
+impl Default for FireLocation {
+    fn default() -> Self {
+        FireLocation::new()
+    }
+}
+impl Display for FireLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.path.join("::"))
+    }
 }
 
 // Stores all relevant data about a project. Mainly a map of all resources.
 pub struct StructuredFireProject {
-    resources: HashMap<FireLocation, UncompiledFireResource>
+    resources: HashMap<FireLocation, UncompiledFireResource>,
+    implementations: Vec<UncompiledImplementation>,
+    imports: HashMap<FireLocation, Vec<Import>>, // File or group -> the imports written in it
+    sources: HashMap<FireLocation, FireLocation> // Resource -> the file it was written in, which differs from its file inside of a group
 }
 impl StructuredFireProject {
-    fn new() -> StructuredFireProject {
-        StructuredFireProject { resources: HashMap::new() }
+    pub fn new() -> StructuredFireProject {
+        StructuredFireProject { resources: HashMap::new(), implementations: Vec::new(), imports: HashMap::new(), sources: HashMap::new() }
     }
     // Returns a resource at the location
-    fn get_resource(&self, loc: &FireLocation) -> Option<&UncompiledFireResource> {
+    pub fn get_resource(&self, loc: &FireLocation) -> Option<&UncompiledFireResource> {
         self.resources.get(loc)
     }
+    // Returns every resource in the project
+    pub fn resources(&self) -> impl Iterator<Item = &UncompiledFireResource> {
+        self.resources.values()
+    }
+    // The file that a resource was written in
+    pub fn source_of(&self, loc: &FireLocation) -> Option<&FireLocation> {
+        self.sources.get(loc)
+    }
+    // Every import, along with the file or group it was written in
+    pub fn imports(&self) -> impl Iterator<Item = (&FireLocation, &Import)> {
+        self.imports.iter().flat_map(|(scope, imports)| imports.iter().map(move |i| (scope, i)))
+    }
     // Sets a resource at the location
-    fn add_resource<'a>(&mut self, loc: &'a FireLocation, f: UncompiledFireResource) -> Result<(), CompileException<'a>> {
-        if self.resources.contains_key(loc) {
-            Err(CompileException::DuplicateResource(loc))
+    fn add_resource(&mut self, f: UncompiledFireResource) -> Result<(), CompileException> {
+        if self.resources.contains_key(&f.loc) {
+            Err(CompileException::DuplicateResource(f.loc))
         } else {
-            self.resources.insert(loc.clone(), f);
+            self.resources.insert(f.loc.clone(), f);
             Ok(())
         }
     }
+    // Finds the resource that a path written inside of a file or group refers to.
+    // Paths are first looked up next to where they were written, then in each group around that, then from the root of the project.
+    pub fn resolve(&self, file: &FireLocation, path: &[String]) -> Option<FireLocation> {
+        let mut scope = file.clone();
+        loop {
+            if let Some(found) = self.find(file, &scope, path, &mut Vec::new()) {
+                return Some(found);
+            }
+            match self.resources.get(&scope) {
+                Some(UncompiledFireResource { resource_type: UncompiledResourceType::Group, .. }) => scope = scope.parent(),
+                _ => break
+            }
+        }
+        self.find(file, &FireLocation::new(), path, &mut Vec::new())
+    }
+    // Looks a path up inside of a scope, following any imports written along the way.
+    // Visiting holds the imports currently being followed, so that imports that lead back to themselves are skipped.
+    fn find(&self, from: &FireLocation, scope: &FireLocation, path: &[String], visiting: &mut Vec<(FireLocation, String)>) -> Option<FireLocation> {
+        let direct = path.iter().fold(scope.clone(), |l, s| l.with(s.clone()));
+        if self.resources.contains_key(&direct) {
+            return Some(direct);
+        }
+        for i in (0..path.len()).rev() {
+            let inner = path[..i].iter().fold(scope.clone(), |l, s| l.with(s.clone()));
+            let Some(imports) = self.imports.get(&inner) else { continue };
+            let key = (inner.clone(), path[i].clone());
+            if visiting.contains(&key) {
+                continue;
+            }
+            visiting.push(key);
+            for import in imports.iter().filter(|im| import_visible(from, &inner, im)) {
+                let target = match &import.name {
+                    Some(name) if *name == path[i] => [&import.path[..], &path[i + 1..]].concat(),
+                    Some(_) => continue,
+                    None => [&import.path[..], &path[i..]].concat()
+                };
+                if let Some(found) = self.find(from, &FireLocation::new(), &target, visiting) {
+                    import.used.set(true);
+                    visiting.pop();
+                    return Some(found);
+                }
+            }
+            visiting.pop();
+        }
+        None
+    }
+    // Makes sure every import points at something, and doesn't clash with a resource of the same name
+    pub fn check_imports(&self) -> Result<(), CompileException> {
+        for (scope, imports) in &self.imports {
+            for import in imports {
+                let target = FireLocation::from_path(&import.path);
+                // Files and folders aren't resources, but can still be imported to shorten paths into them
+                let found = self.resources.keys().any(|l| l.segments().starts_with(target.segments())) || self.imports.contains_key(&target)
+                    || self.find(scope, &FireLocation::new(), &import.path, &mut Vec::new()).is_some();
+                if !found {
+                    return Err(CompileException::InResource(scope.clone(), Box::new(CompileException::UnknownName(target.to_string(), import.line, import.column))));
+                }
+                if let Some(name) = &import.name {
+                    if self.resources.contains_key(&scope.with(name.clone())) {
+                        return Err(CompileException::DuplicateResource(scope.with(name.clone())));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    // Describes every import that nothing was looked up through
+    pub fn unused_imports(&self) -> Vec<String> {
+        let mut unused: Vec<String> = self.imports.iter()
+            .flat_map(|(scope, imports)| imports.iter().filter(|i| !i.used.get()).map(move |i| (scope, i)))
+            .map(|(scope, i)| {
+                let name = match &i.name {
+                    None => "::*".to_string(),
+                    Some(n) if i.path.last() != Some(n) => format!(" as {}", n),
+                    Some(_) => String::new()
+                };
+                format!("Unused import {}{} in {}. At {}:{}", i.path.join("::"), name, scope, i.line, i.column)
+            })
+            .collect();
+        unused.sort();
+        unused
+    }
+    // The first resource along a location that code written in a file or group isn't allowed to use, with its visibility and where it can be used
+    pub fn hidden_from(&self, from: &FireLocation, loc: &FireLocation) -> Option<(FireLocation, &'static str, FireLocation)> {
+        (1..=loc.segments().len()).find_map(|n| {
+            let res = self.resources.get(&FireLocation::from_path(&loc.segments()[..n]))?;
+            let (level, boundary) = match res.modifiers.visibility.unwrap_or_default() {
+                Visibility::Public => return None,
+                Visibility::Internal => ("internal", FireLocation::from_path(&res.loc.segments()[..1])),
+                Visibility::Private => ("private", res.file.clone())
+            };
+            if from.segments().starts_with(boundary.segments()) { None } else { Some((res.loc.clone(), level, boundary)) }
+        })
+    }
+    // Gives every impl block a location next to the type it implements. Trait impls are at Type::path.of.Trait, after the trait's
+    // full location so that traits with the same name don't clash, and inherent ones are at Type::Self.0, Type::Self.1 and so on,
+    // since a type can have any number of them. This can only happen once every file is structured, since the type may live in a
+    // different file than the impl.
+    pub fn register_implementations(&mut self) -> Result<(), CompileException> {
+        for imp in std::mem::take(&mut self.implementations) {
+            let (line, column) = imp.tokens.first().map(|t| (t.line, t.column)).unwrap_or((0, 0));
+            let type_loc = self.resolve(&imp.file, &imp.type_path).ok_or_else(|| CompileException::UnknownResource(imp.type_path.join("::"), line, column))?;
+            let visible = |loc: &FireLocation| match self.hidden_from(&imp.file, loc) {
+                Some((hidden, level, boundary)) => Err(CompileException::NotVisible(hidden, level, boundary, line, column)),
+                None => Ok(())
+            };
+            visible(&type_loc)?;
+            if !matches!(self.resources[&type_loc].resource_type, UncompiledResourceType::Struct | UncompiledResourceType::Enum) {
+                return Err(CompileException::WrongResourceType(type_loc, "type"));
+            }
+            let (impl_loc, trait_loc) = match &imp.trait_path {
+                Some(path) => {
+                    let trait_loc = self.resolve(&imp.file, path).ok_or_else(|| CompileException::UnknownResource(path.join("::"), line, column))?;
+                    visible(&trait_loc)?;
+                    if !matches!(self.resources[&trait_loc].resource_type, UncompiledResourceType::Trait) {
+                        return Err(CompileException::WrongResourceType(trait_loc, "trait"));
+                    }
+                    (type_loc.with(trait_loc.segments().join(".")), Some(trait_loc))
+                }
+                None => {
+                    let mut blocks = (0..).map(|n| type_loc.with(format!("Self.{}", n)));
+                    (blocks.find(|l| !self.resources.contains_key(l)).unwrap(), None)
+                }
+            };
+            // Methods of inherent impls live directly on the type, so they clash with the ones declared inside of it
+            let method_parent = if trait_loc.is_some() { impl_loc.clone() } else { type_loc };
+            self.sources.insert(impl_loc.clone(), imp.source.clone());
+            self.add_resource(UncompiledFireResource {
+                loc: impl_loc,
+                resource_type: UncompiledResourceType::Implementation(trait_loc),
+                file: imp.file.clone(),
+                modifiers: imp.modifiers,
+                tokens: imp.tokens
+            })?;
+            for mut method in imp.methods {
+                method.loc = method_parent.with(method.loc.last().unwrap().clone());
+                self.sources.insert(method.loc.clone(), imp.source.clone());
+                self.add_resource(method)?;
+            }
+        }
+        Ok(())
+    }
+}
+impl Default for StructuredFireProject {
+    fn default() -> Self {
+        StructuredFireProject::new()
+    }
 }
 
 // Represents an uncompiled Fire Resource. that is: "fn hello(?): ?". We only know the names.
 // We cannot parse out any parameters or code about anything until we've structured the whole program to know all the names.
 pub struct UncompiledFireResource {
-    loc: FireLocation,
-    resource_type: UncompiledResourceType
+    pub loc: FireLocation,
+    pub resource_type: UncompiledResourceType,
+    pub file: FireLocation, // The file or group this was declared in, names inside of it are looked up from here
+    pub modifiers: ResourceModifiers,
+    pub tokens: Vec<PositionedToken> // Everything from the keyword to the end of the body
 }
 pub enum UncompiledResourceType {
     Function, // fn myFunc(?): ? { ? } 
@@ -67,5 +251,504 @@ pub enum UncompiledResourceType {
     Trait, // trait MyTrait : ? { absfuncs }
     Enum, // enum MyEnum : ? { vals = ? }
     Var, // let myVar = ?; (top-level)
-    EnumConst // The vals inside of an enum
-}
\ No newline at end of file
+    Const, // const myConst = ?; (top-level), never stored at runtime
+    EnumConst, // The vals inside of an enum
+    Group, // group MyGroup { resources }, which can be opened again in any file
+    Implementation(Option<FireLocation>) // impl MyTrait for MyStruct { funcs } or impl MyStruct { funcs }, with the trait once it's known
+}
+
+// An import written in a file or group. Imports are private, so they're only followed inside of where they're written, unless they're
+// marked public import or internal import to re-export what they import.
+pub struct Import {
+    pub path: Vec<String>,
+    pub name: Option<String>, // What it is called where it's imported, or None for a glob (import lib::*)
+    pub visibility: Visibility,
+    pub line: usize,
+    pub column: usize,
+    used: Cell<bool>
+}
+
+// Whether an import written in a scope can be followed by code in a file or group
+fn import_visible(from: &FireLocation, scope: &FireLocation, import: &Import) -> bool {
+    match import.visibility {
+        Visibility::Public => true,
+        Visibility::Internal => from.segments().first() == scope.segments().first(),
+        Visibility::Private => from.segments().starts_with(scope.segments())
+    }
+}
+
+// Everything written before a resource's keyword
+#[derive(Clone, Default)]
+pub struct ResourceModifiers {
+    pub annotations: Vec<String>,
+    pub visibility: Option<Visibility> // None if none was written, which is public for resources and private for imports
+}
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Visibility {
+    #[default]
+    Public, // Usable from anywhere
+    Internal, // Only inside of the package, the top folder of src it's in
+    Private // Only inside of the file or group it's declared in
+}
+
+// An impl block that hasn't been given a location yet, since the type it's for may not be structured yet.
+struct UncompiledImplementation {
+    file: FireLocation,
+    source: FireLocation,
+    trait_path: Option<Vec<String>>,
+    type_path: Vec<String>,
+    modifiers: ResourceModifiers,
+    tokens: Vec<PositionedToken>,
+    methods: Vec<UncompiledFireResource>
+}
+
+// Walks over a list of tokens, used by both parser passes
+pub struct TokenStream {
+    tokens: Vec<PositionedToken>,
+    index: usize,
+    location: FireLocation // What is being read, for error messages
+}
+impl TokenStream {
+    pub fn new(tokens: Vec<PositionedToken>, location: FireLocation) -> TokenStream {
+        TokenStream { tokens, index: 0, location }
+    }
+    // A stream over tokens found inside of one of these, such as the code in an interpolated string
+    pub fn inner(&self, tokens: Vec<PositionedToken>) -> TokenStream {
+        TokenStream::new(tokens, self.location.clone())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.index >= self.tokens.len()
+    }
+    pub fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+    pub fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.index + n).map(|t| &t.token)
+    }
+    pub fn next(&mut self) -> Result<PositionedToken, CompileException> {
+        let next = self.tokens.get(self.index).cloned().ok_or_else(|| CompileException::UnexpectedEnd(self.location.clone()))?;
+        self.index += 1;
+        Ok(next)
+    }
+    // The token that was just read
+    pub fn previous(&self) -> PositionedToken {
+        self.tokens[self.index - 1].clone()
+    }
+    // Where the next token starts, or where the last one started if there are none left
+    pub fn peek_position(&self) -> (usize, usize) {
+        self.tokens.get(self.index).or(self.tokens.last()).map(|t| (t.line, t.column)).unwrap_or((0, 0))
+    }
+    // Consumes the next token only if it is the one given
+    pub fn next_if(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+    pub fn expect(&mut self, token: Token) -> Result<(), CompileException> {
+        let next = self.next()?;
+        if next.token == token {
+            Ok(())
+        } else {
+            Err(unexpected(&next))
+        }
+    }
+    pub fn expect_identifier(&mut self) -> Result<String, CompileException> {
+        let next = self.next()?;
+        match next.token {
+            Token::Identifier(s) => Ok(s),
+            _ => Err(unexpected(&next))
+        }
+    }
+    pub fn expect_type(&mut self) -> Result<String, CompileException> {
+        let next = self.next()?;
+        match next.token {
+            Token::Type(s) => Ok(s),
+            _ => Err(unexpected(&next))
+        }
+    }
+    // Reads a path such as lib::lib_file::MyStruct
+    pub fn expect_path(&mut self) -> Result<Vec<String>, CompileException> {
+        let mut path = Vec::new();
+        loop {
+            let next = self.next()?;
+            match next.token {
+                Token::Identifier(s) | Token::Type(s) => path.push(s),
+                _ => return Err(unexpected(&next))
+            }
+            if !self.next_if(&Token::Accesser) {
+                return Ok(path);
+            }
+        }
+    }
+    // Takes a bracketed group (the next token must open it) up to and including the matching close
+    pub fn take_balanced(&mut self) -> Result<Vec<PositionedToken>, CompileException> {
+        let mut out = Vec::new();
+        let mut open = Vec::new(); // The closers still expected, innermost last
+        loop {
+            let next = self.next()?;
+            match next.token {
+                Token::OpenParen => open.push(Token::CloseParen),
+                Token::OpenBrace => open.push(Token::CloseBrace),
+                Token::OpenBracket => open.push(Token::CloseBracket),
+                // A closer has to match the latest opener, which it takes off, so ( ] and a stray } are both rejected
+                Token::CloseParen | Token::CloseBrace | Token::CloseBracket if open.pop().as_ref() != Some(&next.token) => return Err(unexpected(&next)),
+                Token::CloseParen | Token::CloseBrace | Token::CloseBracket => {}
+                _ if open.is_empty() => return Err(unexpected(&next)),
+                _ => {}
+            };
+            out.push(next);
+            if open.is_empty() {
+                return Ok(out);
+            }
+        }
+    }
+    // Takes every token up to (but not including) the next { or ; that isn't inside of brackets
+    pub fn take_until_body(&mut self) -> Result<Vec<PositionedToken>, CompileException> {
+        let mut out = Vec::new();
+        while !matches!(self.peek(), Some(Token::OpenBrace) | Some(Token::Semicolon) | None) {
+            if matches!(self.peek(), Some(Token::OpenParen) | Some(Token::OpenBracket)) {
+                out.append(&mut self.take_balanced()?);
+            } else {
+                out.push(self.next()?);
+            }
+        }
+        Ok(out)
+    }
+    // Takes every token up to and including the next ; that isn't inside of brackets
+    pub fn take_statement(&mut self) -> Result<Vec<PositionedToken>, CompileException> {
+        let mut out = Vec::new();
+        while !matches!(self.peek(), Some(Token::Semicolon)) {
+            if matches!(self.peek(), Some(Token::OpenParen) | Some(Token::OpenBrace) | Some(Token::OpenBracket)) {
+                out.append(&mut self.take_balanced()?);
+            } else {
+                out.push(self.next()?);
+            }
+        }
+        out.push(self.next()?);
+        Ok(out)
+    }
+    // Takes a resource body: either a { } block or a lone ;
+    pub fn take_body(&mut self) -> Result<Vec<PositionedToken>, CompileException> {
+        if let Some(Token::Semicolon) = self.peek() {
+            Ok(vec![self.next()?])
+        } else {
+            self.take_balanced()
+        }
+    }
+}
+
+pub fn unexpected(token: &PositionedToken) -> CompileException {
+    CompileException::UnexpectedToken(format!("{:?}", token.token), token.line, token.column)
+}
+
+// Reads the annotations and visibility before a resource
+fn read_modifiers(stream: &mut TokenStream) -> Result<ResourceModifiers, CompileException> {
+    let mut modifiers = ResourceModifiers::default();
+    loop {
+        match stream.peek() {
+            Some(Token::Annotation(_)) => {
+                if let Token::Annotation(a) = stream.next()?.token {
+                    modifiers.annotations.push(a);
+                }
+            }
+            Some(Token::Private) | Some(Token::Internal) if modifiers.visibility.is_none() => {
+                let private = stream.next()?.token == Token::Private;
+                modifiers.visibility = Some(if private { Visibility::Private } else { Visibility::Internal });
+            }
+            // public isn't a keyword, since resources are already public unless they say otherwise. It's only needed to re-export an import.
+            Some(Token::Identifier(s)) if s == "public" && stream.peek_nth(1) == Some(&Token::Import) && modifiers.visibility.is_none() => {
+                stream.next()?;
+                modifiers.visibility = Some(Visibility::Public);
+            }
+            _ => return Ok(modifiers)
+        }
+    }
+}
+
+// Reads a fn or pc: the keyword, name, header, and body
+fn read_function(stream: &mut TokenStream, parent: &FireLocation, file: &FireLocation, modifiers: ResourceModifiers, resource_type: UncompiledResourceType) -> Result<UncompiledFireResource, CompileException> {
+    let mut tokens = vec![stream.next()?];
+    let name = stream.expect_identifier()?;
+    tokens.push(stream.previous());
+    tokens.append(&mut stream.take_until_body()?);
+    let body = stream.take_body()?;
+    let resource_type = match (resource_type, body.len()) {
+        (UncompiledResourceType::AbstractMethod, n) if n > 1 => UncompiledResourceType::Method,
+        (t, 1) if !matches!(t, UncompiledResourceType::AbstractMethod) => return Err(unexpected(&body[0])),
+        (t, _) => t
+    };
+    tokens.extend(body);
+    Ok(UncompiledFireResource { loc: parent.with(name), resource_type, file: file.clone(), modifiers, tokens })
+}
+
+// Reads the functions inside of a struct, trait, or impl body. Struct fields are skipped, they're part of the struct's tokens.
+fn read_members(body: &[PositionedToken], parent: &FireLocation, file: &FireLocation, method_type: fn() -> UncompiledResourceType) -> Result<Vec<UncompiledFireResource>, CompileException> {
+    let mut stream = TokenStream::new(body[1..body.len() - 1].to_vec(), parent.clone());
+    let mut out = Vec::new();
+    while !stream.is_empty() {
+        let modifiers = read_modifiers(&mut stream)?;
+        match stream.peek() {
+            Some(Token::FuncDef) => out.push(read_function(&mut stream, parent, file, modifiers, method_type())?),
+            Some(Token::Identifier(_)) => { // A field, such as a: Int,
+                stream.next()?;
+                stream.expect(Token::Colon)?;
+                while !matches!(stream.peek(), Some(Token::Comma) | Some(Token::FuncDef) | Some(Token::Annotation(_)) | Some(Token::Private) | Some(Token::Internal) | None) {
+                    stream.next()?;
+                }
+                stream.next_if(&Token::Comma);
+            }
+            _ => return Err(unexpected(&stream.next()?))
+        }
+    }
+    Ok(out)
+}
+
+// Reads the traits a struct or enum implements inline, such as struct MyStruct : TraitA, TraitB
+fn read_inline_traits(header: &[PositionedToken], name: &str, file: &FireLocation) -> Result<Vec<UncompiledImplementation>, CompileException> {
+    let mut traits = TokenStream::new(header.to_vec(), file.with(name.to_string()));
+    let mut out = Vec::new();
+    if traits.next_if(&Token::Colon) {
+        while !traits.is_empty() {
+            let path = traits.expect_path()?;
+            if let Some(Token::Less) = traits.peek() {
+                while !traits.next_if(&Token::Greater) {
+                    traits.next()?;
+                }
+            }
+            traits.next_if(&Token::Comma);
+            out.push(UncompiledImplementation {
+                file: file.clone(), source: FireLocation::new(), trait_path: Some(path), type_path: vec![name.to_string()],
+                modifiers: ResourceModifiers::default(), tokens: Vec::new(), methods: Vec::new()
+            });
+        }
+    }
+    Ok(out)
+}
+
+// Reads the constants inside of an enum body, such as Red = 1, Green, Blue
+fn read_enum_constants(body: &[PositionedToken], parent: &FireLocation, file: &FireLocation) -> Result<Vec<UncompiledFireResource>, CompileException> {
+    let mut stream = TokenStream::new(body[1..body.len() - 1].to_vec(), parent.clone());
+    let mut out = Vec::new();
+    while !stream.is_empty() {
+        let modifiers = read_modifiers(&mut stream)?;
+        let name = stream.expect_type()?;
+        let mut tokens = vec![stream.previous()];
+        while !matches!(stream.peek(), Some(Token::Comma) | None) {
+            tokens.push(stream.next()?);
+        }
+        stream.next_if(&Token::Comma);
+        out.push(UncompiledFireResource { loc: parent.with(name), resource_type: UncompiledResourceType::EnumConst, file: file.clone(), modifiers, tokens });
+    }
+    Ok(out)
+}
+
+// Splits the tokens of a file into its top-level resources and adds them to the project
+pub fn structure_file(proj: &mut StructuredFireProject, file: &FireLocation, tokens: Vec<PositionedToken>) -> Result<(), CompileException> {
+    let implementations = proj.implementations.len();
+    let structured = structure_scope(proj, file, tokens);
+    // Everything new came from this file, even what was inside of a group or before an error
+    for loc in proj.resources.keys().filter(|l| !proj.sources.contains_key(*l)).cloned().collect::<Vec<_>>() {
+        proj.sources.insert(loc, file.clone());
+    }
+    for imp in &mut proj.implementations[implementations..] {
+        imp.source = file.clone();
+    }
+    structured
+}
+
+// Adds the resources written in a file or group
+fn structure_scope(proj: &mut StructuredFireProject, file: &FireLocation, tokens: Vec<PositionedToken>) -> Result<(), CompileException> {
+    let mut stream = TokenStream::new(tokens, file.clone());
+    while !stream.is_empty() {
+        let modifiers = read_modifiers(&mut stream)?;
+        match stream.peek() {
+            Some(Token::FuncDef) => proj.add_resource(read_function(&mut stream, file, file, modifiers, UncompiledResourceType::Function)?)?,
+            Some(Token::ProcDef) => proj.add_resource(read_function(&mut stream, file, file, modifiers, UncompiledResourceType::Process)?)?,
+            Some(Token::StructDef) | Some(Token::TraitDef) | Some(Token::EnumDef) => {
+                let keyword = stream.next()?;
+                let name = stream.expect_type()?;
+                let loc = file.with(name.clone());
+                let mut tokens = vec![keyword.clone(), stream.previous()];
+                let header = stream.take_until_body()?;
+                let body = stream.take_balanced()?;
+                let (resource_type, members) = match keyword.token {
+                    Token::StructDef => {
+                        proj.implementations.append(&mut read_inline_traits(&header, &name, file)?);
+                        (UncompiledResourceType::Struct, read_members(&body, &loc, file, || UncompiledResourceType::Method)?)
+                    }
+                    Token::EnumDef => {
+                        proj.implementations.append(&mut read_inline_traits(&header, &name, file)?);
+                        (UncompiledResourceType::Enum, read_enum_constants(&body, &loc, file)?)
+                    }
+                    _ => (UncompiledResourceType::Trait, read_members(&body, &loc, file, || UncompiledResourceType::AbstractMethod)?)
+                };
+                tokens.extend(header);
+                tokens.extend(body);
+                proj.add_resource(UncompiledFireResource { loc, resource_type, file: file.clone(), modifiers, tokens })?;
+                for m in members {
+                    proj.add_resource(m)?;
+                }
+            }
+            Some(Token::Let) | Some(Token::Const) => {
+                let keyword = stream.next()?;
+                let name = match stream.next()? {
+                    PositionedToken { token: Token::Identifier(n), .. } => n,
+                    PositionedToken { token: Token::Type(n), .. } if keyword.token == Token::Const => n,
+                    t => return Err(unexpected(&t))
+                };
+                let mut tokens = vec![keyword.clone(), stream.previous()];
+                tokens.append(&mut stream.take_statement()?);
+                let resource_type = if keyword.token == Token::Let { UncompiledResourceType::Var } else { UncompiledResourceType::Const };
+                proj.add_resource(UncompiledFireResource { loc: file.with(name), resource_type, file: file.clone(), modifiers, tokens })?;
+            }
+            Some(Token::GroupDef) => {
+                let mut tokens = vec![stream.next()?];
+                let start = stream.index;
+                let path = stream.expect_path()?;
+                tokens.extend(stream.tokens[start..stream.index].iter().cloned());
+                // A group is opened next to where it's written, or anywhere in the project by its full path
+                let loc = if path.len() == 1 { file.with(path[0].clone()) } else { FireLocation::from_path(&path) };
+                if stream.peek() != Some(&Token::OpenBrace) {
+                    return Err(unexpected(&stream.next()?));
+                }
+                let mut body = stream.take_balanced()?;
+                tokens.push(body.remove(0));
+                tokens.push(body.pop().unwrap());
+                match proj.get_resource(&loc) {
+                    Some(UncompiledFireResource { resource_type: UncompiledResourceType::Group, .. }) => {}
+                    _ => proj.add_resource(UncompiledFireResource { loc: loc.clone(), resource_type: UncompiledResourceType::Group, file: file.clone(), modifiers, tokens })?
+                }
+                structure_scope(proj, &loc, body)?;
+            }
+            Some(Token::Import) => {
+                let at = stream.next()?;
+                let mut path = Vec::new();
+                let mut name = None;
+                loop {
+                    if stream.next_if(&Token::Multiply) {
+                        break;
+                    }
+                    match stream.next()? {
+                        PositionedToken { token: Token::Identifier(s) | Token::Type(s), .. } => path.push(s),
+                        t => return Err(unexpected(&t))
+                    }
+                    if !stream.next_if(&Token::Accesser) {
+                        name = path.last().cloned();
+                        break;
+                    }
+                }
+                if name.is_some() && matches!(stream.peek(), Some(Token::Identifier(s)) if s == "as") {
+                    stream.next()?;
+                    name = Some(match stream.next()? {
+                        PositionedToken { token: Token::Identifier(s) | Token::Type(s), .. } => s,
+                        t => return Err(unexpected(&t))
+                    });
+                }
+                stream.expect(Token::Semicolon)?;
+                let imports = proj.imports.entry(file.clone()).or_default();
+                if let Some(n) = name.as_ref().filter(|_| imports.iter().any(|i| i.name == name)) {
+                    return Err(CompileException::DuplicateResource(file.with(n.clone())));
+                }
+                let visibility = modifiers.visibility.unwrap_or(Visibility::Private);
+                imports.push(Import { path, name, visibility, line: at.line, column: at.column, used: Cell::new(false) });
+            }
+            Some(Token::Implementation) => {
+                let mut tokens = vec![stream.next()?];
+                let start = stream.index;
+                let first = stream.expect_path()?;
+                if let Some(Token::Less) = stream.peek() { // Skip generics on the trait, they don't change where the impl lives
+                    while !stream.next_if(&Token::Greater) {
+                        stream.next()?;
+                    }
+                }
+                let (trait_path, type_path) = if stream.next_if(&Token::For) {
+                    (Some(first), stream.expect_path()?)
+                } else {
+                    (None, first)
+                };
+                tokens.extend(stream.tokens[start..stream.index].iter().cloned());
+                if stream.peek() != Some(&Token::OpenBrace) {
+                    return Err(unexpected(&stream.next()?));
+                }
+                let body = stream.take_balanced()?;
+                // Members get their real location once the type has been found
+                let methods = read_members(&body, &file.with(type_path.join("::")), file, || UncompiledResourceType::Method)?;
+                tokens.extend(body);
+                proj.implementations.push(UncompiledImplementation { file: file.clone(), source: FireLocation::new(), trait_path, type_path, modifiers, tokens, methods });
+            }
+            _ => return Err(unexpected(&stream.next()?))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::{files, parser_pass_two};
+    use super::{FireLocation, StructuredFireProject};
+
+    fn project() -> StructuredFireProject {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/TestImports");
+        let mut project = StructuredFireProject::new();
+        for file in files::get_all_project_files(&path) {
+            project = file.structure_into(project).unwrap_or_else(|e| panic!("{}", e));
+        }
+        project.register_implementations().unwrap_or_else(|e| panic!("{}", e));
+        project
+    }
+
+    fn loc(path: &str) -> FireLocation {
+        FireLocation::from_path(&path.split("::").map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    // What a path written in a file refers to
+    fn resolve(project: &StructuredFireProject, file: &str, path: &str) -> Option<String> {
+        project.resolve(&loc(file), loc(path).segments()).map(|l| l.to_string())
+    }
+
+    #[test]
+    fn globs() {
+        let project = project();
+        assert_eq!(resolve(&project, "main::main", "hello").as_deref(), Some("lib::prelude::hello"));
+        // Found through the glob, then the import that lib::prelude re-exports
+        assert_eq!(resolve(&project, "main::main", "Thing").as_deref(), Some("lib::lib_nested::things::Thing"));
+        assert_eq!(resolve(&project, "main::main", "nothing"), None);
+    }
+
+    #[test]
+    fn aliases() {
+        let project = project();
+        assert_eq!(resolve(&project, "main::main", "T").as_deref(), Some("lib::lib_nested::things::Thing"));
+        assert_eq!(resolve(&project, "main::main", "build").as_deref(), Some("lib::lib_nested::things::make"));
+        assert_eq!(resolve(&project, "main::main", "things::make").as_deref(), Some("lib::lib_nested::things::make"));
+    }
+
+    #[test]
+    fn imports_are_private() {
+        let project = project();
+        assert_eq!(resolve(&project, "lib::prelude", "secret").as_deref(), Some("lib::lib_nested::things::make"));
+        assert_eq!(resolve(&project, "main::main", "secret"), None);
+        assert_eq!(resolve(&project, "main::main", "lib::prelude::secret"), None);
+    }
+
+    #[test]
+    fn cycles() {
+        let project = project();
+        assert_eq!(resolve(&project, "lib::cycle::ping", "pong").as_deref(), Some("lib::cycle::pong::pong"));
+        assert_eq!(resolve(&project, "lib::cycle::pong", "ping").as_deref(), Some("lib::cycle::ping::ping"));
+        // Through main's glob, then around the cycle
+        assert_eq!(resolve(&project, "main::main", "pong").as_deref(), Some("lib::cycle::pong::pong"));
+        assert_eq!(resolve(&project, "lib::cycle::ping", "nothing"), None);
+    }
+
+    #[test]
+    fn unused_imports() {
+        let project = project();
+        parser_pass_two::parse_project(&project).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(project.unused_imports(), ["Unused import lib::cycle::ping::* in lib::cycle::pong. At 1:8"]);
+    }
+}
@@ -0,0 +1,967 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{compiler::CompileException, parser::{unexpected, FireLocation, StructuredFireProject, TokenStream, UncompiledFireResource, UncompiledResourceType}, tokenizer::{StringPart, Token}};
+
+// A type once its name has been looked up
+#[derive(Clone, Debug, PartialEq)]
+pub enum FireType {
+    Int, Num, Boolean, String,
+    Player, // An entity that per-player values can be read from
+    Struct(FireLocation),
+    Enum(FireLocation),
+    Trait(FireLocation),
+    List(Box<FireType>), // Kept in storage, as are Maps. [] is a List of Void until it's used as some other List.
+    Map(Box<FireType>, Box<FireType>),
+    Function(Vec<FireType>, Box<FireType>), // A lambda, kept in storage along with whatever it captured
+    Event(String), // Something that happened to a player, such as PlayerJoinEvent. Handlers run as that player.
+    Void
+}
+impl Display for FireType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FireType::Int => f.write_str("Int"),
+            FireType::Num => f.write_str("Num"),
+            FireType::Boolean => f.write_str("Boolean"),
+            FireType::String => f.write_str("String"),
+            FireType::Player => f.write_str("Player"),
+            FireType::Struct(l) | FireType::Enum(l) | FireType::Trait(l) => write!(f, "{}", l),
+            FireType::List(t) => write!(f, "List<{}>", t),
+            FireType::Map(k, v) => write!(f, "Map<{}, {}>", k, v),
+            FireType::Function(params, r) => {
+                write!(f, "Fn({})", params.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", "))?;
+                if **r != FireType::Void {
+                    write!(f, ": {}", r)?;
+                }
+                Ok(())
+            }
+            FireType::Event(name) => f.write_str(name),
+            FireType::Void => f.write_str("Void")
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub line: usize,
+    pub column: usize
+}
+#[derive(Clone, Debug)]
+pub enum ExpressionKind {
+    Int(i64), Num, String(String), Boolean(bool), // Nums can't be compiled yet, so their value isn't kept
+    Variable(String), // A local or parameter
+    SelfValue,
+    Resource(FireLocation, Vec<String>), // A path that was found, and whatever came after it such as MyEnum::fromInt
+    Builtin(String), // A name that isn't a resource, such as broadcast
+    StructLiteral(FireLocation, Vec<(String, Expression)>), // MyStruct { a: 1, b: 2 }
+    Field(Box<Expression>, String),
+    Call(Box<Expression>, Vec<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+    Unary(UnaryOperator, Box<Expression>),
+    List(Vec<Expression>), // [1, 2, 3]
+    Map(Vec<(Expression, Expression)>), // ["a": 1, "b": 2]
+    Index(Box<Expression>, Box<Expression>), // list[0] or map["a"]
+    Lambda(Vec<(String, Option<FireType>)>, Vec<Statement>), // (x) => x + 1 has a body of return x + 1
+    Range(Box<Expression>, Box<Expression>, bool), // 0..10, or 0..=10 if it includes the end
+    Interpolation(Vec<Expression>) // "Hello {name}" is the parts "Hello " and name
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOperator {
+    Add, Subtract, Multiply, Divide, Modulo,
+    And, StrictAnd, Or, StrictOr, Xor,
+    Equal, NotEqual, Greater, Less, GreaterEqual, LessEqual,
+    In // x in 0..10
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOperator {
+    Negate, Not
+}
+
+#[derive(Clone, Debug)]
+pub enum Statement {
+    Let(String, Option<FireType>, Expression),
+    Assign(Expression, Option<BinaryOperator>, Expression), // x = 1 or x += 1
+    Expression(Expression),
+    If(Expression, Vec<Statement>, Vec<Statement>),
+    While(Expression, Vec<Statement>),
+    For(String, Expression, Vec<Statement>),
+    Match(Expression, Vec<MatchArm>),
+    Return(Option<Expression>, usize, usize),
+    Select(Expression), // Runs the rest of the block as the given player
+    Raise(Option<Expression>, usize, usize), // Stops every function up to the nearest try, with an optional message
+    Try(Vec<Statement>, Option<String>, Vec<Statement>), // try { } catch (message) { }
+    Wait(Expression, usize, usize), // Pauses a pc for some ticks. yield waits for one.
+    Block(Vec<Statement>)
+}
+// One arm of a match. An arm with no patterns is the else arm.
+#[derive(Clone, Debug)]
+pub struct MatchArm {
+    pub patterns: Vec<Expression>,
+    pub body: Vec<Statement>
+}
+
+pub struct ParsedFunction {
+    pub loc: FireLocation,
+    pub params: Vec<(String, FireType)>, // Includes self if the function takes it
+    pub return_type: FireType,
+    pub body: Vec<Statement>,
+    pub process: bool,
+    pub annotations: Vec<String>
+}
+pub struct ParsedStruct {
+    pub fields: Vec<(String, FireType)>
+}
+pub struct ParsedEnum {
+    pub constants: Vec<(String, i64)>
+}
+// A top-level let or const
+pub struct ParsedGlobal {
+    pub loc: FireLocation,
+    pub ty: Option<FireType>,
+    pub value: Expression,
+    pub constant: bool,
+    pub persistent: bool, // @Persistent keeps the value across reloads, @Session (the default) sets it again on every load
+    pub per_player: bool // @PerPlayer gives every player their own value
+}
+
+// Every resource of the project with its code parsed and its names looked up.
+pub struct ParsedFireProject {
+    pub functions: HashMap<FireLocation, ParsedFunction>,
+    pub structs: HashMap<FireLocation, ParsedStruct>,
+    pub enums: HashMap<FireLocation, ParsedEnum>,
+    pub globals: HashMap<FireLocation, ParsedGlobal>,
+    pub implementations: HashMap<FireLocation, Vec<FireLocation>>, // Type -> its impls, for finding methods
+    pub uncaught: Option<FireLocation>, // The @Uncaught function, which is called with raises that nothing caught instead of logging them
+    pub event_handlers: Vec<(String, FireLocation)>, // Every @EventHandler and the event it handles
+    pub tests: Vec<FireLocation> // Every @Test function, which is only compiled when testing
+}
+
+pub fn parse_project(proj: &StructuredFireProject) -> Result<ParsedFireProject, CompileException> {
+    proj.check_imports()?;
+    let mut parsed = ParsedFireProject { functions: HashMap::new(), structs: HashMap::new(), enums: HashMap::new(), globals: HashMap::new(), implementations: HashMap::new(), uncaught: None, event_handlers: Vec::new(), tests: Vec::new() };
+    let mut resources: Vec<&UncompiledFireResource> = proj.resources().collect();
+    resources.sort_by_key(|r| r.loc.to_string());
+    for res in resources {
+        let in_resource = |e| CompileException::InResource(res.loc.clone(), Box::new(e));
+        match res.resource_type {
+            UncompiledResourceType::Function | UncompiledResourceType::Method | UncompiledResourceType::Process => {
+                let f = parse_function(proj, res).map_err(in_resource)?;
+                if f.annotations.iter().any(|a| a == "Uncaught") {
+                    let (line, column) = (res.tokens[0].line, res.tokens[0].column);
+                    if let Some(other) = &parsed.uncaught {
+                        return Err(in_resource(CompileException::TypeError(format!("{} is already the @Uncaught handler", other), line, column)));
+                    }
+                    if f.params != [("message".to_string(), FireType::String), ("at".to_string(), FireType::String)] || f.return_type != FireType::Void {
+                        return Err(in_resource(CompileException::TypeError("An @Uncaught handler must be fn(message: String, at: String)".to_string(), line, column)));
+                    }
+                    parsed.uncaught = Some(res.loc.clone());
+                }
+                if f.annotations.iter().any(|a| a == "EventHandler") {
+                    let (line, column) = (res.tokens[0].line, res.tokens[0].column);
+                    match &f.params[..] {
+                        [(_, FireType::Event(event))] if f.return_type == FireType::Void => parsed.event_handlers.push((event.clone(), res.loc.clone())),
+                        _ => return Err(in_resource(CompileException::TypeError("An @EventHandler must take just the event, such as pc onJoin(e: PlayerJoinEvent)".to_string(), line, column)))
+                    }
+                }
+                if f.annotations.iter().any(|a| a == "Test") {
+                    let (line, column) = (res.tokens[0].line, res.tokens[0].column);
+                    if !f.params.is_empty() || f.return_type != FireType::Void || !matches!(res.resource_type, UncompiledResourceType::Function) {
+                        return Err(in_resource(CompileException::TypeError("A @Test must be a fn that takes and returns nothing, such as fn addsUp()".to_string(), line, column)));
+                    }
+                    parsed.tests.push(res.loc.clone());
+                }
+                parsed.functions.insert(res.loc.clone(), f);
+            }
+            UncompiledResourceType::Struct => {
+                parsed.structs.insert(res.loc.clone(), parse_struct(proj, res).map_err(in_resource)?);
+            }
+            UncompiledResourceType::Enum => {
+                parsed.enums.insert(res.loc.clone(), parse_enum(proj, res).map_err(in_resource)?);
+            }
+            UncompiledResourceType::Var | UncompiledResourceType::Const => {
+                parsed.globals.insert(res.loc.clone(), parse_global(proj, res).map_err(in_resource)?);
+            }
+            UncompiledResourceType::Implementation(_) => {
+                parsed.implementations.entry(res.loc.parent()).or_default().push(res.loc.clone());
+            }
+            _ => {}
+        }
+    }
+    Ok(parsed)
+}
+
+// Finds the struct or enum that a method belongs to, if any
+fn owning_type(proj: &StructuredFireProject, loc: &FireLocation) -> Option<FireType> {
+    let mut parent = loc.parent();
+    while parent.last().is_some() {
+        match proj.get_resource(&parent).map(|r| &r.resource_type) {
+            Some(UncompiledResourceType::Struct) => return Some(FireType::Struct(parent)),
+            Some(UncompiledResourceType::Enum) => return Some(FireType::Enum(parent)),
+            Some(UncompiledResourceType::Trait) => return Some(FireType::Trait(parent)),
+            Some(UncompiledResourceType::Implementation(_)) => {}
+            _ => return None
+        }
+        parent = parent.parent();
+    }
+    None
+}
+
+fn parse_function(proj: &StructuredFireProject, res: &UncompiledFireResource) -> Result<ParsedFunction, CompileException> {
+    let mut parser = BodyParser::new(proj, res);
+    parser.stream.next()?; // fn or pc
+    parser.stream.expect_identifier()?;
+    parser.stream.expect(Token::OpenParen)?;
+    let mut params = Vec::new();
+    while !parser.stream.next_if(&Token::CloseParen) {
+        if parser.stream.next_if(&Token::SelfIdent) {
+            let self_type = parser.self_type.clone().ok_or_else(|| unexpected(&parser.stream.previous()))?;
+            params.push(("self".to_string(), self_type));
+        } else {
+            let name = parser.stream.expect_identifier()?;
+            parser.stream.expect(Token::Colon)?;
+            params.push((name, parser.parse_type()?));
+        }
+        parser.scopes[0].push(params.last().unwrap().0.clone());
+        if !parser.stream.next_if(&Token::Comma) {
+            parser.stream.expect(Token::CloseParen)?;
+            break;
+        }
+    }
+    let (line, column) = parser.stream.peek_position();
+    let return_type = if parser.stream.next_if(&Token::Colon) { parser.parse_type()? } else { FireType::Void };
+    if matches!(res.resource_type, UncompiledResourceType::Process) && return_type != FireType::Void {
+        return Err(CompileException::TypeError("A pc runs on its own, so it cannot return a value".to_string(), line, column));
+    }
+    let body = parser.parse_block()?;
+    Ok(ParsedFunction {
+        loc: res.loc.clone(), params, return_type, body,
+        process: matches!(res.resource_type, UncompiledResourceType::Process),
+        annotations: res.modifiers.annotations.clone()
+    })
+}
+
+fn parse_struct(proj: &StructuredFireProject, res: &UncompiledFireResource) -> Result<ParsedStruct, CompileException> {
+    let mut parser = BodyParser::new(proj, res);
+    while !parser.stream.next_if(&Token::OpenBrace) {
+        parser.stream.next()?;
+    }
+    let mut fields = Vec::new();
+    while !parser.stream.next_if(&Token::CloseBrace) {
+        match parser.stream.peek() {
+            Some(Token::Identifier(_)) => {
+                let name = parser.stream.expect_identifier()?;
+                parser.stream.expect(Token::Colon)?;
+                fields.push((name, parser.parse_type()?));
+                parser.stream.next_if(&Token::Comma);
+            }
+            _ => { // Methods are their own resources
+                parser.stream.take_until_body()?;
+                parser.stream.take_body()?;
+            }
+        }
+    }
+    Ok(ParsedStruct { fields })
+}
+
+fn parse_enum(proj: &StructuredFireProject, res: &UncompiledFireResource) -> Result<ParsedEnum, CompileException> {
+    let mut parser = BodyParser::new(proj, res);
+    while !parser.stream.next_if(&Token::OpenBrace) {
+        parser.stream.next()?;
+    }
+    let mut constants: Vec<(String, i64)> = Vec::new();
+    let mut next_value = Some(0i64); // None once the previous value was the largest Int
+    while !parser.stream.next_if(&Token::CloseBrace) {
+        while let Some(Token::Annotation(_)) = parser.stream.peek() {
+            parser.stream.next()?;
+        }
+        let name = parser.stream.expect_type()?;
+        if parser.stream.next_if(&Token::Assign) { // Explicit values have to be known now
+            let value = parser.parse_expression()?;
+            next_value = Some(match value.kind {
+                ExpressionKind::Int(n) => n,
+                ExpressionKind::Unary(UnaryOperator::Negate, ref inner) => match inner.kind {
+                    ExpressionKind::Int(n) => -n,
+                    _ => return Err(CompileException::TypeError("Enum values must be Int literals".to_string(), value.line, value.column))
+                },
+                _ => return Err(CompileException::TypeError("Enum values must be Int literals".to_string(), value.line, value.column))
+            });
+        }
+        let Some(number) = next_value else {
+            let at = parser.stream.previous();
+            return Err(CompileException::OutOfRange(format!("{}, numbered after {},", name, constants.last().unwrap().0), "Int", at.line, at.column));
+        };
+        if let Some((other, _)) = constants.iter().find(|(_, v)| *v == number) {
+            let at = parser.stream.previous();
+            return Err(CompileException::TypeError(format!("{} has the same value as {}", name, other), at.line, at.column));
+        }
+        constants.push((name, number));
+        // Values are Ints, so numbering can't go past the largest one
+        next_value = i32::try_from(number).ok().and_then(|n| n.checked_add(1)).map(|n| n as i64);
+        parser.stream.next_if(&Token::Comma);
+    }
+    Ok(ParsedEnum { constants })
+}
+
+fn parse_global(proj: &StructuredFireProject, res: &UncompiledFireResource) -> Result<ParsedGlobal, CompileException> {
+    let mut parser = BodyParser::new(proj, res);
+    let (line, column) = parser.stream.peek_position();
+    let annotations = &res.modifiers.annotations;
+    let persistent = annotations.iter().any(|a| a == "Persistent");
+    let per_player = annotations.iter().any(|a| a == "PerPlayer");
+    if persistent && annotations.iter().any(|a| a == "Session") {
+        return Err(CompileException::Unsupported("A global cannot be both @Persistent and @Session".to_string(), line, column));
+    }
+    let constant = parser.stream.next()?.token == Token::Const;
+    if constant && annotations.iter().any(|a| a == "Persistent" || a == "Session" || a == "PerPlayer") {
+        return Err(CompileException::Unsupported("Consts are never stored, so they cannot have a storage annotation".to_string(), line, column));
+    }
+    parser.stream.next()?;
+    let ty = if parser.stream.next_if(&Token::Colon) { Some(parser.parse_type()?) } else { None };
+    parser.stream.expect(Token::Assign)?;
+    let value = parser.parse_expression()?;
+    parser.stream.expect(Token::Semicolon)?;
+    Ok(ParsedGlobal { loc: res.loc.clone(), ty, value, constant, persistent, per_player })
+}
+
+// Calls f on every expression inside of some statements, including the ones nested inside of other expressions
+pub fn walk_statements(statements: &[Statement], f: &mut dyn FnMut(&Expression)) {
+    for s in statements {
+        match s {
+            Statement::Let(_, _, e) | Statement::Expression(e) | Statement::Return(Some(e), _, _) | Statement::Select(e) | Statement::Raise(Some(e), _, _) | Statement::Wait(e, _, _) => walk_expression(e, f),
+            Statement::Assign(a, _, b) => {
+                walk_expression(a, f);
+                walk_expression(b, f);
+            }
+            Statement::If(e, a, b) => {
+                walk_expression(e, f);
+                walk_statements(a, f);
+                walk_statements(b, f);
+            }
+            Statement::While(e, body) | Statement::For(_, e, body) => {
+                walk_expression(e, f);
+                walk_statements(body, f);
+            }
+            Statement::Match(e, arms) => {
+                walk_expression(e, f);
+                for arm in arms {
+                    arm.patterns.iter().for_each(|p| walk_expression(p, f));
+                    walk_statements(&arm.body, f);
+                }
+            }
+            Statement::Block(body) => walk_statements(body, f),
+            Statement::Try(body, _, handler) => {
+                walk_statements(body, f);
+                walk_statements(handler, f);
+            }
+            Statement::Return(None, _, _) | Statement::Raise(None, _, _) => {}
+        }
+    }
+}
+pub fn walk_expression(expr: &Expression, f: &mut dyn FnMut(&Expression)) {
+    f(expr);
+    match &expr.kind {
+        ExpressionKind::StructLiteral(_, fields) => fields.iter().for_each(|(_, e)| walk_expression(e, f)),
+        ExpressionKind::Field(e, _) | ExpressionKind::Unary(_, e) => walk_expression(e, f),
+        ExpressionKind::Call(e, args) => {
+            walk_expression(e, f);
+            args.iter().for_each(|a| walk_expression(a, f));
+        }
+        ExpressionKind::Binary(_, a, b) | ExpressionKind::Index(a, b) | ExpressionKind::Range(a, b, _) => {
+            walk_expression(a, f);
+            walk_expression(b, f);
+        }
+        ExpressionKind::List(items) | ExpressionKind::Interpolation(items) => items.iter().for_each(|e| walk_expression(e, f)),
+        ExpressionKind::Lambda(_, body) => walk_statements(body, f),
+        ExpressionKind::Map(entries) => entries.iter().for_each(|(k, v)| {
+            walk_expression(k, f);
+            walk_expression(v, f);
+        }),
+        _ => {}
+    }
+}
+
+// Parses the code of a single resource, looking up every name it uses
+struct BodyParser<'a> {
+    proj: &'a StructuredFireProject,
+    stream: TokenStream,
+    file: FireLocation,
+    self_type: Option<FireType>,
+    scopes: Vec<Vec<String>>, // The locals declared so far
+    in_pattern: bool // Whether a match arm's patterns are being read, where the => after ( ) starts the arm rather than a lambda
+}
+impl BodyParser<'_> {
+    fn new<'a>(proj: &'a StructuredFireProject, res: &UncompiledFireResource) -> BodyParser<'a> {
+        let self_type = match res.resource_type {
+            UncompiledResourceType::Struct => Some(FireType::Struct(res.loc.clone())),
+            UncompiledResourceType::Enum => Some(FireType::Enum(res.loc.clone())),
+            _ => owning_type(proj, &res.loc)
+        };
+        BodyParser { proj, stream: TokenStream::new(res.tokens.clone(), res.loc.clone()), file: res.file.clone(), self_type, scopes: vec![Vec::new()], in_pattern: false }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|s| s.iter().any(|n| n == name))
+    }
+
+    fn parse_type(&mut self) -> Result<FireType, CompileException> {
+        if self.stream.next_if(&Token::SelfType) {
+            return self.self_type.clone().ok_or_else(|| unexpected(&self.stream.previous()));
+        }
+        let start = self.stream.peek_position();
+        let path = self.stream.expect_path()?;
+        if path.len() == 1 {
+            match path[0].as_str() {
+                "Int" => return Ok(FireType::Int),
+                "Num" => return Ok(FireType::Num),
+                "Boolean" => return Ok(FireType::Boolean),
+                "String" => return Ok(FireType::String),
+                "Player" => return Ok(FireType::Player),
+                "PlayerJoinEvent" => return Ok(FireType::Event(path[0].clone())),
+                "Fn" => {
+                    // Fn(Int, String): Boolean, leaving out the return type if there isn't one
+                    self.stream.expect(Token::OpenParen)?;
+                    let mut params = Vec::new();
+                    while !self.stream.next_if(&Token::CloseParen) {
+                        params.push(self.parse_type()?);
+                        if !self.stream.next_if(&Token::Comma) {
+                            self.stream.expect(Token::CloseParen)?;
+                            break;
+                        }
+                    }
+                    let return_type = if self.stream.next_if(&Token::Colon) { self.parse_type()? } else { FireType::Void };
+                    return Ok(FireType::Function(params, Box::new(return_type)));
+                }
+                "List" => {
+                    self.stream.expect(Token::Less)?;
+                    let element = self.parse_type()?;
+                    self.stream.expect(Token::Greater)?;
+                    return Ok(FireType::List(Box::new(element)));
+                }
+                "Map" => {
+                    self.stream.expect(Token::Less)?;
+                    let at = self.stream.peek_position();
+                    let key = self.parse_type()?;
+                    // Keys are written into paths, so they have to be something that can be written as text
+                    if !matches!(key, FireType::Int | FireType::Boolean | FireType::String | FireType::Enum(_)) {
+                        return Err(CompileException::TypeError(format!("{} cannot be the key of a Map", key), at.0, at.1));
+                    }
+                    self.stream.expect(Token::Comma)?;
+                    let value = self.parse_type()?;
+                    self.stream.expect(Token::Greater)?;
+                    return Ok(FireType::Map(Box::new(key), Box::new(value)));
+                }
+                _ => {}
+            }
+        }
+        let loc = self.proj.resolve(&self.file, &path).ok_or_else(|| CompileException::UnknownName(path.join("::"), start.0, start.1))?;
+        self.check_visible(&loc, start.0, start.1)?;
+        match self.proj.get_resource(&loc).unwrap().resource_type {
+            UncompiledResourceType::Struct => Ok(FireType::Struct(loc)),
+            UncompiledResourceType::Enum => Ok(FireType::Enum(loc)),
+            UncompiledResourceType::Trait => Ok(FireType::Trait(loc)),
+            _ => Err(CompileException::WrongResourceType(loc, "type"))
+        }
+    }
+
+    // Reads a { } block, or a single statement
+    fn parse_block(&mut self) -> Result<Vec<Statement>, CompileException> {
+        self.scopes.push(Vec::new());
+        let mut out = Vec::new();
+        if self.stream.next_if(&Token::OpenBrace) {
+            while !self.stream.next_if(&Token::CloseBrace) {
+                out.push(self.parse_statement()?);
+            }
+        } else {
+            out.push(self.parse_statement()?);
+        }
+        self.scopes.pop();
+        Ok(out)
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, CompileException> {
+        match self.stream.peek() {
+            Some(Token::Let) => {
+                self.stream.next()?;
+                let name = self.stream.expect_identifier()?;
+                let ty = if self.stream.next_if(&Token::Colon) { Some(self.parse_type()?) } else { None };
+                self.stream.expect(Token::Assign)?;
+                let value = self.parse_expression()?;
+                self.stream.expect(Token::Semicolon)?;
+                self.scopes.last_mut().unwrap().push(name.clone());
+                Ok(Statement::Let(name, ty, value))
+            }
+            Some(Token::If) => {
+                self.stream.next()?;
+                let condition = self.parse_expression()?;
+                let body = self.parse_block()?;
+                let otherwise = if self.stream.next_if(&Token::Else) { self.parse_block()? } else { Vec::new() };
+                Ok(Statement::If(condition, body, otherwise))
+            }
+            Some(Token::While) => {
+                self.stream.next()?;
+                let condition = self.parse_expression()?;
+                Ok(Statement::While(condition, self.parse_block()?))
+            }
+            Some(Token::For) => {
+                self.stream.next()?;
+                let name = self.stream.expect_identifier()?;
+                self.stream.expect(Token::In)?;
+                let iterable = self.parse_expression()?;
+                self.scopes.push(vec![name.clone()]);
+                let body = self.parse_block();
+                self.scopes.pop();
+                Ok(Statement::For(name, iterable, body?))
+            }
+            Some(Token::Match) => {
+                self.stream.next()?;
+                let value = self.parse_expression()?;
+                self.stream.expect(Token::OpenBrace)?;
+                let mut arms = Vec::new();
+                while !self.stream.next_if(&Token::CloseBrace) {
+                    let mut patterns = Vec::new();
+                    if !self.stream.next_if(&Token::Else) {
+                        self.in_pattern = true;
+                        let read = self.parse_patterns(&mut patterns);
+                        self.in_pattern = false;
+                        read?;
+                    }
+                    self.stream.expect(Token::FatArrow)?;
+                    arms.push(MatchArm { patterns, body: self.parse_block()? });
+                    self.stream.next_if(&Token::Comma);
+                }
+                Ok(Statement::Match(value, arms))
+            }
+            Some(Token::Select) => {
+                self.stream.next()?;
+                let player = self.parse_expression()?;
+                self.stream.expect(Token::Semicolon)?;
+                Ok(Statement::Select(player))
+            }
+            Some(Token::Return) => {
+                let at = self.stream.next()?;
+                let value = if self.stream.next_if(&Token::Semicolon) {
+                    None
+                } else {
+                    let value = self.parse_expression()?;
+                    self.stream.expect(Token::Semicolon)?;
+                    Some(value)
+                };
+                Ok(Statement::Return(value, at.line, at.column))
+            }
+            Some(Token::Raise) => {
+                let at = self.stream.next()?;
+                let message = if self.stream.next_if(&Token::Semicolon) {
+                    None
+                } else {
+                    let message = self.parse_expression()?;
+                    self.stream.expect(Token::Semicolon)?;
+                    Some(message)
+                };
+                Ok(Statement::Raise(message, at.line, at.column))
+            }
+            Some(Token::Wait) => {
+                let at = self.stream.next()?;
+                self.stream.expect(Token::OpenParen)?;
+                let ticks = self.parse_expression()?;
+                self.stream.expect(Token::CloseParen)?;
+                self.stream.expect(Token::Semicolon)?;
+                Ok(Statement::Wait(ticks, at.line, at.column))
+            }
+            Some(Token::Yield) => {
+                let at = self.stream.next()?;
+                self.stream.expect(Token::Semicolon)?;
+                Ok(Statement::Wait(Expression { kind: ExpressionKind::Int(1), line: at.line, column: at.column }, at.line, at.column))
+            }
+            Some(Token::Try) => {
+                self.stream.next()?;
+                let body = self.parse_block()?;
+                self.stream.expect(Token::Catch)?;
+                let name = if self.stream.next_if(&Token::OpenParen) {
+                    let name = self.stream.expect_identifier()?;
+                    self.stream.expect(Token::CloseParen)?;
+                    Some(name)
+                } else {
+                    None
+                };
+                self.scopes.push(name.iter().cloned().collect());
+                let handler = self.parse_block();
+                self.scopes.pop();
+                Ok(Statement::Try(body, name, handler?))
+            }
+            Some(Token::OpenBrace) => Ok(Statement::Block(self.parse_block()?)),
+            _ => {
+                let target = self.parse_expression()?;
+                let operator = match self.stream.peek() {
+                    Some(Token::Assign) => Some(None),
+                    Some(Token::PlusAssign) => Some(Some(BinaryOperator::Add)),
+                    Some(Token::MinusAssign) => Some(Some(BinaryOperator::Subtract)),
+                    Some(Token::MultiplyAssign) => Some(Some(BinaryOperator::Multiply)),
+                    Some(Token::DivideAssign) => Some(Some(BinaryOperator::Divide)),
+                    Some(Token::ModAssign) => Some(Some(BinaryOperator::Modulo)),
+                    _ => None
+                };
+                let statement = if let Some(operator) = operator {
+                    self.stream.next()?;
+                    Statement::Assign(target, operator, self.parse_expression()?)
+                } else if matches!(self.stream.peek(), Some(Token::Increment) | Some(Token::Decrement)) {
+                    let operator = if self.stream.next()?.token == Token::Increment { BinaryOperator::Add } else { BinaryOperator::Subtract };
+                    let one = Expression { kind: ExpressionKind::Int(1), line: target.line, column: target.column };
+                    Statement::Assign(target, Some(operator), one)
+                } else {
+                    Statement::Expression(target)
+                };
+                self.stream.expect(Token::Semicolon)?;
+                Ok(statement)
+            }
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, CompileException> {
+        self.parse_binary(0)
+    }
+
+    // Precedence climbing, higher binds tighter
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expression, CompileException> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let (line, column) = (left.line, left.column);
+            // Ranges bind looser than arithmetic but tighter than comparisons, so x in 0..n + 1 works
+            if let Some(inclusive) = match self.stream.peek() { Some(Token::Range) => Some(false), Some(Token::RangeInclusive) => Some(true), _ => None } {
+                if RANGE_PRECEDENCE < min_precedence {
+                    break;
+                }
+                self.stream.next()?;
+                let end = self.parse_binary(RANGE_PRECEDENCE + 1)?;
+                left = Expression { kind: ExpressionKind::Range(Box::new(left), Box::new(end), inclusive), line, column };
+                continue;
+            }
+            let Some((operator, precedence)) = self.stream.peek().and_then(binary_operator) else { break };
+            if precedence < min_precedence {
+                break;
+            }
+            self.stream.next()?;
+            let right = self.parse_binary(precedence + 1)?;
+            left = Expression { kind: ExpressionKind::Binary(operator, Box::new(left), Box::new(right)), line, column };
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, CompileException> {
+        let (line, column) = self.stream.peek_position();
+        let operator = match self.stream.peek() {
+            Some(Token::Minus) => UnaryOperator::Negate,
+            Some(Token::Not) => UnaryOperator::Not,
+            _ => {
+                let expr = self.parse_postfix()?;
+                return match expr.kind { // An Int has to fit in a score
+                    ExpressionKind::Int(n) if n > i32::MAX as i64 => Err(CompileException::OutOfRange(n.to_string(), "Int", line, column)),
+                    _ => Ok(expr)
+                };
+            }
+        };
+        self.stream.next()?;
+        // -2147483648 fits even though 2147483648 doesn't
+        if operator == UnaryOperator::Negate && self.stream.peek() == Some(&Token::Int(1 << 31)) {
+            self.stream.next()?;
+            return Ok(Expression { kind: ExpressionKind::Int(i32::MIN as i64), line, column });
+        }
+        let inner = self.parse_unary()?;
+        Ok(Expression { kind: ExpressionKind::Unary(operator, Box::new(inner)), line, column })
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, CompileException> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            let (line, column) = (expr.line, expr.column);
+            match self.stream.peek() {
+                Some(Token::Dot) => {
+                    self.stream.next()?;
+                    let name = self.stream.expect_identifier()?;
+                    // Positioned at the name, which is what errors about it point at and what renaming a member changes
+                    let at = self.stream.previous();
+                    expr = Expression { kind: ExpressionKind::Field(Box::new(expr), name), line: at.line, column: at.column };
+                }
+                Some(Token::OpenParen) => {
+                    self.stream.next()?;
+                    let mut args = Vec::new();
+                    while !self.stream.next_if(&Token::CloseParen) {
+                        args.push(self.parse_expression()?);
+                        if !self.stream.next_if(&Token::Comma) {
+                            self.stream.expect(Token::CloseParen)?;
+                            break;
+                        }
+                    }
+                    expr = Expression { kind: ExpressionKind::Call(Box::new(expr), args), line, column };
+                }
+                Some(Token::OpenBracket) => {
+                    self.stream.next()?;
+                    let key = self.parse_expression()?;
+                    self.stream.expect(Token::CloseBracket)?;
+                    expr = Expression { kind: ExpressionKind::Index(Box::new(expr), Box::new(key)), line, column };
+                }
+                _ => return Ok(expr)
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, CompileException> {
+        let (line, column) = self.stream.peek_position();
+        let kind = match self.stream.peek() {
+            Some(Token::Int(_)) | Some(Token::Num(_)) | Some(Token::String(_)) => match self.stream.next()?.token {
+                Token::Int(n) => ExpressionKind::Int(n),
+                Token::Num(_) => ExpressionKind::Num,
+                Token::String(s) => ExpressionKind::String(s),
+                _ => unreachable!()
+            },
+            Some(Token::Interpolated(_)) => {
+                let Token::Interpolated(parts) = self.stream.next()?.token else { unreachable!() };
+                let mut expressions = Vec::with_capacity(parts.len());
+                for part in parts {
+                    match part {
+                        StringPart::Text(s) => expressions.push(Expression { kind: ExpressionKind::String(s), line, column }),
+                        StringPart::Code(tokens) => {
+                            // Each value is read from its own tokens, and has to use all of them
+                            let inner = self.stream.inner(tokens);
+                            let outer = std::mem::replace(&mut self.stream, inner);
+                            let value = self.parse_expression().and_then(|v| match self.stream.is_empty() {
+                                true => Ok(v),
+                                false => Err(unexpected(&self.stream.next()?))
+                            });
+                            self.stream = outer;
+                            expressions.push(value?);
+                        }
+                    }
+                }
+                ExpressionKind::Interpolation(expressions)
+            }
+            Some(Token::True) | Some(Token::False) => ExpressionKind::Boolean(self.stream.next()?.token == Token::True),
+            Some(Token::SelfIdent) => {
+                self.stream.next()?;
+                ExpressionKind::SelfValue
+            }
+            Some(Token::OpenBracket) => {
+                self.stream.next()?;
+                self.parse_collection_literal()?
+            }
+            Some(Token::OpenParen) if !self.in_pattern && self.is_lambda() => self.parse_lambda()?,
+            Some(Token::OpenParen) => {
+                self.stream.next()?;
+                let inner = self.parse_expression()?;
+                self.stream.expect(Token::CloseParen)?;
+                return Ok(inner);
+            }
+            Some(Token::Identifier(n)) if self.is_local(n) => ExpressionKind::Variable(self.stream.expect_identifier()?),
+            Some(Token::Identifier(_)) | Some(Token::Type(_)) | Some(Token::SelfType) => {
+                let mut path = Vec::new();
+                if self.stream.next_if(&Token::SelfType) {
+                    match &self.self_type {
+                        Some(FireType::Struct(l)) | Some(FireType::Enum(l)) => path.extend(l.segments().iter().cloned()),
+                        _ => return Err(unexpected(&self.stream.previous()))
+                    }
+                    self.stream.expect(Token::Accesser)?;
+                }
+                path.append(&mut self.stream.expect_path()?);
+                self.resolve_path(path, line, column)?
+            }
+            _ => return Err(unexpected(&self.stream.next()?))
+        };
+        Ok(Expression { kind, line, column })
+    }
+
+    // Finds the resource a path refers to. Only the start of the path has to be a resource, the rest are members of it.
+    fn check_visible(&self, loc: &FireLocation, line: usize, column: usize) -> Result<(), CompileException> {
+        match self.proj.hidden_from(&self.file, loc) {
+            Some((hidden, level, boundary)) => Err(CompileException::NotVisible(hidden, level, boundary, line, column)),
+            None => Ok(())
+        }
+    }
+
+    fn resolve_path(&mut self, path: Vec<String>, line: usize, column: usize) -> Result<ExpressionKind, CompileException> {
+        for n in (1..=path.len()).rev() {
+            if let Some(loc) = self.proj.resolve(&self.file, &path[..n]) {
+                self.check_visible(&loc, line, column)?;
+                if let (UncompiledResourceType::Struct, Some(Token::OpenBrace), true) = (&self.proj.get_resource(&loc).unwrap().resource_type, self.stream.peek(), n == path.len()) {
+                    return Ok(ExpressionKind::StructLiteral(loc, self.parse_struct_literal()?));
+                }
+                return Ok(ExpressionKind::Resource(loc, path[n..].to_vec()));
+            }
+        }
+        if path.len() == 1 {
+            Ok(ExpressionKind::Builtin(path[0].clone()))
+        } else {
+            Err(CompileException::UnknownName(path.join("::"), line, column))
+        }
+    }
+
+    // Whether the ( coming up starts a lambda, which is when the matching ) is followed by =>
+    // The patterns of a match arm, split by ,
+    fn parse_patterns(&mut self, patterns: &mut Vec<Expression>) -> Result<(), CompileException> {
+        loop {
+            patterns.push(self.parse_expression()?);
+            if !self.stream.next_if(&Token::Comma) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn is_lambda(&self) -> bool {
+        let mut depth = 0;
+        for n in 0.. {
+            match self.stream.peek_nth(n) {
+                Some(Token::OpenParen) => depth += 1,
+                Some(Token::CloseParen) if depth == 1 => return self.stream.peek_nth(n + 1) == Some(&Token::FatArrow),
+                Some(Token::CloseParen) => depth -= 1,
+                None => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    // (x, y: Int) => x + y or (x) => { ... }. Parameters without a type get one from where the lambda is used.
+    fn parse_lambda(&mut self) -> Result<ExpressionKind, CompileException> {
+        self.stream.expect(Token::OpenParen)?;
+        let mut params = Vec::new();
+        while !self.stream.next_if(&Token::CloseParen) {
+            let name = self.stream.expect_identifier()?;
+            let ty = if self.stream.next_if(&Token::Colon) { Some(self.parse_type()?) } else { None };
+            params.push((name, ty));
+            if !self.stream.next_if(&Token::Comma) {
+                self.stream.expect(Token::CloseParen)?;
+                break;
+            }
+        }
+        self.stream.expect(Token::FatArrow)?;
+        self.scopes.push(params.iter().map(|(n, _)| n.clone()).collect());
+        let body = if self.stream.peek() == Some(&Token::OpenBrace) {
+            self.parse_block()
+        } else {
+            let (line, column) = self.stream.peek_position();
+            self.parse_expression().map(|e| vec![Statement::Return(Some(e), line, column)])
+        };
+        self.scopes.pop();
+        Ok(ExpressionKind::Lambda(params, body?))
+    }
+
+    // [1, 2, 3] is a List and ["a": 1, "b": 2] is a Map, with [:] being an empty one
+    fn parse_collection_literal(&mut self) -> Result<ExpressionKind, CompileException> {
+        if self.stream.next_if(&Token::Colon) {
+            self.stream.expect(Token::CloseBracket)?;
+            return Ok(ExpressionKind::Map(Vec::new()));
+        }
+        let mut items = Vec::new();
+        let mut entries = Vec::new();
+        while !self.stream.next_if(&Token::CloseBracket) {
+            let item = self.parse_expression()?;
+            // The first item decides whether it's a List or a Map
+            let is_entry = if !entries.is_empty() {
+                self.stream.expect(Token::Colon)?;
+                true
+            } else {
+                items.is_empty() && self.stream.next_if(&Token::Colon)
+            };
+            if is_entry {
+                entries.push((item, self.parse_expression()?));
+            } else {
+                items.push(item);
+            }
+            if !self.stream.next_if(&Token::Comma) {
+                self.stream.expect(Token::CloseBracket)?;
+                break;
+            }
+        }
+        Ok(if entries.is_empty() { ExpressionKind::List(items) } else { ExpressionKind::Map(entries) })
+    }
+
+    fn parse_struct_literal(&mut self) -> Result<Vec<(String, Expression)>, CompileException> {
+        self.stream.expect(Token::OpenBrace)?;
+        let mut fields = Vec::new();
+        while !self.stream.next_if(&Token::CloseBrace) {
+            let name = self.stream.expect_identifier()?;
+            self.stream.expect(Token::Colon)?;
+            fields.push((name, self.parse_expression()?));
+            if !self.stream.next_if(&Token::Comma) {
+                self.stream.expect(Token::CloseBrace)?;
+                break;
+            }
+        }
+        Ok(fields)
+    }
+}
+
+const RANGE_PRECEDENCE: u8 = 6;
+
+fn binary_operator(token: &Token) -> Option<(BinaryOperator, u8)> {
+    Some(match token {
+        Token::StrictOr => (BinaryOperator::StrictOr, 1),
+        Token::Or => (BinaryOperator::Or, 1),
+        Token::StrictAnd => (BinaryOperator::StrictAnd, 2),
+        Token::And => (BinaryOperator::And, 2),
+        Token::Xor => (BinaryOperator::Xor, 3),
+        Token::Equals => (BinaryOperator::Equal, 4),
+        Token::NotEqual => (BinaryOperator::NotEqual, 4),
+        Token::Greater => (BinaryOperator::Greater, 5),
+        Token::Less => (BinaryOperator::Less, 5),
+        Token::GreaterEqual => (BinaryOperator::GreaterEqual, 5),
+        Token::LessEqual => (BinaryOperator::LessEqual, 5),
+        Token::In => (BinaryOperator::In, 5),
+        Token::Plus => (BinaryOperator::Add, 7),
+        Token::Minus => (BinaryOperator::Subtract, 7),
+        Token::Multiply => (BinaryOperator::Multiply, 8),
+        Token::Divide => (BinaryOperator::Divide, 8),
+        Token::Mod => (BinaryOperator::Modulo, 8),
+        _ => return None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compiler::CompileException, parser::{self, FireLocation, StructuredFireProject}, tokenizer};
+    use super::{parse_project, Expression, ExpressionKind, ParsedFireProject, Statement};
+
+    // Parses source as if it were the file main::test
+    fn parse(source: &str) -> Result<ParsedFireProject, CompileException> {
+        let mut project = StructuredFireProject::new();
+        let file = FireLocation::from_path(&["main".to_string(), "test".to_string()]);
+        parser::structure_file(&mut project, &file, tokenizer::tokenize(source)?)?;
+        project.register_implementations()?;
+        parse_project(&project)
+    }
+
+    // What the function main::test::f returns
+    fn returned(source: &str) -> Result<ExpressionKind, CompileException> {
+        let parsed = parse(source)?;
+        let f = &parsed.functions[&FireLocation::from_path(&["main".to_string(), "test".to_string(), "f".to_string()])];
+        match &f.body[..] {
+            [Statement::Return(Some(e), _, _)] => Ok(e.kind.clone()),
+            _ => panic!("f should only return")
+        }
+    }
+
+    #[test]
+    fn int_range() {
+        assert!(matches!(returned("fn f(): Int { return -2147483648; }"), Ok(ExpressionKind::Int(n)) if n == i32::MIN as i64));
+        assert!(matches!(returned("fn f(): Int { return 2147483647; }"), Ok(ExpressionKind::Int(2147483647))));
+        let too_big = returned("fn f(): Int { return 2147483648; }").err().map(|e| e.to_string());
+        assert_eq!(too_big.as_deref(), Some("Inside of main::test::f: 2147483648 does not fit in an Int. At 1:22"));
+        let parenthesized = returned("fn f(): Int { return -(2147483648); }").err().map(|e| e.to_string());
+        assert_eq!(parenthesized.as_deref(), Some("Inside of main::test::f: 2147483648 does not fit in an Int. At 1:24"));
+    }
+    #[test]
+    fn unknown_impl_type() {
+        let error = parse("fn f(): Int { return 0; }\n\nimpl Missing {\n}\n").err().map(|e| e.to_string());
+        assert_eq!(error.as_deref(), Some("Cannot find resource Missing. At 3:1"));
+    }
+
+    #[test]
+    fn parenthesized_patterns() {
+        let parsed = parse("enum Color {\n    Red, Green\n}\n\nfn f(x: Int, c: Color): Int {\n    match x {\n        (1), 2 => { return 10; }\n        else => {}\n    }\n    match c {\n        (Color::Red) => { return 20; }\n        else => { return ((y: Int) => y)(30); }\n    }\n}\n");
+        let parsed = parsed.unwrap_or_else(|e| panic!("{}", e));
+        let f = &parsed.functions[&FireLocation::from_path(&["main".to_string(), "test".to_string(), "f".to_string()])];
+        let [Statement::Match(_, numbers), Statement::Match(_, colors)] = &f.body[..] else { panic!("f should have two matches") };
+        assert!(matches!(numbers[0].patterns[..], [Expression { kind: ExpressionKind::Int(1), .. }, Expression { kind: ExpressionKind::Int(2), .. }]));
+        assert!(matches!(colors[0].patterns[..], [Expression { kind: ExpressionKind::Resource(..), .. }]));
+        // Lambdas still work inside of the arms
+        assert!(matches!(&colors[1].body[..], [Statement::Return(Some(Expression { kind: ExpressionKind::Call(..), .. }), _, _)]));
+    }
+}
@@ -0,0 +1,377 @@
+use std::{cmp::Reverse, collections::HashMap, mem::discriminant, path::{Path, PathBuf}};
+
+use crate::{compiler::{CompileException, MemberUse}, parser::{FireLocation, StructuredFireProject, UncompiledFireResource, UncompiledResourceType}, tokenizer::{self, LosslessToken, PositionedToken, Token}};
+
+// A file of a project, with its text as it is right now, which may not be saved yet
+pub struct SourceFile {
+    pub location: FireLocation,
+    pub path: PathBuf,
+    pub text: String
+}
+
+// Somewhere a name is written, with the line and column counted like the tokenizer does
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize, // In characters
+    pub declaration: bool // Whether this is where it was declared, rather than a use of it
+}
+
+// What a rename changes: the new text of every reference, and the files or folders that get new names
+pub struct Rename {
+    pub edits: Vec<(Reference, String)>,
+    pub moves: Vec<(PathBuf, PathBuf)>
+}
+
+// A name written in a file, and the resource, struct field, file or folder that it refers to
+struct Name {
+    file: usize,
+    line: usize,
+    column: usize,
+    text: String,
+    target: FireLocation,
+    declaration: bool
+}
+
+// Knows what every name in a project refers to, so that they can be found and renamed together.
+// Struct fields have the location of their struct with the field's name on the end, like methods do.
+pub struct ProjectIndex<'a> {
+    project: &'a StructuredFireProject,
+    files: &'a [SourceFile],
+    names: Vec<Name>
+}
+
+impl<'a> ProjectIndex<'a> {
+    // Looks at every name in the project. Names used with a . need the types of values, which the compiler finds for member_uses.
+    pub fn new(project: &'a StructuredFireProject, files: &'a [SourceFile], members: &[MemberUse]) -> ProjectIndex<'a> {
+        let mut index = ProjectIndex { project, files, names: Vec::new() };
+        for (i, file) in files.iter().enumerate() {
+            let Ok((tokens, _)) = tokenizer::tokenize_lossless(&file.text) else { continue };
+            let members: HashMap<(usize, usize), &FireLocation> = members.iter()
+                .filter(|m| project.source_of(&m.within) == Some(&file.location))
+                .map(|m| ((m.line, m.column), &m.member))
+                .collect();
+            index.names.extend(index.read_file(i, &tokens, &members));
+        }
+        index
+    }
+
+    // Finds what the names of a file refer to
+    fn read_file(&self, file: usize, tokens: &[LosslessToken], members: &HashMap<(usize, usize), &FireLocation>) -> Vec<Name> {
+        let source = &self.files[file];
+        let resources = self.resources_in(source);
+        let declarations = declarations(&resources);
+        let mut names = Vec::new();
+        let mut open: Vec<Option<FireLocation>> = Vec::new(); // For each bracket that's open, the struct being made if it's Thing { }
+        for (i, t) in tokens.iter().enumerate() {
+            let at = (t.token.line, t.token.column);
+            let previous = i.checked_sub(1).map(|p| &tokens[p].token.token);
+            let next = tokens.get(i + 1).map(|n| &n.token.token);
+            let (target, declaration) = match &t.token.token {
+                Token::OpenBrace => {
+                    open.push(self.literal_at(source, &resources, tokens, i));
+                    continue;
+                }
+                Token::OpenParen | Token::OpenBracket => {
+                    open.push(None);
+                    continue;
+                }
+                Token::CloseBrace | Token::CloseParen | Token::CloseBracket => {
+                    open.pop();
+                    continue;
+                }
+                Token::Identifier(_) | Token::Type(_) => match declarations.get(&at) {
+                    Some(loc) => (Some(loc.clone()), true),
+                    None if previous == Some(&Token::Dot) => (members.get(&at).map(|l| (*l).clone()), false),
+                    // A field of a struct being made, such as the a in Thing { a: 1 }
+                    None if next == Some(&Token::Colon) && matches!(previous, Some(Token::OpenBrace | Token::Comma)) && matches!(open.last(), Some(Some(_))) => {
+                        (open.last().cloned().flatten().map(|s| s.with(t.text.clone())), false)
+                    }
+                    // A group opened again in another file, or a local being declared
+                    None if previous.is_some_and(declares) && next != Some(&Token::Accesser) => {
+                        let group = self.scope_at(source, &resources, at, true).with(t.text.clone());
+                        let found = previous == Some(&Token::GroupDef) && self.project.get_resource(&group).is_some();
+                        (found.then_some(group), true)
+                    }
+                    None => (self.path_target(source, &resources, tokens, i), false)
+                },
+                _ => continue
+            };
+            if let Some(target) = target {
+                names.push(Name { file, line: at.0, column: at.1, text: t.text.clone(), target, declaration });
+            }
+        }
+        names
+    }
+
+    // What a name that's part of a path such as main::things::Thing refers to. For a name in the middle, that's where the
+    // path up to it leads, which may be a file or folder.
+    fn path_target(&self, source: &SourceFile, resources: &[&UncompiledFireResource], tokens: &[LosslessToken], i: usize) -> Option<FireLocation> {
+        let is_name = |t: &LosslessToken| matches!(t.token.token, Token::Identifier(_) | Token::Type(_));
+        let mut start = i;
+        while start >= 2 && tokens[start - 1].token.token == Token::Accesser && is_name(&tokens[start - 2]) {
+            start -= 2;
+        }
+        let mut end = i;
+        while end + 2 < tokens.len() && tokens[end + 1].token.token == Token::Accesser && is_name(&tokens[end + 2]) {
+            end += 2;
+        }
+        let path: Vec<String> = tokens[start..=end].iter().step_by(2).map(|t| t.text.clone()).collect();
+        let k = (i - start) / 2;
+        let before = start.checked_sub(1).map(|b| &tokens[b].token.token);
+        let at = (tokens[i].token.line, tokens[i].token.column);
+        if path.len() == 1 && self.is_local(resources, at, &path[0]) {
+            return None;
+        }
+        // Imports and groups opened by their full path always start from the root of the project
+        let absolute = before == Some(&Token::Import) || before == Some(&Token::GroupDef) && path.len() > 1;
+        let scope = self.scope_at(source, resources, at, false);
+        // The whole path may not lead anywhere, as in MyEnum::fromInt, so shorter ones are tried too
+        (k + 1..=path.len()).rev().find_map(|n| {
+            let whole = FireLocation::from_path(&path[..n]);
+            let found = match absolute {
+                true => Some(whole).filter(|l| self.project.get_resource(l).is_some() || self.is_file_or_folder(l)),
+                false => self.project.resolve(&scope, &path[..n]).or_else(|| Some(whole).filter(|l| self.is_file_or_folder(l)))
+            }?;
+            let keep = found.segments().len().checked_sub(n - 1 - k)?;
+            Some(FireLocation::from_path(&found.segments()[..keep]))
+        })
+    }
+
+    // The struct being made by a { at tokens[i], as in Thing { a: 1 }. Like the formatter, a Type after a : is a return type
+    // with a body after it rather than a struct being made.
+    fn literal_at(&self, source: &SourceFile, resources: &[&UncompiledFireResource], tokens: &[LosslessToken], i: usize) -> Option<FireLocation> {
+        let mut start = i.checked_sub(1)?;
+        if !matches!(tokens[start].token.token, Token::Type(_)) {
+            return None;
+        }
+        while start >= 2 && tokens[start - 1].token.token == Token::Accesser {
+            start -= 2;
+        }
+        let before = start.checked_sub(1).map(|b| &tokens[b].token.token);
+        if before.is_some_and(|b| matches!(b, Token::Colon | Token::For | Token::Implementation) || declares(b)) {
+            return None;
+        }
+        let path: Vec<String> = tokens[start..i].iter().step_by(2).map(|t| t.text.clone()).collect();
+        let scope = self.scope_at(source, resources, (tokens[i].token.line, tokens[i].token.column), false);
+        let loc = self.project.resolve(&scope, &path)?;
+        matches!(self.project.get_resource(&loc)?.resource_type, UncompiledResourceType::Struct).then_some(loc)
+    }
+
+    // The resources written in a file, in the order they start, with the widest first
+    fn resources_in(&self, file: &SourceFile) -> Vec<&'a UncompiledFireResource> {
+        let mut found: Vec<&UncompiledFireResource> = self.project.resources()
+            .filter(|r| !r.tokens.is_empty() && self.project.source_of(&r.loc) == Some(&file.location))
+            .collect();
+        found.sort_by_key(|r| (r.tokens[0].line, r.tokens[0].column, Reverse(r.tokens.len())));
+        found
+    }
+
+    // Where names written at a position are looked up from: the group around it, or the file. With strict, a resource that
+    // starts right at the position doesn't count, which is what a declaration's own name needs.
+    fn scope_at(&self, file: &SourceFile, resources: &[&UncompiledFireResource], at: (usize, usize), strict: bool) -> FireLocation {
+        let around = resources.iter().rev().find(|r| {
+            let (first, last) = (&r.tokens[0], &r.tokens[r.tokens.len() - 1]);
+            let start = (first.line, first.column);
+            (start < at || !strict && start == at) && at <= (last.line, last.column)
+        });
+        match around {
+            Some(r) if matches!(r.resource_type, UncompiledResourceType::Group) => r.loc.clone(),
+            Some(r) => r.file.clone(),
+            None => file.location.clone()
+        }
+    }
+
+    // Whether a name at a position is a local or parameter of the function around it
+    fn is_local(&self, resources: &[&UncompiledFireResource], at: (usize, usize), name: &str) -> bool {
+        let function = resources.iter().rev().find(|r| {
+            let (first, last) = (&r.tokens[0], &r.tokens[r.tokens.len() - 1]);
+            (first.line, first.column) <= at && at <= (last.line, last.column)
+        });
+        let Some(function) = function.filter(|r| matches!(r.resource_type, UncompiledResourceType::Function | UncompiledResourceType::Method | UncompiledResourceType::Process)) else {
+            return false;
+        };
+        (0..function.tokens.len())
+            .take_while(|i| (function.tokens[*i].line, function.tokens[*i].column) <= at)
+            .any(|i| declared(&function.tokens, i) == Some(name))
+    }
+
+    // Whether a location is a file of the project or a folder with files in it
+    fn is_file_or_folder(&self, loc: &FireLocation) -> bool {
+        self.files.iter().any(|f| f.location.segments().starts_with(loc.segments()))
+    }
+
+    // Everything that has to be renamed along with a location. Renaming a trait's method renames it in every impl of the trait too.
+    fn family(&self, target: &FireLocation) -> Vec<FireLocation> {
+        let Some(name) = target.last() else { return vec![target.clone()] };
+        let parent = target.parent();
+        let trait_loc = match self.project.get_resource(&parent).map(|r| &r.resource_type) {
+            Some(UncompiledResourceType::Trait) => parent,
+            Some(UncompiledResourceType::Implementation(Some(t))) => t.clone(),
+            // A method declared inside of a struct or enum may be how it implements one of its traits
+            Some(UncompiledResourceType::Struct | UncompiledResourceType::Enum) => {
+                let implemented = self.project.resources().find_map(|r| match &r.resource_type {
+                    UncompiledResourceType::Implementation(Some(t)) if r.loc.parent() == parent && self.project.get_resource(&t.with(name.clone())).is_some() => Some(t.clone()),
+                    _ => None
+                });
+                match implemented {
+                    Some(t) => t,
+                    None => return vec![target.clone()]
+                }
+            }
+            _ => return vec![target.clone()]
+        };
+        // Methods of traits implemented inline, as in struct Pair : Comparable, are declared on the type itself
+        let mut family = vec![trait_loc.with(name.clone())];
+        for imp in self.project.resources().filter(|r| matches!(&r.resource_type, UncompiledResourceType::Implementation(Some(t)) if *t == trait_loc)) {
+            family.extend([imp.loc.with(name.clone()), imp.loc.parent().with(name.clone())].into_iter().filter(|l| self.project.get_resource(l).is_some()));
+        }
+        family
+    }
+
+    // What the name at a position refers to, if it's a name
+    pub fn target_at(&self, path: &Path, line: usize, column: usize) -> Option<&FireLocation> {
+        self.names.iter()
+            .find(|n| self.files[n.file].path == path && n.line == line && n.column <= column && column <= n.column + n.text.chars().count())
+            .map(|n| &n.target)
+    }
+
+    // Every place that refers to a resource, struct field, file or folder, including where it was declared. Names brought in
+    // with import ... as are left out, since they're called something else where they're used.
+    pub fn references(&self, target: &FireLocation) -> Vec<Reference> {
+        let family = self.family(target);
+        self.names.iter()
+            .filter(|n| family.contains(&n.target) && Some(&n.text) == target.last())
+            .map(|n| Reference {
+                path: self.files[n.file].path.clone(),
+                line: n.line,
+                column: n.column,
+                length: n.text.chars().count(),
+                declaration: n.declaration
+            })
+            .collect()
+    }
+
+    // Renames a resource, struct field, file or folder, along with everything that refers to it
+    pub fn rename(&self, target: &FireLocation, new_name: &str) -> Result<Rename, CompileException> {
+        let Some(old_name) = target.last() else {
+            return Err(CompileException::InvalidRename("the root of the project has no name".to_string()));
+        };
+        // The new name has to be the same kind of token, so a Type stays a Type
+        let kind = |name: &str| match tokenizer::tokenize(name).ok()?.as_slice() {
+            [PositionedToken { token: t @ (Token::Identifier(_) | Token::Type(_)), .. }] => Some(discriminant(t)),
+            _ => None
+        };
+        if kind(new_name).is_none() || kind(new_name) != kind(old_name) {
+            return Err(CompileException::InvalidRename(format!("{} cannot be called {}", target, new_name)));
+        }
+        for loc in self.family(target) {
+            let renamed = loc.parent().with(new_name.to_string());
+            if self.project.get_resource(&renamed).is_some() || self.is_file_or_folder(&renamed) || self.names.iter().any(|n| n.declaration && n.target == renamed) {
+                return Err(CompileException::InvalidRename(format!("{} already exists", renamed)));
+            }
+        }
+        let mut moves = Vec::new();
+        if self.project.get_resource(target).is_none() {
+            // A file is renamed along with its .fire, and a folder is found from a file inside of it
+            if let Some(file) = self.files.iter().find(|f| f.location == *target) {
+                moves.push((file.path.clone(), file.path.with_file_name(format!("{}.fire", new_name))));
+            } else if let Some(file) = self.files.iter().find(|f| f.location.segments().starts_with(target.segments())) {
+                let depth = file.location.segments().len() - target.segments().len();
+                if let Some(folder) = file.path.ancestors().nth(depth) {
+                    moves.push((folder.to_path_buf(), folder.with_file_name(new_name)));
+                }
+            }
+            if let Some((_, to)) = moves.iter().find(|(_, to)| to.exists()) {
+                return Err(CompileException::InvalidRename(format!("{:?} already exists", to)));
+            }
+        }
+        let edits = self.references(target).into_iter().map(|r| (r, new_name.to_string())).collect();
+        Ok(Rename { edits, moves })
+    }
+
+    // The edits that go with a file or folder being renamed, such as by an editor. It can't be moved into another folder, since
+    // paths from other folders may not lead to it anymore.
+    pub fn rename_path(&self, from: &Path, to: &Path) -> Result<Rename, CompileException> {
+        if from.parent() != to.parent() {
+            return Err(CompileException::InvalidRename(format!("{:?} can only be renamed inside of the folder it is in", from)));
+        }
+        let target = self.files.iter().find_map(|f| {
+            let depth = f.path.strip_prefix(from).ok()?.components().count();
+            let segments = f.location.segments();
+            Some(FireLocation::from_path(&segments[..segments.len().checked_sub(depth)?]))
+        }).ok_or_else(|| CompileException::InvalidRename(format!("{:?} is not part of the project", from)))?;
+        let new_name = if from.extension().is_some_and(|e| e == "fire") { to.file_stem() } else { to.file_name() };
+        let new_name = new_name.map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let mut rename = self.rename(&target, &new_name)?;
+        rename.moves.clear(); // Whatever asked for this moves it
+        Ok(rename)
+    }
+}
+
+// Where the names of a file's resources and struct fields are declared
+fn declarations(resources: &[&UncompiledFireResource]) -> HashMap<(usize, usize), FireLocation> {
+    let mut found = HashMap::new();
+    for res in resources {
+        // An impl is named after its trait, which is already declared somewhere else
+        if matches!(res.resource_type, UncompiledResourceType::Implementation(_)) {
+            continue;
+        }
+        if let Some(t) = name_token(res) {
+            found.insert((t.line, t.column), res.loc.clone());
+        }
+        if let UncompiledResourceType::Struct = res.resource_type {
+            let mut depth = 0;
+            for (i, t) in res.tokens.iter().enumerate() {
+                match t.token {
+                    Token::OpenBrace | Token::OpenParen | Token::OpenBracket => depth += 1,
+                    Token::CloseBrace | Token::CloseParen | Token::CloseBracket => depth -= 1,
+                    Token::Identifier(ref name) if depth == 1 && res.tokens.get(i + 1).is_some_and(|n| n.token == Token::Colon) => {
+                        found.insert((t.line, t.column), res.loc.with(name.clone()));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    found
+}
+
+// The keywords that come right before the name of what they declare
+fn declares(token: &Token) -> bool {
+    matches!(token, Token::FuncDef | Token::ProcDef | Token::StructDef | Token::EnumDef | Token::TraitDef | Token::GroupDef | Token::Let | Token::Const)
+}
+
+// The token with a resource's name in it, which comes after its keyword
+pub fn name_token(res: &UncompiledFireResource) -> Option<&PositionedToken> {
+    let name = res.loc.last()?;
+    res.tokens.iter().find(|t| matches!(&t.token, Token::Identifier(n) | Token::Type(n) if n == name))
+}
+
+// The name a local or parameter declares at a token, as in let x, for x in, fn f(x: Int) and catch (e)
+pub fn declared(tokens: &[PositionedToken], i: usize) -> Option<&str> {
+    let Token::Identifier(name) = &tokens[i].token else { return None };
+    let before = |n: usize| i.checked_sub(n).map(|j| &tokens[j].token);
+    let after = tokens.get(i + 1).map(|t| &t.token);
+    let declares = matches!(before(1), Some(Token::Let | Token::For))
+        || matches!(before(1), Some(Token::OpenParen | Token::Comma)) && after == Some(&Token::Colon) && in_parens(tokens, i)
+        || before(1) == Some(&Token::OpenParen) && before(2) == Some(&Token::Catch);
+    declares.then_some(name.as_str())
+}
+
+// Whether the innermost bracket around a token is a (, unlike the fields of Thing { a: 1, b: 2 }
+fn in_parens(tokens: &[PositionedToken], i: usize) -> bool {
+    let mut depth = 0;
+    for t in tokens[..i].iter().rev() {
+        match t.token {
+            Token::CloseParen | Token::CloseBrace | Token::CloseBracket => depth += 1,
+            Token::OpenParen if depth == 0 => return true,
+            Token::OpenBrace | Token::OpenBracket if depth == 0 => return false,
+            Token::OpenParen | Token::OpenBrace | Token::OpenBracket => depth -= 1,
+            _ => {}
+        }
+    }
+    false
+}
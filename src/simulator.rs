@@ -0,0 +1,1648 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Display, fs, mem, path::{Path, PathBuf}, panic, sync::Arc, thread};
+
+use crate::{compiler::CompiledDatapack, json::{self, Json}};
+
+// An offline stand-in for a Minecraft server, running the commands the compiler writes so datapacks can be checked without the game
+
+const MAX_COMMANDS: usize = 65536; // The game's default maxCommandChainLength, counted for each function the server starts
+const MAX_DEPTH: usize = 1024; // How deep functions can call each other before giving up, instead of overflowing the stack
+const STACK_SIZE: usize = 64 * 1024 * 1024; // Enough to reach MAX_DEPTH through execute ... run function, whatever thread the simulator is on
+const LEAVE_GAME: &str = "minecraft.custom:minecraft.leave_game";
+static EMPTY: Nbt = Nbt::Compound(BTreeMap::new());
+
+// An NBT tag, as kept in data storage
+#[derive(Debug, Clone, PartialEq)]
+pub enum Nbt {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    List(Vec<Nbt>),
+    Compound(BTreeMap<String, Nbt>)
+}
+
+impl Nbt {
+    // Reads a value written as SNBT, like {name:"Alex",scores:[1, 2]}
+    pub fn parse(text: &str) -> Option<Nbt> {
+        let mut reader = Reader::new(text);
+        let value = reader.snbt().ok()?;
+        reader.at_end().then_some(value)
+    }
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Nbt::Byte(n) => Some(*n as f64),
+            Nbt::Short(n) => Some(*n as f64),
+            Nbt::Int(n) => Some(*n as f64),
+            Nbt::Long(n) => Some(*n as f64),
+            Nbt::Float(n) => Some(*n as f64),
+            Nbt::Double(n) => Some(*n),
+            _ => None
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Nbt::String(s) => Some(s),
+            _ => None
+        }
+    }
+    // What data get gives for a tag: numbers scaled and rounded down, or the size of a string, list or compound
+    fn result(&self, scale: f64) -> i32 {
+        match self {
+            Nbt::String(s) => s.chars().count() as i32,
+            Nbt::List(items) => items.len() as i32,
+            Nbt::Compound(entries) => entries.len() as i32,
+            n => (n.as_number().unwrap_or(0.0) * scale).floor() as i32
+        }
+    }
+    // How a macro argument is written into a command: strings as they are and numbers without their suffix
+    fn macro_text(&self) -> String {
+        match self {
+            Nbt::String(s) => s.clone(),
+            Nbt::Float(n) => n.to_string(),
+            Nbt::Double(n) => n.to_string(),
+            Nbt::List(_) | Nbt::Compound(_) => self.to_string(),
+            n => (n.as_number().unwrap_or(0.0) as i64).to_string()
+        }
+    }
+    // How an nbt text component shows a tag, which only leaves the quotes off of strings
+    fn chat_text(&self) -> String {
+        match self {
+            Nbt::String(s) => s.clone(),
+            n => n.to_string()
+        }
+    }
+}
+
+impl Display for Nbt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Nbt::Byte(n) => write!(f, "{}b", n),
+            Nbt::Short(n) => write!(f, "{}s", n),
+            Nbt::Int(n) => write!(f, "{}", n),
+            Nbt::Long(n) => write!(f, "{}L", n),
+            Nbt::Float(n) => write!(f, "{:?}f", n),
+            Nbt::Double(n) => write!(f, "{:?}d", n),
+            Nbt::String(s) => f.write_str(&quote(s)),
+            Nbt::List(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Nbt::Compound(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    if !key.is_empty() && key.chars().all(unquoted) {
+                        write!(f, "{}: {}", key, value)?;
+                    } else {
+                        write!(f, "{}: {}", quote(key), value)?;
+                    }
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Characters that can be in SNBT keys and values without quotes
+fn unquoted(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+// A number or boolean written without quotes, like 5, 1.5d or true
+fn number(token: &str) -> Option<Nbt> {
+    match token {
+        "true" => return Some(Nbt::Byte(1)),
+        "false" => return Some(Nbt::Byte(0)),
+        _ => {}
+    }
+    let (digits, suffix) = match token.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&token[..i], Some(c.to_ascii_lowercase())),
+        _ => (token, None)
+    };
+    if !digits.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.')) {
+        return None;
+    }
+    match suffix {
+        Some('b') => digits.parse().ok().map(Nbt::Byte),
+        Some('s') => digits.parse().ok().map(Nbt::Short),
+        Some('l') => digits.parse().ok().map(Nbt::Long),
+        Some('f') => digits.parse().ok().map(Nbt::Float),
+        Some('d') => digits.parse().ok().map(Nbt::Double),
+        None if digits.contains(['.', 'e', 'E']) => digits.parse().ok().map(Nbt::Double),
+        None => digits.parse().ok().map(Nbt::Int),
+        _ => None
+    }
+}
+
+// One step of an NBT path, like .name or [-1]
+#[derive(Debug, Clone)]
+enum Node {
+    Key(String),
+    Index(i32)
+}
+
+fn parse_path(text: &str) -> Result<Vec<Node>, SimulationException> {
+    let bad = || SimulationException::InvalidCommand(format!("Cannot read the path {}", text));
+    let mut reader = Reader::new(text);
+    let mut path = Vec::new();
+    while let Some(c) = reader.peek() {
+        match c {
+            '[' => {
+                reader.pos += 1;
+                let start = reader.pos;
+                while reader.peek().is_some_and(|c| c != ']') {
+                    reader.pos += 1;
+                }
+                let index: String = reader.chars[start..reader.pos].iter().collect();
+                if !reader.eat(']') {
+                    return Err(bad());
+                }
+                path.push(Node::Index(index.trim().parse().map_err(|_| bad())?));
+            }
+            '"' | '\'' => path.push(Node::Key(reader.quoted()?)),
+            _ => {
+                let start = reader.pos;
+                while reader.peek().is_some_and(|c| !matches!(c, '.' | '[' | '{' | ' ')) {
+                    reader.pos += 1;
+                }
+                if start == reader.pos {
+                    return Err(bad());
+                }
+                path.push(Node::Key(reader.chars[start..reader.pos].iter().collect()));
+            }
+        }
+        reader.eat('.');
+    }
+    Ok(path)
+}
+
+// Where a list index lands, counting back from the end when it's negative
+fn index(i: i32, len: usize) -> Option<usize> {
+    let i = if i < 0 { len as i64 + i as i64 } else { i as i64 };
+    (0..len as i64).contains(&i).then_some(i as usize)
+}
+
+fn find<'a>(mut tag: &'a Nbt, path: &[Node]) -> Option<&'a Nbt> {
+    for node in path {
+        tag = match (node, tag) {
+            (Node::Key(k), Nbt::Compound(entries)) => entries.get(k)?,
+            (Node::Index(i), Nbt::List(items)) => items.get(index(*i, items.len())?)?,
+            _ => return None
+        };
+    }
+    Some(tag)
+}
+
+// Like find, but with create it makes compounds for keys on the way that aren't there yet
+fn find_mut<'a>(mut tag: &'a mut Nbt, path: &[Node], create: bool) -> Option<&'a mut Nbt> {
+    for node in path {
+        tag = match (node, tag) {
+            (Node::Key(k), Nbt::Compound(entries)) => if create {
+                entries.entry(k.clone()).or_insert_with(|| Nbt::Compound(BTreeMap::new()))
+            } else {
+                entries.get_mut(k)?
+            },
+            (Node::Index(i), Nbt::List(items)) => {
+                let len = items.len();
+                items.get_mut(index(*i, len)?)?
+            }
+            _ => return None
+        };
+    }
+    Some(tag)
+}
+
+// Puts a value at a path, giving whether anything changed, or None if the path can't be reached
+fn put(root: &mut Nbt, path: &[Node], value: Nbt) -> Option<bool> {
+    let Some((last, parents)) = path.split_last() else {
+        if !matches!(value, Nbt::Compound(_)) {
+            return None;
+        }
+        let changed = *root != value;
+        *root = value;
+        return Some(changed);
+    };
+    match (last, find_mut(root, parents, true)?) {
+        (Node::Key(k), Nbt::Compound(entries)) => Some(entries.insert(k.clone(), value.clone()).as_ref() != Some(&value)),
+        (Node::Index(i), Nbt::List(items)) => {
+            let i = index(*i, items.len())?;
+            let changed = items[i] != value;
+            items[i] = value;
+            Some(changed)
+        }
+        _ => None
+    }
+}
+
+// The list at a path, made empty if there's nothing there yet
+fn list_mut<'a>(root: &'a mut Nbt, path: &[Node]) -> Option<&'a mut Vec<Nbt>> {
+    let (last, parents) = path.split_last()?;
+    let tag = match (last, find_mut(root, parents, true)?) {
+        (Node::Key(k), Nbt::Compound(entries)) => entries.entry(k.clone()).or_insert_with(|| Nbt::List(Vec::new())),
+        (Node::Index(i), Nbt::List(items)) => {
+            let len = items.len();
+            items.get_mut(index(*i, len)?)?
+        }
+        _ => return None
+    };
+    match tag {
+        Nbt::List(items) => Some(items),
+        _ => None
+    }
+}
+
+fn remove(root: &mut Nbt, path: &[Node]) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    match (last, find_mut(root, parents, false)) {
+        (Node::Key(k), Some(Nbt::Compound(entries))) => entries.remove(k).is_some(),
+        (Node::Index(i), Some(Nbt::List(items))) => match index(*i, items.len()) {
+            Some(i) => {
+                items.remove(i);
+                true
+            }
+            None => false
+        },
+        _ => false
+    }
+}
+
+// Merges compounds into each other key by key, giving whether anything changed
+fn merge(target: &mut Nbt, source: Nbt) -> bool {
+    let (Nbt::Compound(target), Nbt::Compound(source)) = (target, source) else {
+        return false;
+    };
+    let mut changed = false;
+    for (key, value) in source {
+        match target.get_mut(&key) {
+            Some(existing @ Nbt::Compound(_)) if matches!(value, Nbt::Compound(_)) => changed |= merge(existing, value),
+            Some(existing) => {
+                changed |= *existing != value;
+                *existing = value;
+            }
+            None => {
+                target.insert(key, value);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+// Reads through a command, word by word
+struct Reader {
+    chars: Vec<char>,
+    pos: usize
+}
+
+impl Reader {
+    fn new(text: &str) -> Reader {
+        Reader { chars: text.chars().collect(), pos: 0 }
+    }
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(' ') {
+            self.pos += 1;
+        }
+    }
+    fn at_end(&mut self) -> bool {
+        self.skip_spaces();
+        self.pos >= self.chars.len()
+    }
+    // Makes sure nothing is left over at the end of a command
+    fn end(&mut self) -> Result<(), SimulationException> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(SimulationException::InvalidCommand(format!("Unexpected {}", self.rest())))
+        }
+    }
+    // Reads up to the next space that isn't inside brackets or quotes
+    fn word(&mut self) -> Result<String, SimulationException> {
+        self.skip_spaces();
+        let start = self.pos;
+        let mut depth = 0;
+        let mut quote = None;
+        while let Some(c) = self.peek() {
+            match quote {
+                Some(_) if c == '\\' => self.pos += 1,
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None => match c {
+                    ' ' if depth == 0 => break,
+                    '"' | '\'' => quote = Some(c),
+                    '[' | '{' => depth += 1,
+                    ']' | '}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            self.pos += 1;
+        }
+        self.pos = self.pos.min(self.chars.len());
+        if start == self.pos {
+            return Err(SimulationException::InvalidCommand("Expected more at the end of the command".to_string()));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+    // Reads a word that has to be one of a few options
+    fn keyword(&mut self, options: &[&str]) -> Result<String, SimulationException> {
+        let word = self.word()?;
+        if options.contains(&word.as_str()) {
+            Ok(word)
+        } else {
+            Err(SimulationException::InvalidCommand(format!("Expected {} but found {}", options.join(" or "), word)))
+        }
+    }
+    fn int(&mut self) -> Result<i32, SimulationException> {
+        let word = self.word()?;
+        word.parse().map_err(|_| SimulationException::InvalidCommand(format!("{} is not a whole number", word)))
+    }
+    fn float(&mut self) -> Result<f64, SimulationException> {
+        let word = self.word()?;
+        word.parse().map_err(|_| SimulationException::InvalidCommand(format!("{} is not a number", word)))
+    }
+    fn rest(&mut self) -> String {
+        self.skip_spaces();
+        let rest = self.chars[self.pos..].iter().collect();
+        self.pos = self.chars.len();
+        rest
+    }
+    fn quoted(&mut self) -> Result<String, SimulationException> {
+        let quote = self.peek();
+        self.pos += 1;
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == '\\' {
+                match self.peek() {
+                    Some('n') => out.push('\n'),
+                    Some(e) => out.push(e),
+                    None => break
+                }
+                self.pos += 1;
+            } else if Some(c) == quote {
+                return Ok(out);
+            } else {
+                out.push(c);
+            }
+        }
+        Err(SimulationException::InvalidCommand("Unfinished string".to_string()))
+    }
+    fn unquoted(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(unquoted) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+    fn snbt(&mut self) -> Result<Nbt, SimulationException> {
+        let bad = |at: usize| SimulationException::InvalidCommand(format!("Malformed SNBT at character {}", at + 1));
+        self.skip_spaces();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                let mut entries = BTreeMap::new();
+                loop {
+                    self.skip_spaces();
+                    if self.eat('}') {
+                        break;
+                    }
+                    let key = if matches!(self.peek(), Some('"' | '\'')) { self.quoted()? } else { self.unquoted() };
+                    self.skip_spaces();
+                    if key.is_empty() || !self.eat(':') {
+                        return Err(bad(self.pos));
+                    }
+                    entries.insert(key, self.snbt()?);
+                    self.skip_spaces();
+                    if !self.eat(',') {
+                        self.skip_spaces();
+                        if !self.eat('}') {
+                            return Err(bad(self.pos));
+                        }
+                        break;
+                    }
+                }
+                Ok(Nbt::Compound(entries))
+            }
+            Some('[') => {
+                self.pos += 1;
+                // Typed arrays like [I; 1, 2] are kept as plain lists
+                if matches!(self.chars.get(self.pos..self.pos + 2), Some(['B' | 'I' | 'L', ';'])) {
+                    self.pos += 2;
+                }
+                let mut items = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.eat(']') {
+                        break;
+                    }
+                    items.push(self.snbt()?);
+                    self.skip_spaces();
+                    if !self.eat(',') {
+                        self.skip_spaces();
+                        if !self.eat(']') {
+                            return Err(bad(self.pos));
+                        }
+                        break;
+                    }
+                }
+                Ok(Nbt::List(items))
+            }
+            Some('"' | '\'') => Ok(Nbt::String(self.quoted()?)),
+            _ => {
+                let token = self.unquoted();
+                if token.is_empty() {
+                    return Err(bad(self.pos));
+                }
+                Ok(number(&token).unwrap_or(Nbt::String(token)))
+            }
+        }
+    }
+}
+
+// Something tellraw sent, with the players who would have seen it. Messages are kept even when nobody was online to see them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub recipients: Vec<String>,
+    pub text: String
+}
+
+struct Player {
+    name: String,
+    online: bool,
+    tags: BTreeSet<String>
+}
+
+// Who is running a command. Without an executor, it's the server.
+#[derive(Clone, Copy)]
+struct Context {
+    executor: Option<usize>
+}
+
+const SERVER: Context = Context { executor: None };
+
+enum Condition {
+    Matches(String, String, Option<i32>, Option<i32>), // A score and the range it has to be in
+    Compare(String, String, String, String, String), // A score, the operator and the score it's compared to
+    Data(String, Vec<Node>),
+    Entity(String)
+}
+
+enum Store {
+    Score(String, String),
+    Storage(String, Vec<Node>, String, f64) // Where, the type of number and the scale
+}
+
+pub enum SimulationException {
+    NoFileAccess(PathBuf),
+    UnknownFunction(String),
+    InvalidCommand(String), // What's wrong with a command
+    TooManyCommands,
+    TooDeep(String),
+    Mismatch(String, String, String), // What was looked at, what was expected and what was there
+    InFunction(String, usize, String, Box<SimulationException>) // The function, line and command that went wrong
+}
+impl Display for SimulationException {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationException::NoFileAccess(p) => f.write_str(format!("Cannot read file {:?}.", p).as_str())?,
+            SimulationException::UnknownFunction(n) => f.write_str(format!("Cannot find function {}.", n).as_str())?,
+            SimulationException::InvalidCommand(m) => f.write_str(format!("{}.", m).as_str())?,
+            SimulationException::TooManyCommands => f.write_str(format!("Ran more than {} commands at once, which is as many as the game allows.", MAX_COMMANDS).as_str())?,
+            SimulationException::TooDeep(n) => f.write_str(format!("Functions called each other more than {} deep, reaching {}.", MAX_DEPTH, n).as_str())?,
+            SimulationException::Mismatch(w, e, a) => f.write_str(format!("Expected {} to be {} but found {}.", w, e, a).as_str())?,
+            SimulationException::InFunction(n, l, c, e) => f.write_str(format!("Inside of {} at line {}: {} While running `{}`", n, l, e, c).as_str())?,
+        };
+        Ok(())
+    }
+}
+impl From<SimulationException> for Box<dyn Display> {
+    fn from(e: SimulationException) -> Self {
+        Box::new(e)
+    }
+}
+
+pub struct Simulator {
+    functions: BTreeMap<String, Arc<Vec<String>>>,
+    load: Vec<String>,
+    tick: Vec<String>,
+    objectives: BTreeMap<String, String>, // Each objective's criterion
+    scores: BTreeMap<String, BTreeMap<String, i32>>, // Objective, then score holder
+    storage: BTreeMap<String, Nbt>,
+    players: Vec<Player>,
+    scheduled: Vec<(u64, String)>, // When each scheduled function runs, in the order they were scheduled
+    game_time: u64,
+    chat: Vec<Message>,
+    commands: usize, // How many commands have run since the server last started a function
+    depth: usize,
+    returning: Option<i32> // Set by return, until the function it's in stops
+}
+
+impl Simulator {
+    fn new(functions: BTreeMap<String, Arc<Vec<String>>>, load: Vec<String>, tick: Vec<String>) -> Simulator {
+        Simulator {
+            functions, load, tick,
+            objectives: BTreeMap::new(),
+            scores: BTreeMap::new(),
+            storage: BTreeMap::new(),
+            players: Vec::new(),
+            scheduled: Vec::new(),
+            game_time: 0,
+            chat: Vec::new(),
+            commands: 0,
+            depth: 0,
+            returning: None
+        }
+    }
+
+    pub fn from_datapack(datapack: &CompiledDatapack) -> Simulator {
+        let functions = datapack.functions.iter().map(|(name, commands)| (format!("{}:{}", datapack.namespace, name), Arc::new(commands.clone()))).collect();
+        Simulator::new(functions, datapack.load.clone(), datapack.tick.clone())
+    }
+
+    // Reads a datapack that was written to a target folder
+    pub fn load(target: &Path) -> Result<Simulator, SimulationException> {
+        let data = target.join("data");
+        let namespaces = fs::read_dir(&data).map_err(|_| SimulationException::NoFileAccess(data.clone()))?;
+        let mut functions = BTreeMap::new();
+        for namespace in namespaces.flatten() {
+            let folder = namespace.path().join("functions");
+            if folder.is_dir() {
+                read_functions(&folder, &format!("{}:", namespace.file_name().to_string_lossy()), &mut functions)?;
+            }
+        }
+        let tag = |name: &str| -> Result<Vec<String>, SimulationException> {
+            let file = data.join("minecraft/tags/functions").join(format!("{}.json", name));
+            if !file.exists() {
+                return Ok(Vec::new());
+            }
+            let text = fs::read_to_string(&file).map_err(|_| SimulationException::NoFileAccess(file.clone()))?;
+            let tag = json::parse(&text).ok_or(SimulationException::NoFileAccess(file))?;
+            Ok(tag.get("values").as_array().iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+        };
+        Ok(Simulator::new(functions, tag("load")?, tag("tick")?))
+    }
+
+    // Runs the #minecraft:load functions, like the server does when it starts or reloads
+    pub fn reload(&mut self) -> Result<(), SimulationException> {
+        for id in self.load.clone() {
+            self.start(&id)?;
+        }
+        Ok(())
+    }
+
+    // Runs one game tick: the #minecraft:tick functions, then whatever was scheduled for the new game time
+    pub fn tick(&mut self) -> Result<(), SimulationException> {
+        for id in self.tick.clone() {
+            self.start(&id)?;
+        }
+        self.game_time += 1;
+        let (mut due, waiting): (Vec<_>, Vec<_>) = mem::take(&mut self.scheduled).into_iter().partition(|(at, _)| *at <= self.game_time);
+        self.scheduled = waiting;
+        due.sort_by_key(|(at, _)| *at);
+        for (_, id) in due {
+            self.start(&id)?;
+        }
+        Ok(())
+    }
+
+    pub fn run_ticks(&mut self, ticks: u64) -> Result<(), SimulationException> {
+        for _ in 0..ticks {
+            self.tick()?;
+        }
+        Ok(())
+    }
+
+    // Brings a player online. Like in the game, join events are picked up by the next tick.
+    pub fn join(&mut self, name: &str) {
+        match self.players.iter_mut().find(|p| p.name == name) {
+            Some(player) => player.online = true,
+            None => self.players.push(Player { name: name.to_string(), online: true, tags: BTreeSet::new() })
+        }
+    }
+
+    pub fn leave(&mut self, name: &str) {
+        let Some(player) = self.players.iter_mut().find(|p| p.name == name && p.online) else {
+            return;
+        };
+        player.online = false;
+        // Leaving counts towards every objective that watches the leave_game statistic
+        for (objective, criterion) in &self.objectives {
+            if criterion == LEAVE_GAME {
+                let score = self.scores.entry(objective.clone()).or_default().entry(name.to_string()).or_insert(0);
+                *score = score.wrapping_add(1);
+            }
+        }
+    }
+
+    // Runs a function as the server, giving what it returned
+    pub fn run_function(&mut self, id: &str) -> Result<Option<i32>, SimulationException> {
+        self.start(id)
+    }
+
+    // Runs a single command as the server, giving its result, or None if it failed
+    pub fn run_command(&mut self, command: &str) -> Result<Option<i32>, SimulationException> {
+        self.commands = 0;
+        self.depth = 0;
+        let result = self.on_own_stack(|s| s.run(command, SERVER));
+        self.returning = None;
+        result
+    }
+
+    pub fn score(&self, holder: &str, objective: &str) -> Option<i32> {
+        self.scores.get(objective)?.get(holder).copied()
+    }
+
+    // Looks a path up in data storage, where an empty path gives the whole storage
+    pub fn storage(&self, id: &str, path: &str) -> Option<&Nbt> {
+        find(self.root(id), &parse_path(path).ok()?)
+    }
+
+    pub fn chat(&self) -> &[Message] {
+        &self.chat
+    }
+
+    pub fn has_tag(&self, player: &str, tag: &str) -> bool {
+        self.players.iter().any(|p| p.name == player && p.tags.contains(tag))
+    }
+
+    pub fn game_time(&self) -> u64 {
+        self.game_time
+    }
+
+    // Whether anything is scheduled to run in a later tick
+    pub fn waiting(&self) -> bool {
+        !self.scheduled.is_empty()
+    }
+
+    // Runs one line of a simulation script, giving what it prints. Besides the commands themselves, scripts can bring players
+    // in and out, pass time and look at or expect what's in scores, storage and tags:
+    //   join <player>, leave <player>, tick [count], time, score <holder> <objective>, storage <id> [path],
+    //   tagged <player> <tag>, expect <id> <path> <snbt>
+    pub fn script(&mut self, line: &str) -> Result<Option<String>, SimulationException> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let mut reader = Reader::new(line);
+        let shown = |value: Option<String>| Some(value.unwrap_or_else(|| "Nothing".to_string()));
+        let printed = match reader.word()?.as_str() {
+            "join" => {
+                self.join(&reader.word()?);
+                None
+            }
+            "leave" => {
+                self.leave(&reader.word()?);
+                None
+            }
+            "tick" => {
+                let ticks = if reader.at_end() { 1 } else { reader.int()?.max(0) as u64 };
+                self.run_ticks(ticks)?;
+                None
+            }
+            "time" => Some(self.game_time.to_string()),
+            "score" => {
+                let holder = reader.word()?;
+                shown(self.score(&holder, &reader.word()?).map(|s| s.to_string()))
+            }
+            "storage" => {
+                let id = reader.word()?;
+                let path = reader.rest();
+                shown(self.storage(&id, &path).map(|n| n.to_string()))
+            }
+            "tagged" => {
+                let player = reader.word()?;
+                Some(self.has_tag(&player, &reader.word()?).to_string())
+            }
+            "expect" => {
+                let id = reader.word()?;
+                let path = reader.word()?;
+                let text = reader.rest();
+                let expected = Nbt::parse(&text).ok_or_else(|| SimulationException::InvalidCommand(format!("Malformed SNBT {}", text)))?;
+                let found = self.storage(&id, &path);
+                if found != Some(&expected) {
+                    return Err(SimulationException::Mismatch(format!("{} {}", id, path), expected.to_string(), found.map_or("nothing".to_string(), |n| n.to_string())));
+                }
+                None
+            }
+            _ => return self.run_command(line).map(|_| None)
+        };
+        reader.end()?;
+        Ok(printed)
+    }
+
+    fn start(&mut self, id: &str) -> Result<Option<i32>, SimulationException> {
+        self.commands = 0;
+        self.depth = 0;
+        self.returning = None;
+        self.on_own_stack(|s| s.call(id, None, SERVER))
+    }
+
+    // Each function called goes several frames deeper, so they run on a thread with a stack big enough for MAX_DEPTH of them
+    fn on_own_stack<T: Send>(&mut self, run: impl FnOnce(&mut Simulator) -> T + Send) -> T {
+        thread::scope(|scope| {
+            let handle = thread::Builder::new().stack_size(STACK_SIZE).spawn_scoped(scope, || run(self)).expect("Cannot start a thread to simulate on");
+            handle.join().unwrap_or_else(|p| panic::resume_unwind(p))
+        })
+    }
+
+    fn root(&self, id: &str) -> &Nbt {
+        self.storage.get(id).unwrap_or(&EMPTY)
+    }
+
+    fn root_mut(&mut self, id: &str) -> &mut Nbt {
+        self.storage.entry(id.to_string()).or_insert_with(|| Nbt::Compound(BTreeMap::new()))
+    }
+
+    fn call(&mut self, id: &str, arguments: Option<&Nbt>, context: Context) -> Result<Option<i32>, SimulationException> {
+        let Some(commands) = self.functions.get(id).cloned() else {
+            return Err(SimulationException::UnknownFunction(id.to_string()));
+        };
+        if self.depth >= MAX_DEPTH {
+            return Err(SimulationException::TooDeep(id.to_string()));
+        }
+        self.depth += 1;
+        let result = self.call_lines(id, &commands, arguments, context);
+        self.depth -= 1;
+        result
+    }
+
+    fn call_lines(&mut self, id: &str, commands: &[String], arguments: Option<&Nbt>, context: Context) -> Result<Option<i32>, SimulationException> {
+        for (i, line) in commands.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // Only the innermost function is reported, since that's where the command that went wrong is
+            let located = |e: SimulationException, command: &str| match e {
+                e @ SimulationException::InFunction(..) => e,
+                e => SimulationException::InFunction(id.to_string(), i + 1, command.to_string(), Box::new(e))
+            };
+            let command = match line.strip_prefix('$') {
+                Some(line) => substitute(line, arguments).map_err(|e| located(e, line))?,
+                None => line.to_string()
+            };
+            self.run(&command, context).map_err(|e| located(e, &command))?;
+            if let Some(value) = self.returning.take() {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    // Runs a command, giving its result, or None if it failed the way commands can fail in the game
+    fn run(&mut self, command: &str, context: Context) -> Result<Option<i32>, SimulationException> {
+        self.commands += 1;
+        if self.commands > MAX_COMMANDS {
+            return Err(SimulationException::TooManyCommands);
+        }
+        let mut reader = Reader::new(command);
+        match reader.word()?.as_str() {
+            "scoreboard" => self.scoreboard(&mut reader, context),
+            "data" => self.data(&mut reader),
+            "execute" => self.execute(&mut reader, context),
+            "function" => self.function(&mut reader, context),
+            "return" => self.return_command(&mut reader, context),
+            "schedule" => self.schedule(&mut reader),
+            "tellraw" => self.tellraw(&mut reader, context),
+            "tag" => self.tag(&mut reader, context),
+            "time" => {
+                reader.keyword(&["query"])?;
+                let time = match reader.keyword(&["gametime", "daytime", "day"])?.as_str() {
+                    "gametime" => self.game_time,
+                    "daytime" => self.game_time % 24000,
+                    _ => self.game_time / 24000
+                };
+                reader.end()?;
+                Ok(Some(time as i32))
+            }
+            other => Err(SimulationException::InvalidCommand(format!("Unknown command {}", other)))
+        }
+    }
+
+    fn scoreboard(&mut self, reader: &mut Reader, context: Context) -> Result<Option<i32>, SimulationException> {
+        if reader.keyword(&["objectives", "players"])? == "objectives" {
+            let action = reader.keyword(&["add", "remove"])?;
+            let name = reader.word()?;
+            if action == "remove" {
+                reader.end()?;
+                self.scores.remove(&name);
+                return Ok(self.objectives.remove(&name).map(|_| self.objectives.len() as i32));
+            }
+            let criterion = reader.word()?;
+            reader.rest(); // The display name isn't shown anywhere
+            if self.objectives.contains_key(&name) {
+                return Ok(None);
+            }
+            self.objectives.insert(name, criterion);
+            return Ok(Some(self.objectives.len() as i32));
+        }
+        let action = reader.keyword(&["set", "add", "remove", "reset", "get", "operation"])?;
+        let holder = reader.word()?;
+        if action == "reset" {
+            let objective = if reader.at_end() { None } else { Some(reader.word()?) };
+            reader.end()?;
+            let holders = if holder == "*" {
+                self.scores.values().flat_map(|s| s.keys().cloned()).collect::<BTreeSet<_>>().into_iter().collect()
+            } else {
+                self.holders(&holder, context)?
+            };
+            let mut reset = 0;
+            for (name, scores) in self.scores.iter_mut() {
+                if objective.as_ref().is_none_or(|o| o == name) {
+                    for holder in &holders {
+                        reset += scores.remove(holder).is_some() as i32;
+                    }
+                }
+            }
+            return Ok((!holders.is_empty()).then_some(reset));
+        }
+        let objective = reader.word()?;
+        let holders = self.holders(&holder, context)?;
+        match action.as_str() {
+            "get" => {
+                reader.end()?;
+                Ok(holders.first().and_then(|h| self.score(h, &objective)))
+            }
+            "operation" => {
+                let operation = reader.keyword(&["=", "+=", "-=", "*=", "/=", "%=", "<", ">", "><"])?;
+                let source = reader.word()?;
+                let source_objective = reader.word()?;
+                reader.end()?;
+                let sources = self.holders(&source, context)?;
+                if holders.is_empty() || sources.is_empty() || !self.objectives.contains_key(&objective) || !self.objectives.contains_key(&source_objective) {
+                    return Ok(None);
+                }
+                let mut result = 0;
+                for target in &holders {
+                    for source in &sources {
+                        let Some(b) = self.score(source, &source_objective) else {
+                            return Ok(None);
+                        };
+                        let a = self.score(target, &objective).unwrap_or(0);
+                        let (a, b) = operate(&operation, a, b);
+                        self.set_score(target, &objective, a);
+                        if operation == "><" {
+                            self.set_score(source, &source_objective, b);
+                        }
+                        result = a;
+                    }
+                }
+                Ok(Some(result))
+            }
+            _ => {
+                let amount = reader.int()?;
+                reader.end()?;
+                if holders.is_empty() || !self.objectives.contains_key(&objective) {
+                    return Ok(None);
+                }
+                let mut result = 0;
+                for holder in &holders {
+                    let score = self.score(holder, &objective).unwrap_or(0);
+                    result = match action.as_str() {
+                        "set" => amount,
+                        "add" => score.wrapping_add(amount),
+                        _ => score.wrapping_sub(amount)
+                    };
+                    self.set_score(holder, &objective, result);
+                }
+                Ok(Some(result))
+            }
+        }
+    }
+
+    fn set_score(&mut self, holder: &str, objective: &str, value: i32) {
+        self.scores.entry(objective.to_string()).or_default().insert(holder.to_string(), value);
+    }
+
+    // The names a score holder stands for: a selector's players, or just the name itself
+    fn holders(&self, holder: &str, context: Context) -> Result<Vec<String>, SimulationException> {
+        if holder.starts_with('@') {
+            Ok(self.select(holder, context)?.into_iter().map(|p| self.players[p].name.clone()).collect())
+        } else {
+            Ok(vec![holder.to_string()])
+        }
+    }
+
+    fn select(&self, selector: &str, context: Context) -> Result<Vec<usize>, SimulationException> {
+        let bad = |m: String| SimulationException::InvalidCommand(m);
+        let Some(selector) = selector.strip_prefix('@') else {
+            // A plain name picks that player, if they're online
+            return Ok(self.players.iter().position(|p| p.online && p.name == selector).into_iter().collect());
+        };
+        let (kind, arguments) = match selector.split_once('[') {
+            Some((kind, arguments)) => (kind, arguments.strip_suffix(']').ok_or_else(|| bad(format!("Unfinished selector @{}", selector)))?),
+            None => (selector, "")
+        };
+        let mut found: Vec<usize> = match kind {
+            "s" => context.executor.into_iter().collect(),
+            "a" | "e" | "p" | "r" => (0..self.players.len()).filter(|&p| self.players[p].online).collect(),
+            _ => return Err(bad(format!("Unknown selector @{}", kind)))
+        };
+        let mut limit = matches!(kind, "p" | "r").then_some(1);
+        for argument in split_arguments(arguments) {
+            let (key, value) = argument.split_once('=').ok_or_else(|| bad(format!("Cannot read the selector argument {}", argument)))?;
+            let (negated, value) = match value.strip_prefix('!') {
+                Some(value) => (true, value),
+                None => (false, value)
+            };
+            match key.trim() {
+                "tag" => found.retain(|&p| (if value.is_empty() { self.players[p].tags.is_empty() } else { self.players[p].tags.contains(value) }) != negated),
+                "name" => found.retain(|&p| (self.players[p].name == value) != negated),
+                // Players are the only entities there are
+                "type" => if matches!(value, "player" | "minecraft:player") == negated {
+                    found.clear();
+                },
+                "limit" => limit = Some(value.parse().map_err(|_| bad(format!("{} is not a whole number", value)))?),
+                "sort" => {}
+                "scores" => {
+                    let scores = value.strip_prefix('{').and_then(|v| v.strip_suffix('}')).ok_or_else(|| bad(format!("Cannot read the scores {}", value)))?;
+                    for score in scores.split(',').filter(|s| !s.trim().is_empty()) {
+                        let (objective, range) = score.split_once('=').ok_or_else(|| bad(format!("Cannot read the score {}", score)))?;
+                        let (min, max) = parse_range(range)?;
+                        found.retain(|&p| self.score(&self.players[p].name, objective.trim()).is_some_and(|s| in_range(s, min, max)));
+                    }
+                }
+                other => return Err(bad(format!("Unsupported selector argument {}", other)))
+            }
+        }
+        if let Some(limit) = limit {
+            found.truncate(limit);
+        }
+        Ok(found)
+    }
+
+    fn storage_id(&mut self, reader: &mut Reader) -> Result<String, SimulationException> {
+        let kind = reader.word()?;
+        if kind != "storage" {
+            return Err(SimulationException::InvalidCommand(format!("Only storage is supported, not {}", kind)));
+        }
+        reader.word()
+    }
+
+    fn data(&mut self, reader: &mut Reader) -> Result<Option<i32>, SimulationException> {
+        let action = reader.keyword(&["get", "modify", "remove", "merge"])?;
+        let id = self.storage_id(reader)?;
+        match action.as_str() {
+            "get" => {
+                let path = if reader.at_end() { Vec::new() } else { parse_path(&reader.word()?)? };
+                let scale = if reader.at_end() { 1.0 } else { reader.float()? };
+                reader.end()?;
+                Ok(find(self.root(&id), &path).map(|t| t.result(scale)))
+            }
+            "remove" => {
+                let path = parse_path(&reader.word()?)?;
+                reader.end()?;
+                Ok(remove(self.root_mut(&id), &path).then_some(1))
+            }
+            "merge" => {
+                let value = reader.snbt()?;
+                reader.end()?;
+                Ok(merge(self.root_mut(&id), value).then_some(1))
+            }
+            _ => {
+                let path = parse_path(&reader.word()?)?;
+                let operation = reader.keyword(&["set", "append", "prepend", "insert", "merge"])?;
+                let at = if operation == "insert" { Some(reader.int()?) } else { None };
+                let value = match reader.keyword(&["value", "from", "string"])?.as_str() {
+                    "value" => reader.snbt()?,
+                    source => {
+                        let from = self.storage_id(reader)?;
+                        let path = if reader.at_end() { Vec::new() } else { parse_path(&reader.word()?)? };
+                        let Some(value) = find(self.root(&from), &path).cloned() else {
+                            return Ok(None);
+                        };
+                        if source == "string" {
+                            let text = value.chat_text().chars().collect::<Vec<_>>();
+                            let bound = |i: i32| (if i < 0 { text.len() as i32 + i } else { i }).clamp(0, text.len() as i32) as usize;
+                            let start = if reader.at_end() { 0 } else { bound(reader.int()?) };
+                            let end = if reader.at_end() { text.len() } else { bound(reader.int()?) };
+                            Nbt::String(text[start..end.max(start)].iter().collect())
+                        } else {
+                            value
+                        }
+                    }
+                };
+                reader.end()?;
+                let root = self.root_mut(&id);
+                Ok(match operation.as_str() {
+                    "set" => put(root, &path, value).and_then(|changed| changed.then_some(1)),
+                    "merge" => find_mut(root, &path, true).and_then(|t| merge(t, value).then_some(1)),
+                    _ => list_mut(root, &path).and_then(|items| {
+                        let i = match (operation.as_str(), at) {
+                            ("append", _) => items.len() as i32,
+                            (_, Some(at)) if at < 0 => items.len() as i32 + 1 + at,
+                            (_, at) => at.unwrap_or(0)
+                        };
+                        if !(0..=items.len() as i32).contains(&i) {
+                            return None;
+                        }
+                        items.insert(i as usize, value);
+                        Some(1)
+                    })
+                })
+            }
+        }
+    }
+
+    fn function(&mut self, reader: &mut Reader, context: Context) -> Result<Option<i32>, SimulationException> {
+        let id = reader.word()?;
+        let arguments = if reader.at_end() {
+            None
+        } else {
+            reader.keyword(&["with"])?;
+            let from = self.storage_id(reader)?;
+            let path = if reader.at_end() { Vec::new() } else { parse_path(&reader.word()?)? };
+            reader.end()?;
+            match find(self.root(&from), &path) {
+                Some(arguments @ Nbt::Compound(_)) => Some(arguments.clone()),
+                _ => return Ok(None)
+            }
+        };
+        self.call(&id, arguments.as_ref(), context)
+    }
+
+    fn return_command(&mut self, reader: &mut Reader, context: Context) -> Result<Option<i32>, SimulationException> {
+        let (value, result) = match reader.word()?.as_str() {
+            "run" => {
+                let command = reader.rest();
+                let result = self.run(&command, context)?;
+                (result.unwrap_or(0), result)
+            }
+            "fail" => (0, None),
+            value => {
+                let value = value.parse().map_err(|_| SimulationException::InvalidCommand(format!("{} is not a whole number", value)))?;
+                (value, Some(value))
+            }
+        };
+        reader.end()?;
+        self.returning = Some(value);
+        Ok(result)
+    }
+
+    fn schedule(&mut self, reader: &mut Reader) -> Result<Option<i32>, SimulationException> {
+        if reader.keyword(&["function", "clear"])? == "clear" {
+            let id = reader.word()?;
+            reader.end()?;
+            let before = self.scheduled.len();
+            self.scheduled.retain(|(_, f)| *f != id);
+            let cleared = (before - self.scheduled.len()) as i32;
+            return Ok((cleared > 0).then_some(cleared));
+        }
+        let id = reader.word()?;
+        let time = reader.word()?;
+        let (amount, unit) = match time.char_indices().last() {
+            Some((i, c)) if c.is_ascii_alphabetic() => (&time[..i], c),
+            _ => (time.as_str(), 't')
+        };
+        let scale = match unit {
+            't' => 1.0,
+            's' => 20.0,
+            'd' => 24000.0,
+            _ => return Err(SimulationException::InvalidCommand(format!("{} is not a time", time)))
+        };
+        let ticks = amount.parse::<f64>().map_err(|_| SimulationException::InvalidCommand(format!("{} is not a time", time)))? * scale;
+        if ticks < 1.0 {
+            return Err(SimulationException::InvalidCommand("Cannot schedule a function for the tick that's already running".to_string()));
+        }
+        let replace = reader.at_end() || reader.keyword(&["append", "replace"])? == "replace";
+        reader.end()?;
+        if !self.functions.contains_key(&id) {
+            return Err(SimulationException::UnknownFunction(id));
+        }
+        if replace {
+            self.scheduled.retain(|(_, f)| *f != id);
+        }
+        let at = self.game_time + ticks.round() as u64;
+        self.scheduled.push((at, id));
+        Ok(Some(at as i32))
+    }
+
+    fn tellraw(&mut self, reader: &mut Reader, context: Context) -> Result<Option<i32>, SimulationException> {
+        let targets = self.select(&reader.word()?, context)?;
+        let component = json::parse(&reader.rest()).ok_or_else(|| SimulationException::InvalidCommand("Malformed text component".to_string()))?;
+        let text = self.render(&component, context)?;
+        let recipients: Vec<String> = targets.into_iter().map(|p| self.players[p].name.clone()).collect();
+        let result = recipients.len() as i32;
+        self.chat.push(Message { recipients, text });
+        Ok(Some(result))
+    }
+
+    // The plain text a text component shows
+    fn render(&self, component: &Json, context: Context) -> Result<String, SimulationException> {
+        Ok(match component {
+            Json::Null => String::new(),
+            Json::String(s) => s.clone(),
+            Json::Array(items) => items.iter().map(|c| self.render(c, context)).collect::<Result<String, _>>()?,
+            Json::Object(_) => {
+                let mut text = if let Some(text) = component.get("text").as_str() {
+                    text.to_string()
+                } else if !component.get("score").is_null() {
+                    let score = component.get("score");
+                    let name = score.get("name").as_str().unwrap_or_default();
+                    let objective = score.get("objective").as_str().unwrap_or_default();
+                    let holder = self.holders(name, context)?.into_iter().next();
+                    holder.and_then(|h| self.score(&h, objective)).map(|s| s.to_string()).unwrap_or_default()
+                } else if let Some(selector) = component.get("selector").as_str() {
+                    self.holders(selector, context)?.join(", ")
+                } else if let Some(path) = component.get("nbt").as_str() {
+                    let id = component.get("storage").as_str().unwrap_or_default();
+                    find(self.root(id), &parse_path(path)?).map(|t| t.chat_text()).unwrap_or_default()
+                } else {
+                    component.get("translate").as_str().unwrap_or_default().to_string()
+                };
+                for extra in component.get("extra").as_array() {
+                    text += &self.render(extra, context)?;
+                }
+                text
+            }
+            other => other.to_string()
+        })
+    }
+
+    fn tag(&mut self, reader: &mut Reader, context: Context) -> Result<Option<i32>, SimulationException> {
+        let targets = self.select(&reader.word()?, context)?;
+        let action = reader.keyword(&["add", "remove", "list"])?;
+        if action == "list" {
+            reader.end()?;
+            return Ok(Some(targets.iter().map(|&p| self.players[p].tags.len() as i32).sum()));
+        }
+        let tag = reader.word()?;
+        reader.end()?;
+        let mut changed = 0;
+        for p in targets {
+            let tags = &mut self.players[p].tags;
+            if if action == "add" { tags.insert(tag.clone()) } else { tags.remove(&tag) } {
+                changed += 1;
+            }
+        }
+        Ok((changed > 0).then_some(changed))
+    }
+
+    fn execute(&mut self, reader: &mut Reader, context: Context) -> Result<Option<i32>, SimulationException> {
+        let mut contexts = vec![context];
+        let mut stores = Vec::new();
+        loop {
+            match reader.word()?.as_str() {
+                subcommand @ ("as" | "at") => {
+                    let selector = reader.word()?;
+                    let mut next = Vec::new();
+                    for c in &contexts {
+                        for p in self.select(&selector, *c)? {
+                            // Positions aren't simulated, so at only repeats the command for each entity
+                            next.push(if subcommand == "as" { Context { executor: Some(p) } } else { *c });
+                        }
+                    }
+                    contexts = next;
+                }
+                test @ ("if" | "unless") => {
+                    let expected = test == "if";
+                    let condition = self.condition(reader)?;
+                    if reader.at_end() {
+                        // Ending on a condition gives whether it passed
+                        let mut result = None;
+                        for c in contexts {
+                            let passed = (self.test(&condition, c)? == expected).then_some(1);
+                            self.store(&stores, passed, c)?;
+                            result = result.or(passed);
+                        }
+                        return Ok(result);
+                    }
+                    let mut kept = Vec::new();
+                    for c in contexts {
+                        if self.test(&condition, c)? == expected {
+                            kept.push(c);
+                        }
+                    }
+                    contexts = kept;
+                }
+                "store" => {
+                    let success = reader.keyword(&["result", "success"])? == "success";
+                    let store = if reader.keyword(&["score", "storage"])? == "score" {
+                        Store::Score(reader.word()?, reader.word()?)
+                    } else {
+                        let id = reader.word()?;
+                        let path = parse_path(&reader.word()?)?;
+                        let kind = reader.keyword(&["byte", "short", "int", "long", "float", "double"])?;
+                        Store::Storage(id, path, kind, reader.float()?)
+                    };
+                    stores.push((success, store));
+                }
+                "run" => {
+                    let command = reader.rest();
+                    let mut result = None;
+                    for c in contexts {
+                        let r = self.run(&command, c)?;
+                        self.store(&stores, r, c)?;
+                        result = result.or(r);
+                        if self.returning.is_some() {
+                            break;
+                        }
+                    }
+                    return Ok(result);
+                }
+                other => return Err(SimulationException::InvalidCommand(format!("Unsupported execute subcommand {}", other)))
+            }
+        }
+    }
+
+    fn condition(&mut self, reader: &mut Reader) -> Result<Condition, SimulationException> {
+        Ok(match reader.keyword(&["score", "data", "entity"])?.as_str() {
+            "score" => {
+                let holder = reader.word()?;
+                let objective = reader.word()?;
+                match reader.keyword(&["matches", "<", "<=", "=", ">=", ">"])?.as_str() {
+                    "matches" => {
+                        let (min, max) = parse_range(&reader.word()?)?;
+                        Condition::Matches(holder, objective, min, max)
+                    }
+                    operator => Condition::Compare(holder, objective, operator.to_string(), reader.word()?, reader.word()?)
+                }
+            }
+            "data" => {
+                let id = self.storage_id(reader)?;
+                Condition::Data(id, parse_path(&reader.word()?)?)
+            }
+            _ => Condition::Entity(reader.word()?)
+        })
+    }
+
+    fn test(&self, condition: &Condition, context: Context) -> Result<bool, SimulationException> {
+        let score = |holder: &str, objective: &str| -> Result<Option<i32>, SimulationException> {
+            Ok(self.holders(holder, context)?.first().and_then(|h| self.score(h, objective)))
+        };
+        Ok(match condition {
+            Condition::Matches(holder, objective, min, max) => score(holder, objective)?.is_some_and(|s| in_range(s, *min, *max)),
+            Condition::Compare(holder, objective, operator, other, other_objective) => match (score(holder, objective)?, score(other, other_objective)?) {
+                (Some(a), Some(b)) => match operator.as_str() {
+                    "<" => a < b,
+                    "<=" => a <= b,
+                    "=" => a == b,
+                    ">=" => a >= b,
+                    _ => a > b
+                },
+                _ => false
+            },
+            Condition::Data(id, path) => find(self.root(id), path).is_some(),
+            Condition::Entity(selector) => !self.select(selector, context)?.is_empty()
+        })
+    }
+
+    // Saves a command's result where execute store asked for it
+    fn store(&mut self, stores: &[(bool, Store)], result: Option<i32>, context: Context) -> Result<(), SimulationException> {
+        for (success, store) in stores {
+            let value = if *success { result.is_some() as i32 } else { result.unwrap_or(0) };
+            match store {
+                Store::Score(holder, objective) => {
+                    if self.objectives.contains_key(objective) {
+                        for holder in self.holders(holder, context)? {
+                            self.set_score(&holder, objective, value);
+                        }
+                    }
+                }
+                Store::Storage(id, path, kind, scale) => {
+                    let scaled = value as f64 * scale;
+                    let tag = match kind.as_str() {
+                        "byte" => Nbt::Byte(scaled as i32 as i8),
+                        "short" => Nbt::Short(scaled as i32 as i16),
+                        "int" => Nbt::Int(scaled as i32),
+                        "long" => Nbt::Long(scaled as i64),
+                        "float" => Nbt::Float(scaled as f32),
+                        _ => Nbt::Double(scaled)
+                    };
+                    put(self.root_mut(id), path, tag);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_functions(folder: &Path, prefix: &str, functions: &mut BTreeMap<String, Arc<Vec<String>>>) -> Result<(), SimulationException> {
+    let entries = fs::read_dir(folder).map_err(|_| SimulationException::NoFileAccess(folder.to_path_buf()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() {
+            read_functions(&path, &format!("{}{}/", prefix, name), functions)?;
+        } else if let Some(stem) = name.strip_suffix(".mcfunction") {
+            let text = fs::read_to_string(&path).map_err(|_| SimulationException::NoFileAccess(path.clone()))?;
+            functions.insert(format!("{}{}", prefix, stem), Arc::new(text.lines().map(|l| l.to_string()).collect()));
+        }
+    }
+    Ok(())
+}
+
+// Fills a macro line's $(name) arguments in from the compound the function was called with
+fn substitute(line: &str, arguments: Option<&Nbt>) -> Result<String, SimulationException> {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("$(") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..].find(')').ok_or_else(|| SimulationException::InvalidCommand("Unfinished macro argument".to_string()))? + start;
+        let name = &rest[start + 2..end];
+        let value = match arguments {
+            Some(Nbt::Compound(entries)) => entries.get(name),
+            _ => None
+        };
+        let value = value.ok_or_else(|| SimulationException::InvalidCommand(format!("Missing macro argument {}", name)))?;
+        out.push_str(&value.macro_text());
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+// Splits selector arguments on the commas that aren't inside braces
+fn split_arguments(arguments: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in arguments.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&arguments[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&arguments[start..]);
+    parts.into_iter().filter(|p| !p.trim().is_empty()).collect()
+}
+
+// Reads a range like 1, 1.., ..5 or 1..5
+fn parse_range(text: &str) -> Result<(Option<i32>, Option<i32>), SimulationException> {
+    let bad = || SimulationException::InvalidCommand(format!("{} is not a range", text));
+    let bound = |s: &str| if s.is_empty() { Ok(None) } else { s.parse().map(Some).map_err(|_| bad()) };
+    match text.split_once("..") {
+        Some((min, max)) => Ok((bound(min)?, bound(max)?)),
+        None => {
+            let n = bound(text)?.ok_or_else(bad)?;
+            Ok((Some(n), Some(n)))
+        }
+    }
+}
+
+fn in_range(value: i32, min: Option<i32>, max: Option<i32>) -> bool {
+    min.is_none_or(|m| value >= m) && max.is_none_or(|m| value <= m)
+}
+
+// Applies a scoreboard operation, giving the new target and source scores. Dividing by zero leaves the target alone, like the game.
+fn operate(operation: &str, a: i32, b: i32) -> (i32, i32) {
+    let floor_div = |a: i32, b: i32| {
+        let q = a.wrapping_div(b);
+        if a.wrapping_rem(b) != 0 && (a < 0) != (b < 0) { q - 1 } else { q }
+    };
+    match operation {
+        "=" => (b, b),
+        "+=" => (a.wrapping_add(b), b),
+        "-=" => (a.wrapping_sub(b), b),
+        "*=" => (a.wrapping_mul(b), b),
+        "/=" if b != 0 => (floor_div(a, b), b),
+        "%=" if b != 0 => (a.wrapping_sub(floor_div(a, b).wrapping_mul(b)), b),
+        "<" => (a.min(b), b),
+        ">" => (a.max(b), b),
+        "><" => (b, a),
+        _ => (a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, path::Path, sync::Mutex};
+    use crate::{compiler::{self, CompiledDatapack}, files, parser, parser_pass_two};
+    use super::{Nbt, SimulationException, Simulator};
+
+    // Tests that share a fixture would otherwise write its target folder at the same time
+    static WRITING: Mutex<()> = Mutex::new(());
+
+    // Compiles one of the projects in tests/ into its target folder and loads that like a server would
+    fn fixture(name: &str) -> Simulator {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(name);
+        if let Err(e) = files::verify_project_format(&path) {
+            panic!("{}", e);
+        }
+        let mut project = parser::StructuredFireProject::new();
+        for file in files::get_all_project_files(&path) {
+            project = file.structure_into(project).unwrap_or_else(|e| panic!("{}", e));
+        }
+        project.register_implementations().unwrap_or_else(|e| panic!("{}", e));
+        let parsed = parser_pass_two::parse_project(&project).unwrap_or_else(|e| panic!("{}", e));
+        let datapack = compiler::compile_project(&parsed, &files::project_namespace(&path)).unwrap_or_else(|e| panic!("{}", e));
+        let writing = WRITING.lock().unwrap_or_else(|e| e.into_inner());
+        files::write_datapack(&path, &datapack).unwrap_or_else(|e| panic!("{}", e));
+        let mut simulator = Simulator::load(&path.join("target")).unwrap_or_else(|e| panic!("{}", e));
+        drop(writing);
+        simulator.reload().unwrap_or_else(|e| panic!("{}", e));
+        simulator
+    }
+
+    fn run(simulator: &mut Simulator, id: &str) {
+        if let Err(e) = simulator.run_function(id) {
+            panic!("{}", e);
+        }
+    }
+
+    fn chat(simulator: &Simulator) -> Vec<&str> {
+        simulator.chat().iter().map(|m| m.text.as_str()).collect()
+    }
+
+    // What went wrong underneath the functions it happened in
+    fn innermost(e: SimulationException) -> SimulationException {
+        match e {
+            SimulationException::InFunction(_, _, _, e) => innermost(*e),
+            e => e
+        }
+    }
+
+    fn datapack(functions: &[(&str, Vec<String>)]) -> Simulator {
+        let functions: BTreeMap<String, Vec<String>> = functions.iter().map(|(name, commands)| (name.to_string(), commands.clone())).collect();
+        Simulator::from_datapack(&CompiledDatapack { namespace: "t".to_string(), functions, load: Vec::new(), tick: Vec::new() })
+    }
+
+    #[test]
+    fn recursion() {
+        let mut simulator = fixture("TestRecursion");
+        run(&mut simulator, "testrecursion:main/math/run");
+        assert_eq!(chat(&simulator), ["55", "36", "25"]);
+    }
+
+    #[test]
+    fn recursion_through_lambdas() {
+        let mut simulator = fixture("TestRecursion");
+        run(&mut simulator, "testrecursion:main/callbacks/run");
+        assert_eq!(chat(&simulator), ["10", "0", "1", "2"]);
+    }
+
+    #[test]
+    fn players_on_the_stack() {
+        let mut simulator = fixture("TestRecursion");
+        simulator.join("Alex");
+        simulator.join("Steve");
+        for command in ["execute as Alex run function testrecursion:main/players/run", "execute as Steve run function testrecursion:main/players/later"] {
+            simulator.run_command(command).unwrap_or_else(|e| panic!("{}", e));
+        }
+        simulator.run_ticks(2).unwrap_or_else(|e| panic!("{}", e));
+        let chat: Vec<_> = simulator.chat().iter().map(|m| (m.text.as_str(), m.recipients.clone())).collect();
+        let to = |name: &str| vec![name.to_string()];
+        assert_eq!(chat, [("0", to("Alex")), ("1", to("Alex")), ("2", to("Alex")), ("Later", to("Steve"))]);
+    }
+
+    #[test]
+    fn ranges() {
+        let mut simulator = fixture("TestRanges");
+        run(&mut simulator, "testranges:main/loops/run");
+        assert_eq!(chat(&simulator), [
+            "0", "1", "2", "0", "4", "8", "4", "3", "2", "1", "0", "40", "41", "42", "43", "44", "45",
+            "54", "51", "48", "45", "42", "39", "36", "33", "30", "27", "24", "21", "18", "15", "12", "9", "6", "3", "0",
+            "In range", "Weekend", "C"
+        ]);
+    }
+
+    #[test]
+    fn raise() {
+        let mut simulator = fixture("TestRaise");
+        run(&mut simulator, "testraise:main/raise/run");
+        assert_eq!(chat(&simulator), ["Negative", "0", "main::raise::check", "Negative"]);
+        assert_eq!(simulator.storage("testraise:data", "_raised.message"), Some(&Nbt::String("Negative".to_string())));
+        assert_eq!(simulator.score("$_raised", compiler::OBJECTIVE), Some(0));
+    }
+
+    #[test]
+    fn raise_inside_match_arm() {
+        let mut simulator = fixture("TestRaise");
+        run(&mut simulator, "testraise:main/raise/signs");
+        assert_eq!(chat(&simulator), ["Positive", "Matched", "main::raise::sign", "Negative"]);
+    }
+
+    #[test]
+    fn impls() {
+        let mut simulator = fixture("TestImpls");
+        run(&mut simulator, "testimpls:main/shapes/run");
+        assert_eq!(chat(&simulator), ["12", "4", "Circle", "7"]);
+    }
+
+    #[test]
+    fn processes() {
+        let mut simulator = fixture("TestProcesses");
+        run(&mut simulator, "testprocesses:main/timers/start");
+        assert_eq!(chat(&simulator), ["3"]);
+        simulator.run_ticks(20).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(chat(&simulator), ["3", "2"]);
+        simulator.run_ticks(20).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(chat(&simulator), ["3", "2", "Two seconds later", "1"]);
+        simulator.run_ticks(21).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(chat(&simulator), ["3", "2", "Two seconds later", "1", "Go!"]);
+        assert_eq!(simulator.game_time(), 61);
+    }
+
+    #[test]
+    fn join_events_reach_the_player() {
+        let mut simulator = fixture("TestLambdas");
+        simulator.join("Alex");
+        simulator.tick().unwrap_or_else(|e| panic!("{}", e));
+        let welcome = simulator.chat().iter().find(|m| m.text == "Welcome back").expect("No welcome message");
+        assert_eq!(welcome.recipients, ["Alex"]);
+    }
+
+    #[test]
+    fn scores_and_storage() {
+        let mut simulator = datapack(&[]);
+        for command in ["scoreboard objectives add o dummy", "scoreboard players set a o -7", "scoreboard players set b o 2", "scoreboard players operation a o /= b o"] {
+            simulator.run_command(command).unwrap_or_else(|e| panic!("{}", e));
+        }
+        assert_eq!(simulator.score("a", "o"), Some(-4));
+        simulator.run_command("data modify storage t:data list set value [1, 2]").unwrap_or_else(|e| panic!("{}", e));
+        simulator.run_command("data modify storage t:data list append value 3").unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(simulator.storage("t:data", "list"), Nbt::parse("[1, 2, 3]").as_ref());
+        assert_eq!(simulator.run_command("execute store result score c o run data get storage t:data list[2]").ok(), Some(Some(3)));
+        assert_eq!(simulator.score("c", "o"), Some(3));
+    }
+
+    #[test]
+    fn players() {
+        let mut simulator = datapack(&[]);
+        simulator.run_command("scoreboard objectives add left minecraft.custom:minecraft.leave_game").unwrap_or_else(|e| panic!("{}", e));
+        simulator.join("Alex");
+        simulator.join("Steve");
+        simulator.run_command("tag Alex add vip").unwrap_or_else(|e| panic!("{}", e));
+        assert!(simulator.has_tag("Alex", "vip") && !simulator.has_tag("Steve", "vip"));
+        simulator.run_command(r#"tellraw @a[tag=vip] {"text":"Hi"}"#).unwrap_or_else(|e| panic!("{}", e));
+        simulator.leave("Alex");
+        assert_eq!(simulator.score("Alex", "left"), Some(1));
+        simulator.run_command(r#"tellraw @a {"text":"Bye"}"#).unwrap_or_else(|e| panic!("{}", e));
+        let chat: Vec<_> = simulator.chat().iter().map(|m| (m.text.as_str(), m.recipients.clone())).collect();
+        assert_eq!(chat, [("Hi", vec!["Alex".to_string()]), ("Bye", vec!["Steve".to_string()])]);
+    }
+
+    #[test]
+    fn script() {
+        let mut simulator = fixture("TestProcesses");
+        let mut printed = Vec::new();
+        for line in ["function testprocesses:main/timers/start", "tick 40", "time", "tick 21", "# Done", "expect testprocesses:data main.timers.delayed._waiting []"] {
+            printed.extend(simulator.script(line).unwrap_or_else(|e| panic!("{}", e)));
+        }
+        assert_eq!(printed, ["40"]);
+        assert_eq!(chat(&simulator), ["3", "2", "Two seconds later", "1", "Go!"]);
+        match simulator.script("expect testprocesses:data main.timers.delayed._waiting [1]") {
+            Err(SimulationException::Mismatch(_, expected, found)) => assert_eq!((expected.as_str(), found.as_str()), ("[1]", "[]")),
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("A wrong expectation passed")
+        }
+    }
+
+    #[test]
+    fn too_deep() {
+        let mut simulator = datapack(&[("loop", vec!["execute unless score x o matches 1 run function t:loop".to_string()])]);
+        simulator.run_command("scoreboard objectives add o dummy").unwrap_or_else(|e| panic!("{}", e));
+        match simulator.run_function("t:loop").map_err(innermost) {
+            Err(SimulationException::TooDeep(id)) => assert_eq!(id, "t:loop"),
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("Recursing forever didn't stop")
+        }
+    }
+
+    #[test]
+    fn too_many_commands() {
+        // 300 * 300 commands without going deep, which is over the game's limit
+        let mut simulator = datapack(&[
+            ("outer", vec!["function t:inner".to_string(); 300]),
+            ("inner", vec!["scoreboard players add n o 1".to_string(); 300])
+        ]);
+        simulator.run_command("scoreboard objectives add o dummy").unwrap_or_else(|e| panic!("{}", e));
+        match simulator.run_function("t:outer").map_err(innermost) {
+            Err(SimulationException::TooManyCommands) => {}
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("Every command ran")
+        }
+        assert!(simulator.score("n", "o").is_some_and(|n| n < 300 * 300));
+    }
+}
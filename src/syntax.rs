@@ -0,0 +1,260 @@
+use std::fmt::Display;
+
+use crate::{compiler::CompileException, tokenizer::{tokenize_lossless, LosslessToken, Token, Trivia}};
+
+// A concrete syntax tree of a .fire file. Unlike the parsed project it keeps every token, comment and space, so writing it back
+// out gives exactly the file it came from. Tools that edit or look at source, such as the formatter, work on this.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree {
+    pub root: SyntaxNode,
+    pub trailing: Vec<Trivia> // Whatever is after the last token
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyntaxKind {
+    File,
+    Item, // Something at the top of a file, such as a fn or an import, along with its annotations
+    Statement, // Something inside of a block, up to its ; or , or the block that ends it
+    Block, // { ... }
+    Parens, // ( ... )
+    Brackets // [ ... ]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(LosslessToken)
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind) -> SyntaxNode {
+        SyntaxNode { kind, children: Vec::new() }
+    }
+
+    // The tokens directly inside of this node, not inside of its children
+    pub fn tokens(&self) -> impl Iterator<Item = &LosslessToken> {
+        self.children.iter().filter_map(|c| match c {
+            SyntaxElement::Token(t) => Some(t),
+            SyntaxElement::Node(_) => None
+        })
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Token(t) => {
+                    for trivia in &t.leading {
+                        f.write_str(trivia.text())?;
+                    }
+                    f.write_str(&t.text)?;
+                }
+                SyntaxElement::Node(n) => write!(f, "{}", n)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for SyntaxTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.root)?;
+        for trivia in &self.trailing {
+            f.write_str(trivia.text())?;
+        }
+        Ok(())
+    }
+}
+
+// Builds the syntax tree of a file. Only brackets have to match, so this works on files that don't parse yet.
+pub fn parse_lossless(input: &str) -> Result<SyntaxTree, CompileException> {
+    let (tokens, trailing) = tokenize_lossless(input)?;
+    let mut tokens = tokens.into_iter().peekable();
+    let root = read_node(&mut tokens, SyntaxKind::File, None, false)?;
+    Ok(SyntaxTree { root, trailing })
+}
+
+// Reads the contents of a File, Block, Parens or Brackets, up to and including the token that closes it. In the body of a match,
+// the , between patterns doesn't end the arm.
+fn read_node(tokens: &mut std::iter::Peekable<std::vec::IntoIter<LosslessToken>>, kind: SyntaxKind, opener: Option<&LosslessToken>, is_match: bool) -> Result<SyntaxNode, CompileException> {
+    let closer = match kind {
+        SyntaxKind::Block => Some(Token::CloseBrace),
+        SyntaxKind::Parens => Some(Token::CloseParen),
+        SyntaxKind::Brackets => Some(Token::CloseBracket),
+        _ => None
+    };
+    // Files and blocks are split into items and statements, while the insides of brackets are left as they are
+    let part = match kind {
+        SyntaxKind::File => Some(SyntaxKind::Item),
+        SyntaxKind::Block => Some(SyntaxKind::Statement),
+        _ => None
+    };
+    let mut node = SyntaxNode::new(kind);
+    let mut current = part.map(SyntaxNode::new);
+    loop {
+        let Some(token) = tokens.next() else {
+            if let Some(opener) = opener {
+                return Err(CompileException::UnexpectedToken(format!("{:?} that is never closed", opener.token.token), opener.token.line, opener.token.column));
+            }
+            break;
+        };
+        if Some(&token.token.token) == closer.as_ref() {
+            if let Some(current) = current.take().filter(|c| !c.children.is_empty()) {
+                node.children.push(SyntaxElement::Node(current));
+            }
+            node.children.push(SyntaxElement::Token(token));
+            return Ok(node);
+        }
+        let inner = match token.token.token {
+            Token::OpenBrace => Some(SyntaxKind::Block),
+            Token::OpenParen => Some(SyntaxKind::Parens),
+            Token::OpenBracket => Some(SyntaxKind::Brackets),
+            Token::CloseBrace | Token::CloseParen | Token::CloseBracket => {
+                return Err(CompileException::UnexpectedToken(format!("{:?}", token.token.token), token.token.line, token.token.column));
+            }
+            _ => None
+        };
+        let ends = match (&inner, &token.token.token) {
+            (Some(SyntaxKind::Block), _) => current.as_ref().is_some_and(ends_at_block),
+            (None, Token::Semicolon) => true,
+            (None, Token::Comma) => kind == SyntaxKind::Block && current.as_ref().is_some_and(|c| {
+                !in_generics(c) && (!is_match || c.tokens().any(|t| t.token.token == Token::FatArrow))
+            }),
+            _ => false
+        };
+        let is_block = inner == Some(SyntaxKind::Block);
+        let element = match inner {
+            Some(inner) => {
+                let starts_match = current.as_ref().and_then(|c| c.tokens().next()).is_some_and(|t| t.token.token == Token::Match);
+                let mut child = read_node(tokens, inner, Some(&token), inner == SyntaxKind::Block && starts_match)?;
+                child.children.insert(0, SyntaxElement::Token(token));
+                SyntaxElement::Node(child)
+            }
+            None => SyntaxElement::Token(token)
+        };
+        let Some(statement) = current.as_mut() else {
+            node.children.push(element);
+            continue;
+        };
+        // A declaration starts something new even without a ; before it, like a fn after the last field of a struct
+        if let SyntaxElement::Token(t) = &element {
+            if starts_declaration(&t.token.token) && statement.tokens().any(|t| !starts_declaration(&t.token.token)) {
+                let kind = statement.kind;
+                node.children.push(SyntaxElement::Node(std::mem::replace(statement, SyntaxNode::new(kind))));
+            }
+        }
+        statement.children.push(element);
+        // if {} else {} and try {} catch {} carry on past the first part, as does a , or ; right after a block
+        let next = tokens.peek().map(|t| &t.token.token);
+        let first = statement.tokens().next().map(|t| &t.token.token);
+        let carries_on = matches!((first, next), (Some(Token::If), Some(Token::Else)) | (Some(Token::Try), Some(Token::Catch)))
+            || is_block && matches!(next, Some(Token::Comma) | Some(Token::Semicolon));
+        if ends && !carries_on {
+            let kind = statement.kind;
+            node.children.push(SyntaxElement::Node(std::mem::replace(statement, SyntaxNode::new(kind))));
+        }
+    }
+    if let Some(current) = current.filter(|c| !c.children.is_empty()) {
+        node.children.push(SyntaxElement::Node(current));
+    }
+    Ok(node)
+}
+
+// Whether a block ends an item or statement, as it does for a fn or an if, but not for a struct being made
+fn ends_at_block(statement: &SyntaxNode) -> bool {
+    if let Some(SyntaxElement::Token(t)) = statement.children.last() {
+        if t.token.token == Token::FatArrow { // A match arm, or a lambda if a ; follows it
+            return true;
+        }
+    }
+    let mut tokens = statement.tokens().map(|t| &t.token.token).skip_while(|t| matches!(t, Token::Annotation(_) | Token::Private | Token::Internal));
+    match tokens.next() {
+        None => statement.children.is_empty(),
+        Some(first) => matches!(first,
+            Token::If | Token::Else | Token::For | Token::While | Token::Match | Token::Try | Token::Catch | Token::Select |
+            Token::FuncDef | Token::ProcDef | Token::StructDef | Token::EnumDef | Token::TraitDef | Token::Implementation | Token::GroupDef
+        )
+    }
+}
+
+// Annotations, visibility and the keywords that start a fn or pc
+fn starts_declaration(token: &Token) -> bool {
+    matches!(token, Token::Annotation(_) | Token::Private | Token::Internal | Token::FuncDef | Token::ProcDef)
+}
+
+// Whether a statement is in the middle of a type like Map<String, Int>, where the , doesn't end it
+fn in_generics(statement: &SyntaxNode) -> bool {
+    let mut depth = 0;
+    let mut previous: Option<&Token> = None;
+    for t in statement.tokens() {
+        match &t.token.token {
+            Token::Less if matches!(previous, Some(Token::Type(_))) && t.leading.is_empty() => depth += 1,
+            Token::Greater if depth > 0 => depth -= 1,
+            _ => {}
+        }
+        previous = Some(&t.token.token);
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::{Path, PathBuf}};
+    use super::parse_lossless;
+
+    fn round_trip(input: &str) {
+        let tree = parse_lossless(input).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(tree.to_string(), input);
+    }
+
+    // Every .fire file under a folder
+    fn fire_files(path: &Path, found: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(path).unwrap().map(|e| e.unwrap().path()) {
+            if entry.is_dir() {
+                fire_files(&entry, found);
+            } else if entry.extension().is_some_and(|e| e == "fire") {
+                found.push(entry);
+            }
+        }
+    }
+
+    #[test]
+    fn fixtures() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut files = Vec::new();
+        fire_files(&root.join("tests"), &mut files);
+        fire_files(&root.join("std_lib"), &mut files);
+        assert!(!files.is_empty());
+        for file in files {
+            round_trip(&fs::read_to_string(&file).unwrap());
+        }
+    }
+
+    #[test]
+    fn comments() {
+        round_trip("// A line comment\nfn f() { // After code\n    /* A block\n       over lines */ let x = /* inside */ 1;\n}\n// At the end");
+        round_trip("/**/fn f(){}/* no newline */");
+    }
+
+    #[test]
+    fn whitespace() {
+        round_trip("");
+        round_trip("\n\n  \t\n");
+        round_trip("fn  f ( a :Int,b: Int )\t{\r\n\treturn a+b ;\r\n}\r\n");
+        round_trip("   fn f() {}   \n\n\n");
+    }
+
+    #[test]
+    fn strings() {
+        round_trip(r#"fn f() { broadcast("quote \" backslash \\ newline \n tab \t"); }"#);
+        round_trip("fn f() { broadcast(\"\"\"\n    Over\n      lines\n    \"\"\"); }\n");
+        round_trip(r#"fn f(n: Int) { broadcast("{n} and {{braces}} and {"nested {n + 1}"}"); }"#);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{fmt::Display, path::Path};
+use crate::{compiler::{self, FAILED, OBJECTIVE, RAISED}, files::{self, UncompiledFireFile}, parser::{self, FireLocation}, parser_pass_two, simulator::{SimulationException, Simulator}, tokenizer::{self, Token}};
+
+const MAX_TICKS: u64 = 20 * 60 * 10; // How long a test can keep waiting for, which is ten minutes of game time
+
+// How a @Test went, where a failure says why
+pub struct TestResult {
+    pub test: FireLocation,
+    pub failure: Option<String>
+}
+
+// Compiles the project with its @Test functions and runs each of them on its own in a fresh simulator
+pub fn run_tests(path: &Path) -> Result<Vec<TestResult>, Box<dyn Display>> {
+    if !path.join("src").is_dir() {
+        return Err(Box::new(files::ProjectStructureException::NoSrc));
+    }
+    let files = files::get_all_project_files(path);
+    let mut project = parser::StructuredFireProject::new();
+    for file in &files {
+        project = file.structure_into(project)?;
+    }
+    project.register_implementations()?;
+    let parsed = parser_pass_two::parse_project(&project)?;
+    let namespace = files::project_namespace(path);
+    let (datapack, tests) = compiler::compile_tests(&parsed, &namespace)?;
+    let storage = format!("{}:data", namespace);
+    let mut results = Vec::with_capacity(tests.len());
+    for (test, id) in tests {
+        let mut simulator = Simulator::from_datapack(&datapack);
+        let failure = match run_test(&mut simulator, &id, &storage) {
+            Err(e) => Some(e.to_string()),
+            Ok(Some(raised)) => Some(describe_raise(&simulator, &storage, raised, &project, &files)),
+            Ok(None) if simulator.waiting() => Some(format!("Still waiting after {} ticks.", MAX_TICKS)),
+            Ok(None) => None
+        };
+        results.push(TestResult { test, failure });
+    }
+    Ok(results)
+}
+
+// Runs a test and then the ticks it waits for, giving where in storage a raise that ended it was kept
+fn run_test(simulator: &mut Simulator, id: &str, storage: &str) -> Result<Option<&'static str>, SimulationException> {
+    simulator.reload()?;
+    simulator.run_function(id)?;
+    loop {
+        if simulator.score(&format!("${}", RAISED), OBJECTIVE) == Some(1) {
+            return Ok(Some(RAISED));
+        }
+        if simulator.storage(storage, FAILED).is_some() {
+            return Ok(Some(FAILED));
+        }
+        if !simulator.waiting() || simulator.game_time() >= MAX_TICKS {
+            return Ok(None);
+        }
+        simulator.tick()?;
+    }
+}
+
+// Why a test raised, from where in storage the raise was kept. Failed assertions are shown by their span in the source, along
+// with the values assertEq compared.
+fn describe_raise(simulator: &Simulator, storage: &str, raised: &str, project: &parser::StructuredFireProject, files: &[UncompiledFireFile]) -> String {
+    let get = |key: &str| simulator.storage(storage, &format!("{}.{}", raised, key));
+    let at = get("at").and_then(|n| n.as_str()).unwrap_or("").to_string();
+    let message = get("message").and_then(|n| n.as_str()).unwrap_or("").to_string();
+    let (Some(line), Some(column)) = (get("line").and_then(|n| n.as_number()), get("column").and_then(|n| n.as_number())) else {
+        return format!("Raised in {}: {}", at, message);
+    };
+    let (line, column) = (line as usize, column as usize);
+    let loc = FireLocation::from_path(&at.split("::").map(|s| s.to_string()).collect::<Vec<_>>());
+    let mut text = match source_file(&loc, project, files) {
+        Some(file) => {
+            let end = file.read_all().ok().and_then(|source| call_end(&source, line, column));
+            match end {
+                Some((end_line, end_column)) => format!("Assertion failed at {:?}:{}:{}-{}:{}", file.path(), line, column, end_line, end_column),
+                None => format!("Assertion failed at {:?}:{}:{}", file.path(), line, column)
+            }
+        }
+        None => format!("{} in {}", message, at)
+    };
+    if let (Some(expected), Some(actual)) = (get("expected"), get("actual")) {
+        text.push_str(&format!(": expected {} but found {}", expected, actual));
+    }
+    text
+}
+
+// The file something was written in, falling back to the file whose location it is inside of for members of types
+fn source_file<'a>(loc: &FireLocation, project: &parser::StructuredFireProject, files: &'a [UncompiledFireFile]) -> Option<&'a UncompiledFireFile> {
+    if let Some(source) = project.source_of(loc) {
+        if let Some(file) = files.iter().find(|f| f.location() == source) {
+            return Some(file);
+        }
+    }
+    files.iter().filter(|f| loc.segments().starts_with(f.location().segments())).max_by_key(|f| f.location().segments().len())
+}
+
+// Where the call that starts at a line and column ends, just after its closing )
+fn call_end(source: &str, line: usize, column: usize) -> Option<(usize, usize)> {
+    let tokens = tokenizer::tokenize(source).ok()?;
+    let start = tokens.iter().position(|t| t.line == line && t.column == column)?;
+    let mut depth = 0;
+    for t in &tokens[start + 1..] {
+        match t.token {
+            Token::OpenParen => depth += 1,
+            Token::CloseParen if depth == 1 => return Some((t.line, t.column + 1)),
+            Token::CloseParen => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::run_tests;
+
+    // Each test's location, and why it failed if it did
+    fn results(name: &str) -> Vec<(String, Option<String>)> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(name);
+        let results = run_tests(&path).unwrap_or_else(|e| panic!("{}", e));
+        results.into_iter().map(|r| (r.test.to_string(), r.failure)).collect()
+    }
+
+    #[test]
+    fn passing() {
+        let results = results("TestTesting");
+        assert_eq!(results.iter().map(|(t, _)| t.as_str()).collect::<Vec<_>>(), ["main::math::collections", "main::math::factorials", "main::math::raisesAreCaught", "main::math::waits"]);
+        assert!(results.iter().all(|(_, failure)| failure.is_none()), "{:?}", results);
+    }
+
+    #[test]
+    fn failing() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/TestTestingFailures/src/main/checks.fire");
+        let at = |span: &str| format!("Assertion failed at {:?}:{}", file, span);
+        assert_eq!(results("TestTestingFailures"), [
+            ("main::checks::failsAfterWaiting".to_string(), Some(at("51:5-51:27: expected 1 but found 4"))),
+            ("main::checks::falseCondition".to_string(), Some(at("33:5-33:19"))),
+            ("main::checks::insideLambda".to_string(), Some(at("39:9-39:22"))),
+            ("main::checks::passes".to_string(), None),
+            ("main::checks::raisesOut".to_string(), Some("Raised in main::checks::checked: Negative".to_string())),
+            ("main::checks::wrongList".to_string(), Some(at("27:5-27:29: expected [1, 2] but found [2, 1]"))),
+            ("main::checks::wrongNumber".to_string(), Some(at("20:5-21:19: expected 5 but found 4")))
+        ]);
+    }
+}
@@ -1,36 +1,162 @@
+use std::{iter::Peekable, ops::Range, str::Chars};
+
 use crate::compiler::CompileException;
 
-enum Token {
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
     Plus, Minus, Multiply, Divide, Mod, And, Or, Not, Xor, StrictAnd, StrictOr,
     Assign, PlusAssign, MinusAssign, MultiplyAssign, DivideAssign, ModAssign, Increment, Decrement,
     Equals, NotEqual, Greater, Less, GreaterEqual, LessEqual, 
-    Colon, Comma, OpenParen, CloseParen, OpenBrace, CloseBrace, OpenBracket, CloseBracket, Dot, Accesser,
-    String(String), Annotation(String), Identifier(String), Type(String), Int(i64), Num(f32), True, False,
-    FuncDef, ProcDef, StructDef, EnumDef, TraitDef, Implementation, GroupDef, Private, Const, Let,
-    Select, Raise, Import,
+    Colon, Semicolon, Comma, OpenParen, CloseParen, OpenBrace, CloseBrace, OpenBracket, CloseBracket, Dot, Accesser, Range, RangeInclusive,
+    String(String), Interpolated(Vec<StringPart>), Annotation(String), Identifier(String), Type(String), Int(i64), Num(f32), True, False,
+    FuncDef, ProcDef, StructDef, EnumDef, TraitDef, Implementation, GroupDef, Private, Internal, Const, Let,
+    Select, Raise, Import, FatArrow,
     SelfIdent, SelfType,
-    For, While, If, In, Else
+    For, While, If, In, Else, Match, Return, Try, Catch, Wait, Yield
 }
 
 // + - * / % & | ! ^ && ||
 // = += -= *= /= %=
 // == > < >= <=
-// : , ( ) { } [ ] . ::
-// "str" 'str' @Annotation identifier Type 23 23.4 true false 
+// : ; , ( ) { } [ ] . :: .. ..=
+// "str" "str {value}" 'str' @Annotation identifier Type 23 23.4 true false 
 // Self self
-// fn pc struct enum trait impl group private const let
+// fn pc struct enum trait impl group private internal const let
 // select raise import
-// for in while if else
+// for in while if else match return try catch wait yield
 
 // 23 23.4
 
-fn tokenize(input: &str) -> Result<Vec<Token>, CompileException> {
+// A piece of a string with {values} in it
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    Code(Vec<PositionedToken>) // The tokens between { and }
+}
+
+// A token along with the line and column it started at
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionedToken {
+    pub token: Token,
+    pub line: usize,
+    pub column: usize
+}
+
+// A token that remembers exactly how it was written, and the comments and whitespace before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessToken {
+    pub token: PositionedToken,
+    pub text: String,
+    pub leading: Vec<Trivia>
+}
+
+// Text between tokens that doesn't change what a file means
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trivia {
+    Whitespace(String),
+    LineComment(String), // Without the line break after it, which is Whitespace
+    BlockComment(String)
+}
+impl Trivia {
+    pub fn text(&self) -> &str {
+        match self {
+            Trivia::Whitespace(s) | Trivia::LineComment(s) | Trivia::BlockComment(s) => s
+        }
+    }
+}
+
+// Wraps the characters of a file, keeping track of the current line and column
+struct CharReader<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+    offset: usize // In bytes, from the start of the input
+}
+impl CharReader<'_> {
+    fn new(input: &str, line: usize, column: usize) -> CharReader<'_> {
+        CharReader { chars: input.chars().peekable(), line, column, offset: 0 }
+    }
+    fn next(&mut self) -> Option<char> {
+        let next = self.chars.next();
+        match next {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(_) => self.column += 1,
+            None => {}
+        };
+        self.offset += next.map_or(0, char::len_utf8);
+        next
+    }
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+    // Whether the characters coming up are these
+    fn starts_with(&self, s: &str) -> bool {
+        let mut chars = self.chars.clone();
+        s.chars().all(|c| chars.next() == Some(c))
+    }
+    // The character n after the next one, so that the . of 0..10 isn't read as a decimal point
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n)
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<PositionedToken>, CompileException> {
+    tokenize_at(input, 1, 1)
+}
+
+// Tokenizes text that starts somewhere inside of a file, so the positions are where it is in the file
+fn tokenize_at(input: &str, line: usize, column: usize) -> Result<Vec<PositionedToken>, CompileException> {
+    Ok(lex(input, line, column)?.into_iter().map(|(token, _)| token).collect())
+}
+
+// Tokenizes a file without losing anything, so the comments and whitespace before each token are kept with it, along with the
+// exact text of the token. The trivia at the very end of the file comes back separately.
+pub fn tokenize_lossless(input: &str) -> Result<(Vec<LosslessToken>, Vec<Trivia>), CompileException> {
+    let mut tokens = Vec::new();
+    let mut last = 0;
+    for (token, span) in lex(input, 1, 1)? {
+        tokens.push(LosslessToken { leading: split_trivia(&input[last..span.start]), text: input[span.clone()].to_string(), token });
+        last = span.end;
+    }
+    Ok((tokens, split_trivia(&input[last..])))
+}
+
+// Splits the text between two tokens into its whitespace and comments
+fn split_trivia(mut text: &str) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    while !text.is_empty() {
+        let length = if text.starts_with("//") {
+            text.find('\n').unwrap_or(text.len())
+        } else if let Some(comment) = text.strip_prefix("/*") {
+            comment.find("*/").map_or(text.len(), |i| i + 4)
+        } else {
+            text.find(|c: char| !c.is_whitespace()).unwrap_or(text.len())
+        };
+        let (piece, rest) = text.split_at(length);
+        trivia.push(match piece {
+            p if p.starts_with("//") => Trivia::LineComment(p.to_string()),
+            p if p.starts_with("/*") => Trivia::BlockComment(p.to_string()),
+            p => Trivia::Whitespace(p.to_string())
+        });
+        text = rest;
+    }
+    trivia
+}
+
+// Tokenizes text along with the span of bytes that each token came from
+fn lex(input: &str, line: usize, column: usize) -> Result<Vec<(PositionedToken, Range<usize>)>, CompileException> {
     let mut tokens = vec![];
-    let mut iter = input.chars().peekable();
-    let mut line_number = 1usize;
-    let mut line_index = 0usize;
-    while let Some(next) = iter.next() {
-        line_index+=1;
+    let mut positions = vec![];
+    let mut spans = vec![];
+    let mut iter = CharReader::new(input, line, column);
+    loop {
+        let line_number = iter.line;
+        let line_index = iter.column;
+        let start = iter.offset;
+        let Some(next) = iter.next() else { break };
         match next {
             '+' => {
                 match iter.peek() {
@@ -74,19 +200,20 @@ fn tokenize(input: &str) -> Result<Vec<Token>, CompileException> {
                         tokens.push(Token::DivideAssign)
                     },
                     Some('/') => {
-                        loop {
-                            if let Some('\n') = iter.next() {
+                        while let Some(c) = iter.next() {
+                            if c == '\n' {
                                 break;
                             }
                         }
                     },
                     Some('*') => {
-                        loop {
-                            if let Some('*') = iter.next() {
-                                if let Some('/') = iter.next() {
-                                    break;
-                                }
+                        iter.next();
+                        let mut last = ' ';
+                        while let Some(c) = iter.next() {
+                            if last == '*' && c == '/' {
+                                break;
                             }
+                            last = c;
                         }
                     },
                     _ => tokens.push(Token::Divide)
@@ -171,24 +298,35 @@ fn tokenize(input: &str) -> Result<Vec<Token>, CompileException> {
                 
             }
             '^' => tokens.push(Token::Xor),
+            ';' => tokens.push(Token::Semicolon),
             ',' => tokens.push(Token::Comma),
-            '.' => tokens.push(Token::Dot),
+            '.' => {
+                match iter.peek() {
+                    Some('.') => {
+                        iter.next();
+                        if let Some('=') = iter.peek() {
+                            iter.next();
+                            tokens.push(Token::RangeInclusive)
+                        } else {
+                            tokens.push(Token::Range)
+                        }
+                    },
+                    _ => tokens.push(Token::Dot)
+                }
+            }
             '(' => tokens.push(Token::OpenParen),
             ')' => tokens.push(Token::CloseParen),
             '[' => tokens.push(Token::OpenBracket),
             ']' => tokens.push(Token::CloseBracket),
             '{' => tokens.push(Token::OpenBrace),
             '}' => tokens.push(Token::CloseBrace),
-            '\n' => {
-                line_index = 0;
-                line_number+=1;
-            }
+            'r' if matches!(iter.peek(), Some('"') | Some('#')) => tokens.push(read_raw_string(&mut iter, line_number, line_index)?),
             c @ 'a'..='z' => {
                 let mut ident = String::with_capacity(32);
                 ident.push(c);
                 while let Some(m @ 'a'..='z' | m @ 'A'..='Z' | m @ '0'..='9' | m @ '_') = iter.peek() {
                     iter.next();
-                    ident.push(*m);
+                    ident.push(m);
                 };
                 tokens.push(match ident.as_str() {
                     "fn" => Token::FuncDef,
@@ -198,12 +336,27 @@ fn tokenize(input: &str) -> Result<Vec<Token>, CompileException> {
                     "enum" => Token::EnumDef,
                     "group" => Token::GroupDef,
                     "private" => Token::Private,
+                    "internal" => Token::Internal,
                     "let" => Token::Let,
                     "const" => Token::Const,
                     "self" => Token::SelfIdent,
                     "import" => Token::Import,
                     "impl" => Token::Implementation,
+                    "select" => Token::Select,
+                    "raise" => Token::Raise,
+                    "for" => Token::For,
                     "in" => Token::In,
+                    "while" => Token::While,
+                    "if" => Token::If,
+                    "else" => Token::Else,
+                    "match" => Token::Match,
+                    "return" => Token::Return,
+                    "try" => Token::Try,
+                    "catch" => Token::Catch,
+                    "wait" => Token::Wait,
+                    "yield" => Token::Yield,
+                    "true" => Token::True,
+                    "false" => Token::False,
                     _ => Token::Identifier(ident)
 
                 });
@@ -213,7 +366,7 @@ fn tokenize(input: &str) -> Result<Vec<Token>, CompileException> {
                 ident.push(c);
                 while let Some(m @ 'a'..='z' | m @ 'A'..='Z' | m @ '0'..='9' | m @ '_') = iter.peek() {
                     iter.next();
-                    ident.push(*m);
+                    ident.push(m);
                 };
                 tokens.push(match ident.as_str() {
                     "Self" => Token::SelfType,
@@ -226,123 +379,320 @@ fn tokenize(input: &str) -> Result<Vec<Token>, CompileException> {
                     match n {
                         m @ 'a'..='z' | m @ 'A'..='Z' | m @ '0'..='9' | m @ '_' => {
                             iter.next();
-                            ident.push(*m);
+                            ident.push(m);
                         }
                         _ => break
                     };
                 };
                 tokens.push(Token::Annotation(ident));
             }
-            c @ '"' | c @ '\'' => {
-                let mut ident = String::with_capacity(128);
-                while let un = iter.peek() {
-                    if let Some(n) = un {
-                        match n {
-                            c => break,
-                            '\\' => {
-                                match iter.next() {
-                                    Some('n') => ident.push('\n'),
-                                    Some('t') => ident.push('\t'),
-                                    Some('\\') => ident.push('\\'),
-                                    Some(c) => ident.push(c),
-                                    _ => return Err(CompileException::UnrecognizedEscape(line_number, line_index))
-                                }
-                            }
-                            _ => ident.push(*n)
-                        };
-                    } else {
-                        return Err(CompileException::UnfinishedString(line_number, line_index))
-                    }
-                };
-                tokens.push(Token::String(ident));
+            '"' if iter.starts_with("\"\"") => {
+                iter.next();
+                iter.next();
+                tokens.push(read_string(&mut iter, '"', true, line_number, line_index)?);
             }
-            c @ '1'..='9' => {
-                let digits = Vec::with_capacity(16);
-                while let Some(d @ '0'..='9') = iter.peek() {   // Get all main digits
-                    digits.push(iter.next().unwrap())
-                }
-                let mut num = 0i64;
-                let mut position = 1i64;
-                for d in digits.into_iter().rev() { // Look through them backwards and add up as necessary
-                    num += position * d.to_digit(10).unwrap() as i64;
-                    position *= 10;
-                }
-                if let Some('.') = iter.peek() {    // If a . follows the number
-                    iter.next();
-                    let mut num = num as f32;
-                    let mut position = 0.1f32;
-                    while let Some(d @ '0'..='9') = iter.peek() {   // Loop over decimal digits (no reverse necessary)
-                        num += position * iter.next().unwrap().to_digit(10).unwrap() as f32;
-                        position /= 10f32;
-                    }
-                    tokens.push(Token::Num(num));   // Add num token
-                } else {
-                    if let Some('f') = iter.peek() {    // If int ends in f
-                        iter.next();
-                        tokens.push(Token::Num(num as f32));    // Add num token
-                    } else {
-                        tokens.push(Token::Int(num));   // Add int token
-                    }
+            quote @ '"' | quote @ '\'' => tokens.push(read_string(&mut iter, quote, false, line_number, line_index)?),
+            c @ '0'..='9' => tokens.push(read_number(&mut iter, c, line_number, line_index)?),
+            ' ' | '\t' | '\r' | '\n' => {}
+            c => return Err(CompileException::UnrecognizedToken(c.to_string(), line_number, line_index))
+        };
+        if positions.len() < tokens.len() {
+            positions.push((line_number, line_index));
+            spans.push(start..iter.offset);
+        }
+    };
+    Ok(tokens.into_iter().zip(positions).zip(spans).map(|((token, (line, column)), span)| (PositionedToken { token, line, column }, span)).collect())
+}
+
+// Reads a number that starts with the digit first, which was at line:column. Digits can be split up with _, and a number can have
+// a fraction, an exponent and an i or f suffix to make it an Int or a Num. Without one it's an Int, or a Num if it has a fraction or
+// an exponent. 0x and 0b numbers are hexadecimal and binary.
+fn read_number(iter: &mut CharReader<'_>, first: char, line: usize, column: usize) -> Result<Token, CompileException> {
+    let radix = match (first, iter.peek()) {
+        ('0', Some('x')) if iter.peek_at(1).is_some_and(|c| c.is_ascii_hexdigit()) => 16,
+        ('0', Some('b')) if matches!(iter.peek_at(1), Some('0' | '1')) => 2,
+        _ => 10
+    };
+    let mut text = String::new();
+    if radix == 10 {
+        text.push(first);
+    } else {
+        iter.next();
+    }
+    let digits = |iter: &mut CharReader<'_>, text: &mut String| {
+        while let Some(c) = iter.peek().filter(|c| c.is_digit(radix) || *c == '_') {
+            iter.next();
+            if c != '_' {
+                text.push(c);
+            }
+        }
+    };
+    digits(iter, &mut text);
+    let mut whole = true;
+    // A . is only a decimal point if a digit follows, so 0..10 is a range
+    if radix == 10 && iter.peek() == Some('.') && iter.peek_at(1).is_some_and(|c| c.is_ascii_digit()) {
+        text.push(iter.next().unwrap());
+        digits(iter, &mut text);
+        whole = false;
+    }
+    let exponent = match iter.peek_at(1) {
+        Some('+' | '-') => iter.peek_at(2),
+        c => c
+    };
+    if radix == 10 && matches!(iter.peek(), Some('e' | 'E')) && exponent.is_some_and(|c| c.is_ascii_digit()) {
+        text.push(iter.next().unwrap());
+        if let Some(sign @ ('+' | '-')) = iter.peek() {
+            iter.next();
+            text.push(sign);
+        }
+        digits(iter, &mut text);
+        whole = false;
+    }
+    let suffix = iter.peek().filter(|c| matches!(c, 'i' | 'f') && !iter.peek_at(1).is_some_and(|c| c.is_alphanumeric() || c == '_'));
+    if suffix.is_some() {
+        iter.next();
+    }
+    let ty = match suffix {
+        Some('i') => "Int",
+        Some(_) => "Num",
+        None if whole => "Int",
+        None => "Num"
+    };
+    let out_of_range = || CompileException::OutOfRange(text.clone(), ty, line, column);
+    if ty == "Num" {
+        let n = if radix != 10 { i64::from_str_radix(&text, radix).map_err(|_| out_of_range())? as f32 } else { text.parse().map_err(|_| out_of_range())? };
+        return if n.is_finite() { Ok(Token::Num(n)) } else { Err(out_of_range()) };
+    }
+    let n = if whole {
+        i64::from_str_radix(&text, radix).map_err(|_| out_of_range())?
+    } else { // 1e3 is a whole number, so 1e3i is fine
+        let n: f64 = text.parse().map_err(|_| out_of_range())?;
+        if n.fract() != 0.0 || n.abs() >= i64::MAX as f64 {
+            return Err(out_of_range());
+        }
+        n as i64
+    };
+    Ok(Token::Int(n))
+}
+
+// Reads the rest of a string after its opening quote, which was at line:column. Double quoted strings can have {values} in them,
+// and triple quoted ones can go over several lines with the indentation that the lines share taken off.
+fn read_string(iter: &mut CharReader<'_>, quote: char, triple: bool, line: usize, column: usize) -> Result<Token, CompileException> {
+    let mut text = String::with_capacity(128);
+    let mut parts = Vec::new();
+    let indent = if triple { block_indent(iter) } else { 0 };
+    // A line break straight after the opening quotes isn't part of the string
+    let mut line_start = triple && skip_blank_line(iter);
+    loop {
+        if line_start {
+            for _ in 0..indent {
+                if !matches!(iter.peek(), Some(' ') | Some('\t')) {
+                    break;
                 }
+                iter.next();
+            }
+            line_start = false;
+        }
+        let (escape_line, escape_column) = (iter.line, iter.column);
+        match iter.next() {
+            Some(c) if c == quote && !triple => break,
+            Some('"') if triple && iter.starts_with("\"\"") => {
+                iter.next();
+                iter.next();
+                break;
             }
-            '0' => {
+            // {{ and }} are a brace, and {} is left for format
+            Some('{') if quote == '"' => {
                 match iter.peek() {
-                    Some('x') => {
+                    Some('{') => {
                         iter.next();
-                        let digits = Vec::with_capacity(16);
-                        while let Some('0'..='9') | Some('a'..='f') | Some('A'..='F') = iter.peek() {
-                            digits.push(iter.next().unwrap().to_ascii_lowercase())
-                        }
-                        let mut num = 0i64;
-                        let mut position = 1i64;
-                        for d in digits.into_iter().rev() {
-                            num += position * d.to_digit(16).unwrap() as i64;
-                            position *= 16;
-                        }
-                        tokens.push(Token::Int(num));
+                        text.push('{');
                     }
-                    Some('b') => {
+                    Some('}') => {
                         iter.next();
-                        let digits = Vec::with_capacity(16);
-                        while let Some(d @ '0'..='1') = iter.peek() {
-                            digits.push(iter.next().unwrap())
-                        }
-                        let mut num = 0i64;
-                        let mut position = 1i64;
-                        for d in digits.into_iter().rev() {
-                            num += position * d.to_digit(2).unwrap() as i64;
-                            position *= 2;
-                        }
-                        tokens.push(Token::Int(num));
-                    }
-                    Some('.') => {
-                        iter.next();
-                        let mut num = 0f32;
-                        let mut position = 0.1f32;
-                        while let Some(d @ '0'..='9') = iter.peek() {
-                            num += position * iter.next().unwrap().to_digit(10).unwrap() as f32;
-                            position /= 10f32;
-                        }
-                        tokens.push(Token::Num(num));
-                    }
-                    Some('f') => {
-                        iter.next();
-                        tokens.push(Token::Num(0f32));
-                    }
-                    Some('0'..='9') => {
-                        iter.next(); // Ignore something like 05325, this will just turn into 5325
+                        text.push_str("{}");
                     }
                     _ => {
-                        iter.next();
-                        tokens.push(Token::Int(0));
+                        let (line, column) = (iter.line, iter.column);
+                        let code = read_embedded(iter).ok_or(CompileException::UnfinishedString(line, column))?;
+                        parts.push(StringPart::Text(std::mem::take(&mut text)));
+                        parts.push(StringPart::Code(tokenize_at(&code, line, column)?));
                     }
                 }
             }
-            ' ' | '\n' => {
+            Some('}') if quote == '"' && iter.peek() == Some('}') => {
                 iter.next();
+                text.push('}');
             }
-            c => return Err(CompileException::UnrecognizedToken(c.to_string(), line_number, line_index))
+            Some('\\') => read_escape(iter, &mut text, escape_line, escape_column)?,
+            Some('\n') => {
+                text.push('\n');
+                line_start = triple;
+            }
+            Some(c) => text.push(c),
+            None => return Err(CompileException::UnfinishedString(line, column))
         };
+    }
+    // Nor is the line that the closing quotes are on, if there's nothing else on it
+    if let Some(i) = text.rfind('\n').filter(|i| triple && text[i + 1..].trim().is_empty()) {
+        text.truncate(i);
+    }
+    if parts.is_empty() {
+        return Ok(Token::String(text));
+    }
+    parts.push(StringPart::Text(text));
+    parts.retain(|p| *p != StringPart::Text(String::new()));
+    Ok(Token::Interpolated(parts))
+}
+
+// How far in every line of a triple quoted string is indented, ignoring the first line and any that are blank
+fn block_indent(iter: &CharReader<'_>) -> usize {
+    let mut chars = iter.chars.clone();
+    let mut raw = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                raw.push(c);
+                raw.extend(chars.next());
+            }
+            '"' if raw.ends_with("\"\"") => break,
+            c => raw.push(c)
+        }
+    }
+    raw.lines().skip(1).filter(|l| !l.trim().is_empty()).map(|l| l.chars().take_while(|c| *c == ' ' || *c == '\t').count()).min().unwrap_or(0)
+}
+
+// Skips the rest of the line if there's only whitespace left on it, returning whether it did
+fn skip_blank_line(iter: &mut CharReader<'_>) -> bool {
+    let mut chars = iter.chars.clone();
+    loop {
+        match chars.next() {
+            Some('\n') => break,
+            Some(c) if c.is_whitespace() => {}
+            _ => return false
+        }
+    }
+    while iter.next().is_some_and(|c| c != '\n') {}
+    true
+}
+
+// Reads what comes after a \ in a string. The \ was at line:column.
+fn read_escape(iter: &mut CharReader<'_>, text: &mut String, line: usize, column: usize) -> Result<(), CompileException> {
+    let bad = || CompileException::UnrecognizedEscape(line, column);
+    let c = match iter.next().ok_or_else(bad)? {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        c @ ('\\' | '"' | '\'' | '{' | '}') => c,
+        // \x41 is a character by its code, which only goes up to 7F
+        'x' => {
+            let mut code = 0;
+            for _ in 0..2 {
+                code = code * 16 + iter.next().and_then(|d| d.to_digit(16)).ok_or_else(bad)?;
+            }
+            char::from_u32(code).filter(|c| c.is_ascii()).ok_or_else(bad)?
+        }
+        // \u{1F525} is any character by its code, with up to 6 digits
+        'u' => {
+            if iter.next() != Some('{') {
+                return Err(bad());
+            }
+            let (mut code, mut digits) = (0, 0);
+            loop {
+                match iter.next() {
+                    Some('}') if digits > 0 => break,
+                    Some(d) if digits < 6 && d.is_ascii_hexdigit() => {
+                        code = code * 16 + d.to_digit(16).unwrap();
+                        digits += 1;
+                    }
+                    _ => return Err(bad())
+                }
+            }
+            char::from_u32(code).ok_or_else(bad)?
+        }
+        // \&c is the section sign that Minecraft uses for colors and formatting, followed by the code, so \&c is red
+        '&' => match iter.next().map(|c| c.to_ascii_lowercase()) {
+            Some(c @ ('0'..='9' | 'a'..='f' | 'k'..='o' | 'r')) => {
+                text.push('§');
+                c
+            }
+            _ => return Err(bad())
+        },
+        _ => return Err(bad())
     };
-    Ok(tokens)
-}
\ No newline at end of file
+    text.push(c);
+    Ok(())
+}
+
+// r"..." or r#"..."#, where nothing is escaped. Each # lets the string have a " followed by one less #.
+fn read_raw_string(iter: &mut CharReader<'_>, line: usize, column: usize) -> Result<Token, CompileException> {
+    let mut hashes = 0;
+    while iter.peek() == Some('#') {
+        iter.next();
+        hashes += 1;
+    }
+    if iter.next() != Some('"') {
+        return Err(CompileException::UnrecognizedToken(format!("r{}", "#".repeat(hashes)), line, column));
+    }
+    let end = format!("\"{}", "#".repeat(hashes));
+    let mut text = String::new();
+    while !iter.starts_with(&end) {
+        text.push(iter.next().ok_or(CompileException::UnfinishedString(line, column))?);
+    }
+    for _ in 0..end.len() {
+        iter.next();
+    }
+    Ok(Token::String(text))
+}
+
+// Reads the code of a {value} up to the } that closes it, skipping over any braces or strings inside of it
+fn read_embedded(iter: &mut CharReader<'_>) -> Option<String> {
+    let mut code = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    loop {
+        let c = iter.next()?;
+        match (c, quote) {
+            ('\\', Some(_)) => {
+                code.push(c);
+                code.push(iter.next()?);
+                continue;
+            }
+            (c, Some(q)) if c == q => quote = None,
+            ('"' | '\'', None) => quote = Some(c),
+            ('{', None) => depth += 1,
+            ('}', None) if depth == 0 => return Some(code),
+            ('}', None) => depth -= 1,
+            _ => {}
+        }
+        code.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::CompileException;
+    use super::{tokenize, Token};
+
+    fn tokens(input: &str) -> Vec<Token> {
+        tokenize(input).unwrap_or_else(|e| panic!("{}", e)).into_iter().map(|t| t.token).collect()
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(tokens("1_000_000"), [Token::Int(1_000_000)]);
+        assert_eq!(tokens("05325"), [Token::Int(5325)]);
+        assert_eq!(tokens("0x1F 0b101"), [Token::Int(31), Token::Int(5)]);
+        assert_eq!(tokens("1e3 2.5e-2 1e3i"), [Token::Num(1000.0), Token::Num(0.025), Token::Int(1000)]);
+        assert_eq!(tokens("2f 2.5f"), [Token::Num(2.0), Token::Num(2.5)]);
+        assert_eq!(tokens("0..10"), [Token::Int(0), Token::Range, Token::Int(10)]);
+    }
+
+    #[test]
+    fn numbers_out_of_range() {
+        assert!(matches!(tokenize("99999999999999999999"), Err(CompileException::OutOfRange(n, "Int", 1, 1)) if n == "99999999999999999999"));
+        assert!(matches!(tokenize("x = 1.5i"), Err(CompileException::OutOfRange(n, "Int", 1, 5)) if n == "1.5"));
+        assert!(matches!(tokenize("1e39"), Err(CompileException::OutOfRange(_, "Num", 1, 1))));
+    }
+}